@@ -3,6 +3,8 @@
 import React, { useState, useEffect } from "react";
 import { useRouter } from "next/navigation";
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { downloadDir, join } from '@tauri-apps/api/path';
 import { measureLatency } from "@/app/utils/serverLatency"; 
 
 interface Server {
@@ -13,6 +15,15 @@ interface Server {
   latency?: number; 
 }
 
+// A server with a profile, as list_servers reads it
+interface CatalogServer {
+  name: string;
+  remotes: { host: string; port: number; protocol: "udp" | "tcp" }[];
+  location: string | null;
+  country: string | null;
+  flags: string[];
+}
+
 interface User {
   username: string;
   email: string;
@@ -20,6 +31,98 @@ interface User {
   memberSince: string; 
 }
 
+interface ConnectionInfo {
+  state: { state: string; reason?: string };
+  server: string | null;
+  connected_at: number | null;
+  uptime_secs: number | null;
+  tunnel: {
+    local_ip: string | null;
+    local_ipv6: string | null;
+    remote: string | null;
+    gateway: string | null;
+    dns_servers: string[];
+    cipher: string | null;
+  } | null;
+  pid: number | null;
+}
+
+interface TrafficStats {
+  bytes_in: number;
+  bytes_out: number;
+  rate_in: number;
+  rate_out: number;
+  peak_rate_in: number;
+  peak_rate_out: number;
+}
+
+const TRAFFIC_HISTORY = 60;
+
+type Severity = "debug" | "info" | "warning" | "error";
+
+interface LogRecord {
+  seq: number;
+  timestamp: number;
+  source: "open_vpn" | "app";
+  severity: Severity;
+  message: string;
+}
+
+const LOG_VIEW_LINES = 500;
+
+const severityColors: Record<Severity, string> = {
+  debug: "text-gray-400",
+  info: "text-gray-700 dark:text-gray-300",
+  warning: "text-amber-600",
+  error: "text-red-600",
+};
+
+const formatBytes = (bytes: number) => {
+  const units = ["B", "KB", "MB", "GB", "TB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit++;
+  }
+  return `${value.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+};
+
+const formatRate = (bytesPerSecond: number) => `${formatBytes(bytesPerSecond)}/s`;
+
+const sparklinePoints = (values: number[], peak: number) =>
+  values
+    .map((value, i) => `${(i / (TRAFFIC_HISTORY - 1)) * 100},${40 - (peak > 0 ? (value / peak) * 38 : 0)}`)
+    .join(" ");
+
+const formatUptime = (seconds: number) => {
+  const hours = Math.floor(seconds / 3600);
+  const minutes = Math.floor((seconds % 3600) / 60);
+  return `${hours}h ${minutes.toString().padStart(2, "0")}m ${(seconds % 60).toString().padStart(2, "0")}s`;
+};
+
+// Errors returned by Tauri commands
+interface VpnError {
+  code: string;
+  category: string;
+  message: string;
+  detail?: string;
+  diagnosis?: {
+    cause: string;
+    summary: string;
+    suggestion?: string;
+  };
+}
+
+const describeError = (error: unknown) => {
+  if (typeof error === "object" && error !== null && "message" in error) {
+    const vpnError = error as VpnError;
+    const suggestion = vpnError.diagnosis?.suggestion;
+    return suggestion ? `${vpnError.message} ${suggestion}` : vpnError.message;
+  }
+  return String(error);
+};
+
 const Dashboard = () => {
   const [isConnected, setIsConnected] = useState(false);
   const [selectedServer, setSelectedServer] = useState<Server | null>(null);
@@ -32,6 +135,12 @@ const Dashboard = () => {
   const [message, setMessage] = useState("");
   const [user, setUser] = useState<User | null>(null);
   const [isLoading, setIsLoading] = useState(false);
+  const [connectionInfo, setConnectionInfo] = useState<ConnectionInfo | null>(null);
+  const [traffic, setTraffic] = useState<TrafficStats | null>(null);
+  const [trafficHistory, setTrafficHistory] = useState<TrafficStats[]>([]);
+  const [logs, setLogs] = useState<LogRecord[]>([]);
+  const [showLogs, setShowLogs] = useState(false);
+  const [logSeverity, setLogSeverity] = useState<Severity>("info");
   const router = useRouter();
 
   const serversPerPage = 5;
@@ -106,8 +215,15 @@ const fetchServers = async () => {
         const data = await response.json();
         console.log("Servers fetched from API:", data);
 
+        // Only servers with a profile can be connected to
+        const catalog = await invoke<CatalogServer[]>('list_servers');
+        const available = new Set(catalog.map((server) => server.name));
+        const connectable = data.servers.filter((server: Server) =>
+            available.has(server.name.replace(/\s+/g, '-'))
+        );
+
         const serversWithLatency = await Promise.all(
-            data.servers.map(async (server: Server) => {
+            connectable.map(async (server: Server) => {
                 const latency = await measureLatency(server.ip);
                 return { ...server, latency };
             })
@@ -176,7 +292,7 @@ const fetchServers = async () => {
       }
     } catch (error) {
       console.error('VPN connection error:', error);
-      setMessage(`Failed to ${isConnected ? 'disconnect from' : 'connect to'} VPN: ${error}`);
+      setMessage(`Failed to ${isConnected ? 'disconnect from' : 'connect to'} VPN: ${describeError(error)}`);
     } finally {
       setIsLoading(false);
     }
@@ -252,6 +368,84 @@ const fetchServers = async () => {
     checkVpnStatus();
   }, []);
 
+  useEffect(() => {
+    if (!isConnected) {
+      setConnectionInfo(null);
+      return;
+    }
+
+    const refreshConnectionInfo = async () => {
+      try {
+        setConnectionInfo(await invoke<ConnectionInfo>('get_connection_info'));
+      } catch (error) {
+        console.error('Error fetching connection info:', error);
+      }
+    };
+
+    refreshConnectionInfo();
+    const interval = setInterval(refreshConnectionInfo, 5000);
+    return () => clearInterval(interval);
+  }, [isConnected]);
+
+  useEffect(() => {
+    const unlisten = listen<TrafficStats>('traffic', (event) => {
+      setTraffic(event.payload);
+      setTrafficHistory((history) => [...history, event.payload].slice(-TRAFFIC_HISTORY));
+    });
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (isConnected) {
+      invoke<TrafficStats>('get_traffic_stats').then(setTraffic).catch(console.error);
+    } else {
+      setTrafficHistory([]);
+    }
+  }, [isConnected]);
+
+  useEffect(() => {
+    if (!showLogs) return;
+    invoke<LogRecord[]>('get_vpn_logs', { filter: { limit: LOG_VIEW_LINES } })
+      .then(setLogs)
+      .catch(console.error);
+    const unlisten = listen<LogRecord>('vpn-log', (event) => {
+      setLogs((lines) => [...lines, event.payload].slice(-LOG_VIEW_LINES));
+    });
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, [showLogs]);
+
+  const exportLogs = async () => {
+    try {
+      const path = await join(await downloadDir(), `gekkovpn-${Date.now()}.log`);
+      await invoke('export_vpn_logs', { path });
+      setMessage(`Logs saved to ${path}`);
+    } catch (error) {
+      setMessage(describeError(error));
+    }
+  };
+
+  const exportDiagnostics = async () => {
+    try {
+      const path = await join(await downloadDir(), `gekkovpn-diagnostics-${Date.now()}.zip`);
+      await invoke('export_diagnostics', {
+        path,
+        serverName: selectedServer ? selectedServer.name.replace(/\s+/g, '-') : null,
+      });
+      setMessage(`Diagnostics saved to ${path}`);
+    } catch (error) {
+      setMessage(describeError(error));
+    }
+  };
+
+  const severityRank: Severity[] = ["debug", "info", "warning", "error"];
+  const visibleLogs = logs.filter(
+    (line) => severityRank.indexOf(line.severity) >= severityRank.indexOf(logSeverity)
+  );
+
   const paginatedServers = filteredServers.slice(
     currentPage * serversPerPage,
     (currentPage + 1) * serversPerPage
@@ -310,6 +504,64 @@ const fetchServers = async () => {
           </p>
         )}
 
+        {connectionInfo?.tunnel && (
+          <div className="mt-8 p-6 bg-white rounded-lg shadow-lg dark:bg-gray-800">
+            <h2 className="text-xl font-semibold text-gray-800 dark:text-white">
+              Connection
+            </h2>
+            <div className="mt-4 grid grid-cols-1 sm:grid-cols-2 gap-4 text-sm">
+              {[
+                ["Server", connectionInfo.server],
+                ["Uptime", connectionInfo.uptime_secs !== null ? formatUptime(connectionInfo.uptime_secs) : null],
+                ["Connected Since", connectionInfo.connected_at !== null ? new Date(connectionInfo.connected_at * 1000).toLocaleString() : null],
+                ["Tunnel IPv4", connectionInfo.tunnel.local_ip],
+                ["Tunnel IPv6", connectionInfo.tunnel.local_ipv6],
+                ["Remote", connectionInfo.tunnel.remote],
+                ["Gateway", connectionInfo.tunnel.gateway],
+                ["DNS", connectionInfo.tunnel.dns_servers.join(", ")],
+                ["Cipher", connectionInfo.tunnel.cipher],
+                ["OpenVPN PID", connectionInfo.pid?.toString()],
+              ].map(([label, value]) => (
+                <div key={label}>
+                  <p className="font-medium text-gray-800 dark:text-gray-200">
+                    {label}:
+                  </p>
+                  <p className="text-gray-500 dark:text-gray-400">
+                    {value || "N/A"}
+                  </p>
+                </div>
+              ))}
+            </div>
+
+            {traffic && (
+              <div className="mt-6">
+                <div className="flex justify-between text-sm text-gray-800 dark:text-gray-200">
+                  <span className="text-emerald-600">
+                    ↓ {formatRate(traffic.rate_in)} (peak {formatRate(traffic.peak_rate_in)}, total {formatBytes(traffic.bytes_in)})
+                  </span>
+                  <span className="text-sky-600">
+                    ↑ {formatRate(traffic.rate_out)} (peak {formatRate(traffic.peak_rate_out)}, total {formatBytes(traffic.bytes_out)})
+                  </span>
+                </div>
+                <svg viewBox="0 0 100 40" preserveAspectRatio="none" className="mt-2 w-full h-24 bg-gray-50 rounded dark:bg-gray-700">
+                  <polyline
+                    fill="none"
+                    stroke="#10b981"
+                    strokeWidth="0.8"
+                    points={sparklinePoints(trafficHistory.map((t) => t.rate_in), Math.max(...trafficHistory.map((t) => Math.max(t.rate_in, t.rate_out)), 1))}
+                  />
+                  <polyline
+                    fill="none"
+                    stroke="#0ea5e9"
+                    strokeWidth="0.8"
+                    points={sparklinePoints(trafficHistory.map((t) => t.rate_out), Math.max(...trafficHistory.map((t) => Math.max(t.rate_in, t.rate_out)), 1))}
+                  />
+                </svg>
+              </div>
+            )}
+          </div>
+        )}
+
         <div className="mt-8 p-6 bg-white rounded-lg shadow-lg dark:bg-gray-800">
           <h2 className="text-xl font-semibold text-gray-800 dark:text-white">
             Available Servers
@@ -445,6 +697,63 @@ const fetchServers = async () => {
           </div>
         </div>
 
+        <div className="mt-8 p-6 bg-white rounded-lg shadow-lg dark:bg-gray-800">
+          <div className="flex justify-between items-center">
+            <h2 className="text-xl font-semibold text-gray-800 dark:text-white">
+              Logs
+            </h2>
+            <div className="flex space-x-2">
+              {showLogs && (
+                <>
+                  <select
+                    value={logSeverity}
+                    className="px-2 py-1 border rounded-lg text-sm text-gray-800 dark:text-gray-200 bg-gray-50 dark:bg-gray-700"
+                    onChange={(e) => setLogSeverity(e.target.value as Severity)}
+                  >
+                    {severityRank.map((severity) => (
+                      <option key={severity} value={severity}>
+                        {severity}
+                      </option>
+                    ))}
+                  </select>
+                  <button
+                    onClick={exportLogs}
+                    className="px-3 py-1 text-sm rounded-lg bg-gray-200 text-gray-800 hover:bg-gray-300 dark:bg-gray-700 dark:text-gray-200"
+                  >
+                    Export
+                  </button>
+                </>
+              )}
+              <button
+                onClick={exportDiagnostics}
+                className="px-3 py-1 text-sm rounded-lg bg-gray-200 text-gray-800 hover:bg-gray-300 dark:bg-gray-700 dark:text-gray-200"
+              >
+                Diagnostics
+              </button>
+              <button
+                onClick={() => setShowLogs((show) => !show)}
+                className="px-3 py-1 text-sm rounded-lg bg-emerald-500 text-white hover:bg-emerald-600"
+              >
+                {showLogs ? "Hide" : "Show"}
+              </button>
+            </div>
+          </div>
+
+          {showLogs && (
+            <div className="mt-4 h-64 overflow-y-auto p-2 bg-gray-50 rounded font-mono text-xs dark:bg-gray-900">
+              {visibleLogs.map((line) => (
+                <div key={line.seq} className={severityColors[line.severity]}>
+                  <span className="text-gray-400">
+                    {new Date(line.timestamp).toLocaleTimeString()}{" "}
+                  </span>
+                  {line.source === "app" ? "[app] " : ""}
+                  {line.message}
+                </div>
+              ))}
+            </div>
+          )}
+        </div>
+
         {message && (
           <div className="mt-8 p-4 text-center text-sm text-gray-800 bg-emerald-100 rounded-lg dark:bg-gray-700 dark:text-gray-300">
             {message}
@@ -0,0 +1,124 @@
+use gekkovpn_core::error::ErrorCategory;
+use gekkovpn_core::VpnError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Exit codes, which scripts may rely on once released. Invalid arguments exit
+// with 2, as clap reports them.
+pub const SUCCESS: u8 = 0;
+/// Anything not covered below
+pub const FAILURE: u8 = 1;
+/// No tunnel is up or being set up
+pub const NOT_CONNECTED: u8 = 3;
+pub const ALREADY_CONNECTED: u8 = 4;
+/// No saved password, an unusable keyring, or credentials the server rejected
+pub const CREDENTIALS: u8 = 5;
+/// openvpn, the profile or the username is missing
+pub const CONFIGURATION: u8 = 6;
+/// The tunnel did not come up or went down for good
+pub const CONNECTION: u8 = 7;
+/// The network adapter or the rights to create it are missing
+pub const ADAPTER: u8 = 8;
+/// The connect was cancelled with Ctrl-C or `gekkovpn disconnect`
+pub const CANCELLED: u8 = 130;
+
+/// An error raised here or reported by whatever runs the tunnel, in the shape
+/// `VpnError` serializes to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub code: String,
+    pub category: ErrorCategory,
+    pub message: String,
+    pub detail: Option<String>,
+}
+
+impl Failure {
+    /// Relies on error codes not changing once released
+    pub fn exit_code(&self) -> u8 {
+        match self.code.as_str() {
+            "not_connected" => NOT_CONNECTED,
+            "already_connected" => ALREADY_CONNECTED,
+            "cancelled" => CANCELLED,
+            _ => match self.category {
+                ErrorCategory::Credentials => CREDENTIALS,
+                ErrorCategory::Configuration => CONFIGURATION,
+                ErrorCategory::Connection => CONNECTION,
+                ErrorCategory::Adapter => ADAPTER,
+                ErrorCategory::Process | ErrorCategory::Internal => FAILURE,
+            },
+        }
+    }
+}
+
+impl From<VpnError> for Failure {
+    fn from(error: VpnError) -> Self {
+        Failure {
+            code: error.code().to_string(),
+            category: error.category(),
+            message: error.message(),
+            detail: error.detail(),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gekkovpn_core::diagnosis;
+    use std::path::PathBuf;
+
+    #[test]
+    fn exit_codes_follow_the_error() {
+        let auth_failed = diagnosis::diagnose(
+            "The server rejected the credentials",
+            Some(diagnosis::FailureCause::AuthenticationFailed),
+            &[],
+        );
+        let cases = [
+            (VpnError::NotConnected, NOT_CONNECTED),
+            (VpnError::AlreadyConnected, ALREADY_CONNECTED),
+            (VpnError::Cancelled, CANCELLED),
+            (VpnError::ConnectionFailed(auth_failed), CREDENTIALS),
+            (
+                VpnError::NoSavedPassword {
+                    username: "alice".to_string(),
+                },
+                CREDENTIALS,
+            ),
+            (
+                VpnError::ConfigNotFound(PathBuf::from("nl1")),
+                CONFIGURATION,
+            ),
+            (VpnError::InvalidState("busy".to_string()), CONNECTION),
+            (VpnError::AdminRequired, ADAPTER),
+            (VpnError::Storage("disk full".to_string()), FAILURE),
+        ];
+        for (error, code) in cases {
+            assert_eq!(
+                Failure::from(error.clone()).exit_code(),
+                code,
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn reads_errors_as_the_core_serializes_them() {
+        let json = serde_json::to_string(&VpnError::NotConnected).unwrap();
+        let failure: Failure = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(failure.exit_code(), NOT_CONNECTED);
+        assert_eq!(failure.to_string(), "Not connected to VPN");
+    }
+}
@@ -0,0 +1,447 @@
+//! `gekkovpn`, the GekkoVPN client for servers and scripts. When `gekkovpnd` runs,
+//! the tunnel is its; otherwise `connect` runs it in the foreground. The other
+//! commands talk to whichever owns it or read the same profiles and keyring as
+//! the app. The exit codes are listed in `error.rs`.
+
+mod error;
+mod session;
+mod settings;
+mod tui;
+
+use crate::error::Failure;
+use crate::settings::Settings;
+use clap::{Parser, Subcommand};
+use gekkovpn_core::catalog::{self, Server};
+use gekkovpn_core::credentials::{CredentialStore, Keyring};
+use gekkovpn_core::ipc::Connection;
+use gekkovpn_core::logbuffer::{LogFilter, LogRecord, LOG_EVENT};
+use gekkovpn_core::paths::{PathResolver, Platform, SystemEnvironment};
+use gekkovpn_core::states::{ConnectionState, STATE_EVENT};
+use gekkovpn_core::traffic::TrafficStats;
+use gekkovpn_core::{
+    Backend, Broadcast, ConnectionInfo, Event, Events, Service, VpnClient, VpnError,
+};
+use serde::Serialize;
+use std::io::{BufRead, IsTerminal};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+#[derive(Parser)]
+#[command(
+    name = "gekkovpn",
+    version,
+    about = "GekkoVPN client for servers and scripts"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Connect to a server. Without gekkovpnd, keeps the tunnel up in the
+    /// foreground until disconnected or interrupted.
+    Connect {
+        server: String,
+        /// Defaults to the username of the last `login`
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// Disconnect the tunnel
+    Disconnect,
+    /// Show the connection; exits with 0 only while connected
+    Status {
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the servers that have a profile, with where they are and how to reach them
+    Servers {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the openvpn and client log of the tunnel
+    Logs {
+        /// Keep printing new lines until the tunnel is closed
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Save the password for a username in the system keyring, read from
+    /// stdin when it is not a terminal
+    Login { username: String },
+    /// Pick servers, connect and watch the tunnel in a terminal UI
+    Tui {
+        /// Defaults to the username of the last `login`
+        #[arg(long)]
+        user: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Connect { server, user } => connect(server, user).await,
+        Command::Disconnect => disconnect().await,
+        Command::Status { json } => status(json).await,
+        Command::Servers { json } => servers(json).await,
+        Command::Logs { follow } => logs(follow).await,
+        Command::Login { username } => login(username),
+        Command::Tui { user } => tui::run(user).await,
+    };
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(failure) => {
+            eprintln!("Error: {}", failure);
+            ExitCode::from(failure.exit_code())
+        }
+    }
+}
+
+/// `user`, or the username of the last `login`
+fn username(user: Option<String>) -> Result<String, Failure> {
+    match user {
+        Some(user) => Ok(user),
+        None => Ok(Settings::load()?.username.ok_or_else(|| {
+            VpnError::InvalidSetting(
+                "No username given. Pass --user or run `gekkovpn login` first.".to_string(),
+            )
+        })?),
+    }
+}
+
+async fn connect(server: String, user: Option<String>) -> Result<u8, Failure> {
+    let username = username(user)?;
+    match tunnel().await? {
+        Some(Tunnel::Daemon(connection)) => {
+            tokio::spawn(print_states(connection.subscribe().await?));
+            let service = Service::remote(connection);
+            // Ctrl-C cancels the connect; the tunnel stays up once established
+            let interrupted = service.clone();
+            tokio::spawn(async move {
+                while tokio::signal::ctrl_c().await.is_ok() {
+                    let _ = interrupted.cancel_connect().await;
+                }
+            });
+            let message = service.connect(server, username).await?;
+            eprintln!("{}", message);
+            return Ok(error::SUCCESS);
+        }
+        Some(Tunnel::Session(_)) => return Err(VpnError::AlreadyConnected.into()),
+        None => {}
+    }
+
+    let broadcast = Broadcast::default();
+    let client = VpnClient::start(Backend::system(Events::new(Arc::new(broadcast.clone()))));
+    let _published = session::publish(client.clone(), broadcast.clone()).await?;
+    tokio::spawn(print_states(broadcast.subscribe()));
+
+    // Ctrl-C cancels a connect in progress and closes an established tunnel
+    let interrupted = client.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            let _ = interrupted.disconnect().await;
+        }
+    });
+
+    let mut updates = broadcast.subscribe();
+    client.connect(server, username).await?;
+    match until_closed(&client, &mut updates).await {
+        ConnectionState::Failed(_) => Err(client
+            .last_failure()
+            .map(VpnError::from)
+            .unwrap_or(VpnError::NotConnected)
+            .into()),
+        _ => Ok(error::SUCCESS),
+    }
+}
+
+/// Whatever owns the tunnel
+enum Tunnel {
+    Daemon(Connection),
+    /// A `gekkovpn connect` running in the foreground
+    Session(Connection),
+}
+
+impl Tunnel {
+    fn connection(self) -> Connection {
+        match self {
+            Tunnel::Daemon(connection) | Tunnel::Session(connection) => connection,
+        }
+    }
+}
+
+/// The daemon, else the running `connect`, or `None` when there is neither
+async fn tunnel() -> Result<Option<Tunnel>, Failure> {
+    #[cfg(unix)]
+    if let Some(connection) = Connection::daemon().await? {
+        return Ok(Some(Tunnel::Daemon(connection)));
+    }
+    let Some(endpoint) = session::find()? else {
+        return Ok(None);
+    };
+    Ok(session::open(&endpoint).await?.map(Tunnel::Session))
+}
+
+/// The connection to whatever owns the tunnel, failing with `NotConnected` when
+/// nothing does
+async fn connection() -> Result<Connection, Failure> {
+    Ok(tunnel().await?.ok_or(VpnError::NotConnected)?.connection())
+}
+
+/// Waits until the tunnel is closed for good, and returns how it ended
+async fn until_closed(client: &VpnClient, updates: &mut Receiver<Event>) -> ConnectionState {
+    loop {
+        match updates.recv().await {
+            Ok(event) if event.name != STATE_EVENT => continue,
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return client.state(),
+        }
+        let state = client.state();
+        if matches!(
+            state,
+            ConnectionState::Disconnected | ConnectionState::Failed(_)
+        ) {
+            return state;
+        }
+    }
+}
+
+async fn print_states(mut updates: Receiver<Event>) {
+    loop {
+        match updates.recv().await {
+            Ok(event) if event.name == STATE_EVENT => {
+                if let Ok(state) = serde_json::from_value::<ConnectionState>(event.payload) {
+                    eprintln!("{}", describe(&state));
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+fn describe(state: &ConnectionState) -> String {
+    match state {
+        ConnectionState::Disconnected => "Disconnected".to_string(),
+        ConnectionState::Resolving => "Resolving the server".to_string(),
+        ConnectionState::Connecting => "Connecting".to_string(),
+        ConnectionState::Authenticating => "Authenticating".to_string(),
+        ConnectionState::Connected => "Connected".to_string(),
+        ConnectionState::Reconnecting => "Reconnecting".to_string(),
+        ConnectionState::Disconnecting => "Disconnecting".to_string(),
+        ConnectionState::Failed(reason) => format!("Failed: {}", reason),
+    }
+}
+
+async fn disconnect() -> Result<u8, Failure> {
+    let message = Service::remote(connection().await?).disconnect().await?;
+    eprintln!("{}", message);
+    Ok(error::SUCCESS)
+}
+
+#[derive(Serialize)]
+struct Status {
+    #[serde(flatten)]
+    connection: ConnectionInfo,
+    traffic: TrafficStats,
+}
+
+async fn status(json: bool) -> Result<u8, Failure> {
+    let status = match tunnel().await? {
+        Some(tunnel) => {
+            let service = Service::remote(tunnel.connection());
+            Status {
+                connection: service.status().await?,
+                traffic: service.traffic_stats().await?,
+            }
+        }
+        None => Status {
+            connection: disconnected(),
+            traffic: Default::default(),
+        },
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&status).unwrap_or_default()
+        );
+    } else {
+        print_status(&status);
+    }
+    Ok(match status.connection.state {
+        ConnectionState::Connected => error::SUCCESS,
+        _ => error::NOT_CONNECTED,
+    })
+}
+
+/// What status reports when nothing owns the tunnel
+fn disconnected() -> ConnectionInfo {
+    ConnectionInfo {
+        state: ConnectionState::Disconnected,
+        server: None,
+        connected_at: None,
+        uptime_secs: None,
+        tunnel: None,
+        pid: None,
+    }
+}
+
+fn print_status(status: &Status) {
+    let connection = &status.connection;
+    println!("State:    {}", describe(&connection.state));
+    if let Some(server) = &connection.server {
+        println!("Server:   {}", server);
+    }
+    if let Some(uptime) = connection.uptime_secs {
+        println!(
+            "Uptime:   {}h {:02}m {:02}s",
+            uptime / 3_600,
+            uptime / 60 % 60,
+            uptime % 60
+        );
+    }
+    if let Some(local_ip) = connection.tunnel.as_ref().and_then(|t| t.local_ip.as_ref()) {
+        println!("Address:  {}", local_ip);
+    }
+    if connection.connected_at.is_some() {
+        println!(
+            "Traffic:  {} received, {} sent",
+            bytes(status.traffic.bytes_in),
+            bytes(status.traffic.bytes_out)
+        );
+    }
+}
+
+fn bytes(count: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = count as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", count)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+async fn servers(json: bool) -> Result<u8, Failure> {
+    let servers = list_servers().await?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&servers).unwrap_or_default()
+        );
+        return Ok(error::SUCCESS);
+    }
+
+    let width = servers.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for server in servers {
+        let place = [server.location.as_deref(), server.country.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(", ");
+        let remote = server
+            .remotes
+            .first()
+            .map(|r| format!("{} {}:{}", r.protocol, r.host, r.port))
+            .unwrap_or_default();
+        let flags = if server.flags.is_empty() {
+            String::new()
+        } else {
+            format!("  [{}]", server.flags.join(", "))
+        };
+        println!(
+            "{:width$}  {:24}  {}{}",
+            server.name,
+            place,
+            remote,
+            flags,
+            width = width
+        );
+    }
+    Ok(error::SUCCESS)
+}
+
+/// The servers whatever runs the tunnel can connect to. The daemon reads its own
+/// profiles; a tunnel run by this user reads the same ones as this process.
+async fn list_servers() -> Result<Vec<Server>, Failure> {
+    #[cfg(unix)]
+    if let Some(connection) = Connection::daemon().await? {
+        return Ok(Service::remote(connection).list_servers().await?);
+    }
+    let paths = PathResolver::new(SystemEnvironment, Platform::current()).resolve()?;
+    Ok(catalog::list(&paths.config_dir)?)
+}
+
+async fn logs(follow: bool) -> Result<u8, Failure> {
+    let connection = connection().await?;
+    // Subscribed first, so no line falls between the backlog and the updates
+    let mut updates = if follow {
+        Some(connection.subscribe().await?)
+    } else {
+        None
+    };
+    let service = Service::remote(connection);
+    let mut last_seq = 0;
+    for record in service.logs(LogFilter::default()).await? {
+        last_seq = record.seq;
+        println!("{}", record);
+    }
+    let Some(updates) = &mut updates else {
+        return Ok(error::SUCCESS);
+    };
+
+    loop {
+        let records: Vec<LogRecord> = match updates.recv().await {
+            Ok(event) if event.name == LOG_EVENT => {
+                serde_json::from_value(event.payload).into_iter().collect()
+            }
+            Ok(_) => continue,
+            // Fell behind, the buffer still has what was missed
+            Err(RecvError::Lagged(_)) => {
+                service
+                    .logs(LogFilter {
+                        after_seq: Some(last_seq),
+                        ..LogFilter::default()
+                    })
+                    .await?
+            }
+            Err(RecvError::Closed) => return Ok(error::SUCCESS),
+        };
+        for record in records {
+            if record.seq > last_seq {
+                last_seq = record.seq;
+                println!("{}", record);
+            }
+        }
+    }
+}
+
+fn login(username: String) -> Result<u8, Failure> {
+    let password = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("Password for {}: ", username))
+    } else {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line).map(|_| line)
+    }
+    .map_err(|e| VpnError::Keyring(format!("Failed to read the password: {}", e)))?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(VpnError::InvalidSetting("The password is empty".to_string()).into());
+    }
+
+    Keyring.set_password(&username, password)?;
+    Settings {
+        username: Some(username.clone()),
+    }
+    .save()?;
+    eprintln!("Saved the password for {}", username);
+    Ok(error::SUCCESS)
+}
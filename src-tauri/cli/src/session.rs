@@ -0,0 +1,299 @@
+//! Without the daemon, a running `gekkovpn connect` owns the tunnel. It serves the
+//! daemon's protocol where only its user can reach it, a Unix socket in a private
+//! directory or a named pipe on Windows, and publishes the address in a file only
+//! its user can read, so later invocations can ask for its status and logs or
+//! tell it to disconnect.
+
+use crate::error::Failure;
+use crate::settings;
+use gekkovpn_core::ipc::{self, Connection};
+use gekkovpn_core::{Broadcast, VpnClient, VpnError};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use {gekkovpn_core::ipc::Access, tokio::net::UnixListener};
+
+#[cfg(windows)]
+use {
+    gekkovpn_core::ipc::Access,
+    tokio::net::windows::named_pipe::{ClientOptions, NamedPipeServer, ServerOptions},
+};
+
+const SESSION_FILE: &str = "cli-session.json";
+
+/// Where the running session listens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Endpoint {
+    /// The socket on Unix, the name of the pipe on Windows
+    pub address: PathBuf,
+}
+
+fn session_path() -> Result<PathBuf, Failure> {
+    Ok(settings::data_dir()?.join(SESSION_FILE))
+}
+
+fn random_name() -> Result<String, Failure> {
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| VpnError::Process(format!("Failed to name the session: {}", e)))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Removes the session file, and the socket's directory, when the session ends
+pub struct Published {
+    file: PathBuf,
+    #[cfg(unix)]
+    dir: PathBuf,
+}
+
+impl Drop for Published {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.file);
+        #[cfg(unix)]
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Starts answering requests for `client` and publishes where
+pub async fn publish(client: VpnClient, broadcast: Broadcast) -> Result<Published, Failure> {
+    let listening =
+        |e: io::Error| VpnError::Process(format!("Failed to listen for commands: {}", e));
+    let name = random_name()?;
+
+    // A directory of our own, so no one else can reach the socket or put theirs there
+    #[cfg(unix)]
+    let (listener, address, dir) = {
+        use std::os::unix::fs::DirBuilderExt;
+
+        let dir = settings::data_dir()?.join(format!("session-{}", name));
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .map_err(|e| VpnError::Storage(format!("Failed to create {:?}: {}", dir, e)))?;
+        let address = dir.join("cli.sock");
+        (
+            UnixListener::bind(&address).map_err(listening)?,
+            address,
+            dir,
+        )
+    };
+    #[cfg(windows)]
+    let (listener, address) = {
+        let address = PathBuf::from(format!(r"\\.\pipe\gekkovpn-session-{}", name));
+        (Listener::bind(&address).map_err(listening)?, address)
+    };
+
+    let file = session_path()?;
+    let json = serde_json::to_vec(&Endpoint { address })
+        .map_err(|e| VpnError::Storage(format!("Failed to serialize the session: {}", e)))?;
+    write_private(&file, &json)
+        .map_err(|e| VpnError::Storage(format!("Failed to write {:?}: {}", file, e)))?;
+
+    tokio::spawn(serve(listener, client, broadcast));
+    Ok(Published {
+        file,
+        #[cfg(unix)]
+        dir,
+    })
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)
+}
+
+/// The app data directory is private to the user on Windows
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+/// Only the user who started the session, and root, may use it
+#[cfg(unix)]
+fn authorize(user: u32, peer: io::Result<u32>) -> Result<Access, VpnError> {
+    match peer {
+        Ok(uid) if uid == user || uid == 0 => Ok(Access::Full),
+        Ok(uid) => Err(VpnError::PermissionDenied(format!(
+            "uid {} did not start this session",
+            uid
+        ))),
+        Err(e) => Err(VpnError::PermissionDenied(format!(
+            "Failed to identify the client: {}",
+            e
+        ))),
+    }
+}
+
+/// Answers requests until the process exits
+#[cfg(unix)]
+pub async fn serve(listener: UnixListener, client: VpnClient, broadcast: Broadcast) {
+    // SAFETY: geteuid cannot fail and touches no memory
+    let user = unsafe { libc::geteuid() };
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let peer = stream.peer_cred().map(|cred| cred.uid());
+        tokio::spawn(ipc::serve(
+            stream,
+            client.clone(),
+            broadcast.clone(),
+            move |_| authorize(user, peer),
+        ));
+    }
+}
+
+/// A named pipe with the default security, which lets only its owner,
+/// administrators and SYSTEM write to it
+#[cfg(windows)]
+pub struct Listener {
+    server: NamedPipeServer,
+    address: PathBuf,
+}
+
+#[cfg(windows)]
+impl Listener {
+    fn bind(address: &Path) -> io::Result<Listener> {
+        let server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .reject_remote_clients(true)
+            .create(address)?;
+        Ok(Listener {
+            server,
+            address: address.to_path_buf(),
+        })
+    }
+}
+
+/// Answers requests until the process exits
+#[cfg(windows)]
+pub async fn serve(mut listener: Listener, client: VpnClient, broadcast: Broadcast) {
+    loop {
+        let connected = listener.server.connect().await;
+        // The next client needs an instance of its own
+        let Ok(next) = ServerOptions::new()
+            .reject_remote_clients(true)
+            .create(&listener.address)
+        else {
+            return;
+        };
+        let stream = std::mem::replace(&mut listener.server, next);
+        if connected.is_ok() {
+            tokio::spawn(ipc::serve(
+                stream,
+                client.clone(),
+                broadcast.clone(),
+                |_| Ok(Access::Full),
+            ));
+        }
+    }
+}
+
+/// The running session, or `None` when no `gekkovpn connect` is running
+pub fn find() -> Result<Option<Endpoint>, Failure> {
+    let path = session_path()?;
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(VpnError::Storage(format!("Failed to read {:?}: {}", path, e)).into())
+        }
+    };
+    // A file that cannot be parsed was left behind by a session that crashed
+    Ok(serde_json::from_slice(&bytes).ok())
+}
+
+/// Connects to the session at `endpoint`. `None` when nothing listens there
+/// anymore, because the session crashed.
+pub async fn open(endpoint: &Endpoint) -> Result<Option<Connection>, Failure> {
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(&endpoint.address).await;
+    #[cfg(windows)]
+    let stream = ClientOptions::new().open(&endpoint.address);
+    let Ok(stream) = stream else {
+        return Ok(None);
+    };
+    Ok(Some(Connection::open(stream).await?))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use gekkovpn_core::connection::Connector;
+    use gekkovpn_core::paths::{PathResolver, Platform, SystemEnvironment};
+    use gekkovpn_core::states::ConnectionState;
+    use gekkovpn_core::usage::UsageLedger;
+    use gekkovpn_core::{Backend, Events, Service};
+    use std::sync::Arc;
+
+    fn address(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "gekkovpn-session-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    async fn session(name: &str) -> (Endpoint, VpnClient) {
+        let broadcast = Broadcast::default();
+        let client = VpnClient::start(Backend {
+            events: Events::new(Arc::new(broadcast.clone())),
+            paths: PathResolver::new(SystemEnvironment, Platform::current()),
+            usage: UsageLedger::open(None),
+            connector: Connector::system(),
+        });
+        let endpoint = Endpoint {
+            address: address(name),
+        };
+        let listener = UnixListener::bind(&endpoint.address).unwrap();
+        tokio::spawn(serve(listener, client.clone(), broadcast));
+        (endpoint, client)
+    }
+
+    #[tokio::test]
+    async fn reports_the_status() {
+        let (endpoint, _client) = session("status").await;
+        let service = Service::remote(open(&endpoint).await.unwrap().unwrap());
+
+        let status = service.status().await.unwrap();
+        assert_eq!(status.state, ConnectionState::Disconnected);
+        assert_eq!(service.traffic_stats().await.unwrap(), Default::default());
+    }
+
+    #[test]
+    fn refuses_other_users() {
+        assert_eq!(authorize(1000, Ok(1000)).unwrap(), Access::Full);
+        assert_eq!(authorize(1000, Ok(0)).unwrap(), Access::Full);
+        assert!(matches!(
+            authorize(1000, Ok(1001)),
+            Err(VpnError::PermissionDenied(_))
+        ));
+        assert!(matches!(
+            authorize(1000, Err(io::Error::other("gone"))),
+            Err(VpnError::PermissionDenied(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn finds_nothing_where_no_session_listens() {
+        let endpoint = Endpoint {
+            address: address("gone"),
+        };
+        drop(UnixListener::bind(&endpoint.address).unwrap());
+
+        assert!(open(&endpoint).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,46 @@
+use crate::error::Failure;
+use gekkovpn_core::paths::{Environment, SystemEnvironment};
+use gekkovpn_core::VpnError;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const SETTINGS_FILE: &str = "cli.json";
+
+/// The app data directory the GUI uses too, created if missing
+pub fn data_dir() -> Result<PathBuf, Failure> {
+    let dir = SystemEnvironment
+        .app_data_dir()
+        .ok_or_else(|| VpnError::AppPaths("No app data directory".to_string()))?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| VpnError::Storage(format!("Failed to create {:?}: {}", dir, e)))?;
+    Ok(dir)
+}
+
+/// What `gekkovpn login` remembers for later commands
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// Used when `connect` is not given `--user`
+    pub username: Option<String>,
+}
+
+impl Settings {
+    /// The saved settings, or the defaults when there are none
+    pub fn load() -> Result<Self, Failure> {
+        let path = data_dir()?.join(SETTINGS_FILE);
+        match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                VpnError::Storage(format!("Failed to parse {:?}: {}", path, e)).into()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(e) => Err(VpnError::Storage(format!("Failed to read {:?}: {}", path, e)).into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Failure> {
+        let path = data_dir()?.join(SETTINGS_FILE);
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| VpnError::Storage(format!("Failed to serialize settings: {}", e)))?;
+        std::fs::write(&path, json)
+            .map_err(|e| VpnError::Storage(format!("Failed to write {:?}: {}", path, e)).into())
+    }
+}
@@ -0,0 +1,322 @@
+use crate::error::Failure;
+use gekkovpn_core::catalog::Server;
+use gekkovpn_core::logbuffer::{LogRecord, LOG_EVENT};
+use gekkovpn_core::states::{ConnectionState, STATE_EVENT};
+use gekkovpn_core::traffic::TrafficStats;
+use gekkovpn_core::{ConnectionInfo, Event, VpnError};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Rates kept for the traffic graphs, one per tick
+pub const HISTORY: usize = 240;
+/// Log lines kept for the log pane
+pub const LOG_LINES: usize = 2_000;
+/// Lines Page Up and Page Down scroll the log pane by
+const PAGE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    Measuring,
+    Measured(Duration),
+    /// No answer within the timeout, or the server has no remote
+    Unreachable,
+}
+
+pub struct Entry {
+    pub server: Server,
+    pub latency: Latency,
+}
+
+/// What a key asks the tunnel to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Connect(String),
+    SwitchServer(String),
+    Disconnect,
+    CancelConnect,
+    MeasureLatency,
+    Quit,
+}
+
+pub struct App {
+    pub username: String,
+    pub servers: Vec<Entry>,
+    pub selected: usize,
+    pub connection: ConnectionInfo,
+    pub traffic: TrafficStats,
+    pub rates_in: VecDeque<u64>,
+    pub rates_out: VecDeque<u64>,
+    pub logs: VecDeque<LogRecord>,
+    /// Lines the log pane is scrolled up from the newest; 0 follows new lines
+    pub scroll: usize,
+    /// The outcome of the last action
+    pub message: Option<String>,
+}
+
+impl App {
+    pub fn new(
+        username: String,
+        servers: Vec<Server>,
+        connection: ConnectionInfo,
+        logs: Vec<LogRecord>,
+    ) -> Self {
+        let mut app = App {
+            username,
+            servers: servers
+                .into_iter()
+                .map(|server| Entry {
+                    server,
+                    latency: Latency::Measuring,
+                })
+                .collect(),
+            selected: 0,
+            connection,
+            traffic: TrafficStats::default(),
+            rates_in: VecDeque::with_capacity(HISTORY),
+            rates_out: VecDeque::with_capacity(HISTORY),
+            logs: VecDeque::with_capacity(LOG_LINES),
+            scroll: 0,
+            message: None,
+        };
+        // Start on the server the tunnel runs to
+        if let Some(server) = &app.connection.server {
+            app.selected = app
+                .servers
+                .iter()
+                .position(|entry| entry.server.name == *server)
+                .unwrap_or(0);
+        }
+        for record in logs {
+            app.log(record);
+        }
+        app
+    }
+
+    /// Whether a tunnel is up or being set up, so connecting means switching
+    fn busy(&self) -> bool {
+        !matches!(
+            self.connection.state,
+            ConnectionState::Disconnected | ConnectionState::Failed(_)
+        )
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Action::Quit)
+            }
+            KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.servers.len().saturating_sub(1));
+                None
+            }
+            KeyCode::Enter | KeyCode::Char('c') => {
+                let name = self.servers.get(self.selected)?.server.name.clone();
+                Some(if self.busy() {
+                    Action::SwitchServer(name)
+                } else {
+                    Action::Connect(name)
+                })
+            }
+            KeyCode::Char('d') => Some(Action::Disconnect),
+            KeyCode::Char('x') => Some(Action::CancelConnect),
+            KeyCode::Char('r') => {
+                for entry in &mut self.servers {
+                    entry.latency = Latency::Measuring;
+                }
+                Some(Action::MeasureLatency)
+            }
+            KeyCode::PageUp => {
+                self.scroll = (self.scroll + PAGE).min(self.logs.len().saturating_sub(1));
+                None
+            }
+            KeyCode::PageDown => {
+                self.scroll = self.scroll.saturating_sub(PAGE);
+                None
+            }
+            KeyCode::Home => {
+                self.scroll = self.logs.len().saturating_sub(1);
+                None
+            }
+            KeyCode::End => {
+                self.scroll = 0;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// An event of the tunnel, of which states and log lines are shown as they come
+    pub fn event(&mut self, event: Event) {
+        if event.name == STATE_EVENT {
+            if let Ok(state) = serde_json::from_value(event.payload) {
+                self.connection.state = state;
+            }
+        } else if event.name == LOG_EVENT {
+            if let Ok(record) = serde_json::from_value(event.payload) {
+                self.log(record);
+            }
+        }
+    }
+
+    fn log(&mut self, record: LogRecord) {
+        if self.logs.back().is_some_and(|last| last.seq >= record.seq) {
+            return;
+        }
+        if self.logs.len() == LOG_LINES {
+            self.logs.pop_front();
+        }
+        self.logs.push_back(record);
+        // A scrolled pane keeps showing the same lines
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.logs.len().saturating_sub(1));
+        }
+    }
+
+    /// The connection and traffic as polled once per tick
+    pub fn tick(&mut self, connection: ConnectionInfo, traffic: TrafficStats) {
+        for (rates, rate) in [
+            (&mut self.rates_in, traffic.rate_in),
+            (&mut self.rates_out, traffic.rate_out),
+        ] {
+            if rates.len() == HISTORY {
+                rates.pop_front();
+            }
+            rates.push_back(rate.max(0.0) as u64);
+        }
+        self.connection = connection;
+        self.traffic = traffic;
+    }
+
+    pub fn latency(&mut self, name: &str, latency: Option<Duration>) {
+        if let Some(entry) = self.servers.iter_mut().find(|e| e.server.name == name) {
+            entry.latency = latency.map_or(Latency::Unreachable, Latency::Measured);
+        }
+    }
+
+    pub fn finished(&mut self, result: Result<String, VpnError>) {
+        self.message = Some(match result {
+            Ok(message) => message,
+            Err(e) => format!("Error: {}", Failure::from(e)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gekkovpn_core::logbuffer::{LogSource, Severity};
+
+    fn app(servers: &[&str]) -> App {
+        let servers = servers
+            .iter()
+            .map(|name| Server {
+                name: name.to_string(),
+                remotes: Vec::new(),
+                location: None,
+                country: None,
+                flags: Vec::new(),
+            })
+            .collect();
+        App::new(
+            "alice".to_string(),
+            servers,
+            crate::disconnected(),
+            Vec::new(),
+        )
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Option<Action> {
+        app.key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn record(seq: u64) -> LogRecord {
+        LogRecord {
+            seq,
+            timestamp: 0,
+            source: LogSource::OpenVpn,
+            severity: Severity::Info,
+            message: format!("line {}", seq),
+        }
+    }
+
+    #[test]
+    fn connects_to_the_selected_server_or_switches_to_it() {
+        let mut app = app(&["de1", "nl1"]);
+        assert_eq!(press(&mut app, KeyCode::Up), None);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(
+            press(&mut app, KeyCode::Enter),
+            Some(Action::Connect("nl1".to_string()))
+        );
+
+        app.event(Event {
+            name: STATE_EVENT.to_string(),
+            payload: serde_json::to_value(ConnectionState::Connected).unwrap(),
+        });
+        press(&mut app, KeyCode::Char('k'));
+        assert_eq!(
+            press(&mut app, KeyCode::Char('c')),
+            Some(Action::SwitchServer("de1".to_string()))
+        );
+        assert_eq!(
+            app.key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Action::Quit)
+        );
+    }
+
+    #[test]
+    fn keeps_a_scrolled_log_pane_in_place() {
+        let mut app = app(&[]);
+        for seq in 1..=30 {
+            app.log(record(seq));
+        }
+        press(&mut app, KeyCode::PageUp);
+        assert_eq!(app.scroll, PAGE);
+
+        app.event(Event {
+            name: LOG_EVENT.to_string(),
+            payload: serde_json::to_value(record(31)).unwrap(),
+        });
+        // Already shown lines, as when the backlog overlaps the events
+        app.log(record(31));
+        assert_eq!(app.scroll, PAGE + 1);
+        assert_eq!(app.logs.len(), 31);
+
+        press(&mut app, KeyCode::End);
+        assert_eq!(app.scroll, 0);
+        app.log(record(32));
+        assert_eq!(app.scroll, 0);
+    }
+
+    #[test]
+    fn keeps_a_bounded_history() {
+        let mut app = app(&[]);
+        for rate in 0..HISTORY as u64 + 10 {
+            app.tick(
+                crate::disconnected(),
+                TrafficStats {
+                    rate_in: rate as f64,
+                    ..TrafficStats::default()
+                },
+            );
+        }
+        for seq in 1..=LOG_LINES as u64 + 5 {
+            app.log(record(seq));
+        }
+
+        assert_eq!(app.rates_in.len(), HISTORY);
+        assert_eq!(app.rates_in.back(), Some(&(HISTORY as u64 + 9)));
+        assert_eq!(app.logs.len(), LOG_LINES);
+        assert_eq!(app.logs.front().map(|r| r.seq), Some(6));
+    }
+}
@@ -0,0 +1,200 @@
+//! `gekkovpn tui`, a terminal front-end for the same tunnel as the other commands.
+//! It controls the tunnel of the daemon or of a running `connect` when there is
+//! one; otherwise it runs its own, which closes when it quits.
+
+mod app;
+mod ui;
+
+use self::app::{Action, App};
+use crate::error::{self, Failure};
+use crate::session;
+use gekkovpn_core::catalog::{self, Server};
+use gekkovpn_core::logbuffer::LogFilter;
+use gekkovpn_core::traffic::TrafficStats;
+use gekkovpn_core::{
+    Backend, Broadcast, ConnectionInfo, Event, Events, Service, VpnClient, VpnError,
+};
+use ratatui::crossterm::event::{self as terminal, Event as Input};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+/// How often the connection and traffic are polled
+const TICK: Duration = Duration::from_secs(1);
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Everything the UI reacts to
+enum Update {
+    Input(Input),
+    Event(Event),
+    Tick(ConnectionInfo, TrafficStats),
+    Latency(String, Option<Duration>),
+    Finished(Result<String, VpnError>),
+}
+
+pub async fn run(user: Option<String>) -> Result<u8, Failure> {
+    let username = crate::username(user)?;
+    let (service, events, owned) = open().await?;
+    let servers = service.list_servers().await?;
+    let connection = service.status().await?;
+    let backlog = service.logs(LogFilter::default()).await?;
+    let mut app = App::new(username, servers.clone(), connection, backlog);
+
+    let (updates, mut inbox) = mpsc::unbounded_channel();
+    tokio::spawn(forward(events, updates.clone()));
+    tokio::spawn(poll(service.clone(), updates.clone()));
+    measure(&servers, &updates);
+    let input = updates.clone();
+    // crossterm reads the terminal blocking; the thread ends with the process
+    std::thread::spawn(move || {
+        while let Ok(event) = terminal::read() {
+            if input.send(Update::Input(event)).is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut screen = ratatui::init();
+    let result = loop {
+        if let Err(e) = screen.draw(|frame| ui::draw(frame, &app)) {
+            break Err(VpnError::Process(format!("Failed to draw: {}", e)));
+        }
+        let Some(update) = inbox.recv().await else {
+            break Ok(());
+        };
+        let action = match update {
+            Update::Input(Input::Key(key)) => app.key(key),
+            Update::Input(_) => None,
+            Update::Event(event) => {
+                app.event(event);
+                None
+            }
+            Update::Tick(connection, traffic) => {
+                app.tick(connection, traffic);
+                None
+            }
+            Update::Latency(name, latency) => {
+                app.latency(&name, latency);
+                None
+            }
+            Update::Finished(result) => {
+                app.finished(result);
+                None
+            }
+        };
+        match action {
+            Some(Action::Quit) => break Ok(()),
+            Some(Action::MeasureLatency) => measure(&servers, &updates),
+            Some(action) => {
+                app.message = None;
+                tokio::spawn(perform(
+                    service.clone(),
+                    app.username.clone(),
+                    action,
+                    updates.clone(),
+                ));
+            }
+            None => {}
+        }
+    };
+    ratatui::restore();
+
+    // A tunnel of our own does not outlive the UI
+    if owned.is_some() {
+        let _ = service.disconnect().await;
+    }
+    result?;
+    Ok(error::SUCCESS)
+}
+
+/// The tunnel to control, its events, and the session published when it is our own
+async fn open() -> Result<(Service, Receiver<Event>, Option<session::Published>), Failure> {
+    if let Some(tunnel) = crate::tunnel().await? {
+        let connection = tunnel.connection();
+        let events = connection.subscribe().await?;
+        return Ok((Service::remote(connection), events, None));
+    }
+    let broadcast = Broadcast::default();
+    let client = VpnClient::start(Backend::system(Events::new(Arc::new(broadcast.clone()))));
+    let published = session::publish(client.clone(), broadcast.clone()).await?;
+    Ok((
+        Service::local(client),
+        broadcast.subscribe(),
+        Some(published),
+    ))
+}
+
+async fn forward(mut events: Receiver<Event>, updates: UnboundedSender<Update>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if updates.send(Update::Event(event)).is_err() {
+                    return;
+                }
+            }
+            // The next tick catches up on the state
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => {
+                let _ = updates.send(Update::Finished(Err(VpnError::ManagerUnavailable)));
+                return;
+            }
+        }
+    }
+}
+
+async fn poll(service: Service, updates: UnboundedSender<Update>) {
+    let mut ticks = tokio::time::interval(TICK);
+    loop {
+        ticks.tick().await;
+        let update = match (service.status().await, service.traffic_stats().await) {
+            (Ok(connection), Ok(traffic)) => Update::Tick(connection, traffic),
+            // Whatever ran the tunnel is gone; the event forwarder reports it
+            _ => return,
+        };
+        if updates.send(update).is_err() {
+            return;
+        }
+    }
+}
+
+/// Measures every server at once, each by its first remote
+fn measure(servers: &[Server], updates: &UnboundedSender<Update>) {
+    for server in servers {
+        let (name, remote, updates) = (
+            server.name.clone(),
+            server.remotes.first().cloned(),
+            updates.clone(),
+        );
+        tokio::spawn(async move {
+            let latency = match remote {
+                Some(remote) => catalog::latency(&remote, LATENCY_TIMEOUT).await,
+                None => None,
+            };
+            let _ = updates.send(Update::Latency(name, latency));
+        });
+    }
+}
+
+async fn perform(
+    service: Service,
+    username: String,
+    action: Action,
+    updates: UnboundedSender<Update>,
+) {
+    let result = match action {
+        Action::Connect(server) => service.connect(server, username).await,
+        Action::SwitchServer(server) => service.switch_server(server, username).await,
+        Action::Disconnect => service.disconnect().await,
+        Action::CancelConnect => service.cancel_connect().await.map(|cancelled| {
+            if cancelled {
+                "Connection cancelled".to_string()
+            } else {
+                "No connection in progress".to_string()
+            }
+        }),
+        Action::MeasureLatency | Action::Quit => return,
+    };
+    let _ = updates.send(Update::Finished(result));
+}
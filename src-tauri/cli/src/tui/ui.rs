@@ -0,0 +1,238 @@
+use super::app::{App, Latency};
+use crate::{bytes, describe};
+use gekkovpn_core::logbuffer::Severity;
+use gekkovpn_core::states::ConnectionState;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Sparkline};
+use ratatui::Frame;
+
+const KEYS: &str =
+    "↑↓ select  enter connect  d disconnect  x cancel  r latency  PgUp/PgDn log  q quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [header, middle, log, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Percentage(45),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [servers, traffic] =
+        Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(middle);
+
+    draw_header(frame, app, header);
+    draw_servers(frame, app, servers);
+    draw_traffic(frame, app, traffic);
+    draw_log(frame, app, log);
+    let footer_text = app.message.as_deref().unwrap_or(KEYS);
+    frame.render_widget(
+        Paragraph::new(footer_text).style(Style::default().fg(Color::DarkGray)),
+        footer,
+    );
+}
+
+fn state_color(state: &ConnectionState) -> Color {
+    match state {
+        ConnectionState::Connected => Color::Green,
+        ConnectionState::Disconnected => Color::Gray,
+        ConnectionState::Failed(_) => Color::Red,
+        _ => Color::Yellow,
+    }
+}
+
+fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
+    let connection = &app.connection;
+    let mut spans = vec![Span::styled(
+        describe(&connection.state),
+        Style::default()
+            .fg(state_color(&connection.state))
+            .add_modifier(Modifier::BOLD),
+    )];
+    if let Some(server) = &connection.server {
+        spans.push(Span::raw(format!("  {}", server)));
+    }
+    if let Some(local_ip) = connection.tunnel.as_ref().and_then(|t| t.local_ip.as_ref()) {
+        spans.push(Span::raw(format!("  {}", local_ip)));
+    }
+    if let Some(uptime) = connection.uptime_secs {
+        spans.push(Span::raw(format!(
+            "  up {}h {:02}m {:02}s",
+            uptime / 3_600,
+            uptime / 60 % 60,
+            uptime % 60
+        )));
+    }
+    let title = format!(" GekkoVPN · {} ", app.username);
+    frame.render_widget(
+        Paragraph::new(Line::from(spans)).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn draw_servers(frame: &mut Frame, app: &App, area: Rect) {
+    let width = app
+        .servers
+        .iter()
+        .map(|entry| entry.server.name.len())
+        .max()
+        .unwrap_or(0);
+    let items: Vec<ListItem> = app
+        .servers
+        .iter()
+        .map(|entry| {
+            let server = &entry.server;
+            let place = [server.location.as_deref(), server.country.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(", ");
+            let (latency, color) = match entry.latency {
+                Latency::Measuring => ("…".to_string(), Color::DarkGray),
+                Latency::Measured(latency) => {
+                    let ms = latency.as_millis();
+                    let color = match ms {
+                        0..=79 => Color::Green,
+                        80..=199 => Color::Yellow,
+                        _ => Color::Red,
+                    };
+                    (format!("{} ms", ms), color)
+                }
+                Latency::Unreachable => ("-".to_string(), Color::Red),
+            };
+            let current = app.connection.server.as_deref() == Some(server.name.as_str());
+            let name_style = if current {
+                Style::default().fg(state_color(&app.connection.state))
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:width$}", server.name, width = width), name_style),
+                Span::raw(format!("  {:20} ", place)),
+                Span::styled(format!("{:>7}", latency), Style::default().fg(color)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::bordered().title(" Servers "))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("› ");
+    let mut state = ListState::default().with_selected(Some(app.selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_traffic(frame: &mut Frame, app: &App, area: Rect) {
+    let [down, up] =
+        Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(area);
+    let traffic = &app.traffic;
+    for (area, arrow, rate, total, rates, color) in [
+        (
+            down,
+            "↓",
+            traffic.rate_in,
+            traffic.bytes_in,
+            &app.rates_in,
+            Color::Cyan,
+        ),
+        (
+            up,
+            "↑",
+            traffic.rate_out,
+            traffic.bytes_out,
+            &app.rates_out,
+            Color::Magenta,
+        ),
+    ] {
+        // The newest samples that fit, right-aligned like a scrolling graph
+        let visible = area.width.saturating_sub(2) as usize;
+        let data: Vec<u64> = rates
+            .iter()
+            .skip(rates.len().saturating_sub(visible))
+            .copied()
+            .collect();
+        let title = format!(
+            " {} {}/s · {} total ",
+            arrow,
+            bytes(rate.max(0.0) as u64),
+            bytes(total)
+        );
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title(title))
+                .data(&data)
+                .style(Style::default().fg(color)),
+            area,
+        );
+    }
+}
+
+fn draw_log(frame: &mut Frame, app: &App, area: Rect) {
+    let height = area.height.saturating_sub(2) as usize;
+    let end = app.logs.len().saturating_sub(app.scroll);
+    let lines: Vec<Line> = app
+        .logs
+        .range(end.saturating_sub(height)..end)
+        .map(|record| {
+            let color = match record.severity {
+                Severity::Debug => Color::DarkGray,
+                Severity::Info => Color::Reset,
+                Severity::Warning => Color::Yellow,
+                Severity::Error => Color::Red,
+            };
+            Line::styled(record.to_string(), Style::default().fg(color))
+        })
+        .collect();
+    let title = if app.scroll > 0 {
+        format!(" Log · {} lines up ", app.scroll)
+    } else {
+        " Log ".to_string()
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gekkovpn_core::catalog::Server;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::time::Duration;
+
+    #[test]
+    fn shows_the_servers_and_the_state() {
+        let server = Server {
+            name: "nl1".to_string(),
+            remotes: Vec::new(),
+            location: Some("Amsterdam".to_string()),
+            country: Some("NL".to_string()),
+            flags: Vec::new(),
+        };
+        let mut app = App::new(
+            "alice".to_string(),
+            vec![server],
+            crate::disconnected(),
+            Vec::new(),
+        );
+        app.latency("nl1", Some(Duration::from_millis(23)));
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        assert!(screen.contains("Disconnected"));
+        assert!(screen.contains("GekkoVPN · alice"));
+        assert!(screen.contains("nl1  Amsterdam, NL"));
+        assert!(screen.contains("23 ms"));
+    }
+}
@@ -0,0 +1,178 @@
+//! Stands in for openvpn in the connect tests. It accepts openvpn's arguments, reads a
+//! scenario from the file passed as `--config` and plays it, one directive per line:
+//!
+//! ```text
+//! stdout <line>          print a line to stdout
+//! stderr <line>          print a line to stderr
+//! management             connect to `--management`, a Unix socket or a TCP port,
+//!                        then hold and wait for `hold release`
+//! state <STATE>[,<...>]  send `>STATE:`, e.g. `state CONNECTED,SUCCESS,10.8.0.2,185.107.56.21,1194`
+//! log <line>             send `>LOG:`
+//! auth                   ask for username and password and wait for them
+//! auth-failed            tell the client its credentials were rejected
+//! fatal <message>        send `>FATAL:`
+//! bytecount <in> <out>   send `>BYTECOUNT:`
+//! sleep <ms>             wait
+//! hang                   wait until killed, ignoring `signal SIGTERM`
+//! serve                  wait for `signal SIGTERM`, then report EXITING and exit 0
+//! exit <code>            exit right away, as a crash would
+//! ```
+//!
+//! Every management command is answered with `SUCCESS:`. Empty lines and lines
+//! starting with `#` are skipped, and the scenario ends with exit code 0.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+struct Management {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    commands: Receiver<String>,
+}
+
+impl Management {
+    /// `port` is `unix` when `address` is the path of a socket
+    fn connect(address: &str, port: &str) -> Management {
+        fn connected<T>(e: std::io::Error) -> T {
+            fail(&format!("cannot connect to management: {}", e))
+        }
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) = if port == "unix" {
+            #[cfg(unix)]
+            {
+                let stream =
+                    std::os::unix::net::UnixStream::connect(address).unwrap_or_else(connected);
+                (Box::new(stream.try_clone().unwrap()), Box::new(stream))
+            }
+            #[cfg(not(unix))]
+            fail("no unix sockets here")
+        } else {
+            let stream =
+                TcpStream::connect(format!("{}:{}", address, port)).unwrap_or_else(connected);
+            (Box::new(stream.try_clone().unwrap()), Box::new(stream))
+        };
+        Management::start(reader, writer)
+    }
+
+    fn start(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>) -> Management {
+        let writer = Arc::new(Mutex::new(writer));
+        let (sender, commands) = mpsc::channel();
+
+        let replies = writer.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(command) = line else { break };
+                let verb = command.split_whitespace().next().unwrap_or_default();
+                let reply = format!("SUCCESS: {} command succeeded\r\n", verb);
+                if replies.lock().unwrap().write_all(reply.as_bytes()).is_err()
+                    || sender.send(command).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Management { writer, commands }
+    }
+
+    fn send(&self, line: &str) {
+        let _ = self
+            .writer
+            .lock()
+            .unwrap()
+            .write_all(format!("{}\r\n", line).as_bytes());
+    }
+
+    /// Blocks until a command starting with `prefix` arrives
+    fn wait_for(&self, prefix: &str) {
+        loop {
+            match self.commands.recv() {
+                Ok(command) if command.starts_with(prefix) => return,
+                Ok(_) => {}
+                // The client hung up, which real openvpn treats as a reason to exit
+                Err(_) => std::process::exit(1),
+            }
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let value = |name: &str, offset: usize| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + offset))
+            .cloned()
+    };
+    let config = value("--config", 1).unwrap_or_else(|| fail("no --config"));
+    let scenario = std::fs::read_to_string(&config)
+        .unwrap_or_else(|e| fail(&format!("cannot read {}: {}", config, e)));
+
+    let mut management: Option<Management> = None;
+
+    for line in scenario.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (directive, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match directive {
+            "stdout" => println!("{}", rest),
+            "stderr" => eprintln!("{}", rest),
+            "management" => {
+                let host = value("--management", 1).unwrap_or_else(|| fail("no --management"));
+                let port = value("--management", 2).unwrap_or_else(|| fail("no --management"));
+                let client = Management::connect(&host, &port);
+                client.send(
+                    ">INFO:OpenVPN Management Interface Version 5 -- type 'help' for more info",
+                );
+                client.send(">HOLD:Waiting for hold release:0");
+                client.wait_for("hold release");
+                management = Some(client);
+            }
+            "state" => connected(&management).send(&format!(">STATE:{},{}", now(), rest)),
+            "log" => connected(&management).send(&format!(">LOG:{},I,{}", now(), rest)),
+            "auth" => {
+                let client = connected(&management);
+                client.send(">PASSWORD:Need 'Auth' username/password");
+                client.wait_for("password ");
+            }
+            "auth-failed" => connected(&management).send(">PASSWORD:Verification Failed: 'Auth'"),
+            "fatal" => connected(&management).send(&format!(">FATAL:{}", rest)),
+            "bytecount" => {
+                let (bytes_in, bytes_out) = rest.split_once(' ').unwrap_or((rest, "0"));
+                connected(&management).send(&format!(">BYTECOUNT:{},{}", bytes_in, bytes_out));
+            }
+            "sleep" => std::thread::sleep(Duration::from_millis(rest.parse().unwrap_or(0))),
+            "hang" => loop {
+                std::thread::sleep(Duration::from_secs(60));
+            },
+            "serve" => {
+                let client = connected(&management);
+                client.wait_for("signal SIGTERM");
+                client.send(&format!(">STATE:{},EXITING,SIGTERM,,,,,", now()));
+                std::process::exit(0);
+            }
+            "exit" => std::process::exit(rest.parse().unwrap_or(1)),
+            other => fail(&format!("unknown directive `{}`", other)),
+        }
+    }
+}
+
+fn connected(management: &Option<Management>) -> &Management {
+    management
+        .as_ref()
+        .unwrap_or_else(|| fail("no management connection yet"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("fake_openvpn: {}", message);
+    std::process::exit(2)
+}
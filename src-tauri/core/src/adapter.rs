@@ -0,0 +1,211 @@
+use crate::error::VpnError;
+use crate::paths::AppPaths;
+#[cfg(windows)]
+use crate::paths::Platform;
+#[cfg(windows)]
+use crate::tapadapter::TapAdapter;
+#[cfg(target_os = "linux")]
+use crate::tunadapter::TunAdapter;
+use serde::{Deserialize, Serialize};
+
+/// Name given to adapters the app creates
+pub const ADAPTER_NAME: &str = "GekkoVPN";
+
+/// A virtual network device openvpn can send the tunnel through
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdapterInfo {
+    pub name: String,
+    /// Passed to openvpn as `--dev-node`
+    pub dev_node: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "reason", rename_all = "snake_case")]
+pub enum AdapterHealth {
+    /// An adapter is there and can be used
+    Ready,
+    /// No adapter yet, `ensure_exists` would create one. openvpn creates its own
+    /// device elsewhere, so only Windows reports this.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Missing,
+    /// Adapters cannot be used, e.g. the driver is not installed
+    Unavailable(String),
+}
+
+/// The adapters there are and whether one can be used
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdapterReport {
+    pub health: AdapterHealth,
+    pub adapters: Vec<AdapterInfo>,
+}
+
+/// Creates and finds the devices openvpn needs. Implementations may block, so call
+/// them off the async runtime.
+pub trait NetworkAdapter: Send + Sync {
+    /// Returns a usable adapter, creating one if there is none
+    fn ensure_exists(&self) -> Result<AdapterInfo, VpnError>;
+    fn list(&self) -> Result<Vec<AdapterInfo>, VpnError>;
+    fn create(&self, name: &str) -> Result<AdapterInfo, VpnError>;
+    fn remove(&self, adapter: &AdapterInfo) -> Result<(), VpnError>;
+    fn health(&self) -> AdapterHealth;
+
+    /// openvpn arguments that make it use `adapter`
+    fn openvpn_args(&self, adapter: &AdapterInfo) -> Vec<String> {
+        vec!["--dev-node".to_string(), adapter.dev_node.clone()]
+    }
+}
+
+/// The TAP adapters managed with the bundled tapctl
+#[cfg(windows)]
+pub fn for_platform(paths: &AppPaths) -> Result<Box<dyn NetworkAdapter>, VpnError> {
+    Ok(Box::new(TapAdapter::new(
+        &paths.bin_dir,
+        Platform::current(),
+    )?))
+}
+
+/// Tun devices, which openvpn creates through the kernel's clone device
+#[cfg(target_os = "linux")]
+pub fn for_platform(_paths: &AppPaths) -> Result<Box<dyn NetworkAdapter>, VpnError> {
+    Ok(Box::new(TunAdapter::default()))
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn for_platform(_paths: &AppPaths) -> Result<Box<dyn NetworkAdapter>, VpnError> {
+    Err(VpnError::Adapter(format!(
+        "Network adapters are not supported on {}",
+        std::env::consts::OS
+    )))
+}
+
+/// Health and adapters of `adapter`
+pub async fn report(adapter: Box<dyn NetworkAdapter>) -> Result<AdapterReport, VpnError> {
+    tokio::task::spawn_blocking(move || {
+        Ok(AdapterReport {
+            health: adapter.health(),
+            adapters: adapter.list()?,
+        })
+    })
+    .await
+    .map_err(|e| VpnError::Adapter(format!("Failed to list adapters: {}", e)))?
+}
+
+/// Creates an adapter called `name`, or `GekkoVPN`, ahead of the first connect
+pub async fn create(
+    adapter: Box<dyn NetworkAdapter>,
+    name: Option<String>,
+) -> Result<AdapterInfo, VpnError> {
+    let name = name.unwrap_or_else(|| ADAPTER_NAME.to_string());
+    tokio::task::spawn_blocking(move || adapter.create(&name))
+        .await
+        .map_err(|e| VpnError::Adapter(format!("Failed to create the adapter: {}", e)))?
+}
+
+/// Removes the adapter called `name`, e.g. to have a broken one recreated on the next connect
+pub async fn remove(adapter: Box<dyn NetworkAdapter>, name: String) -> Result<(), VpnError> {
+    tokio::task::spawn_blocking(move || {
+        let info = adapter
+            .list()?
+            .into_iter()
+            .find(|info| info.name == name)
+            .ok_or_else(|| {
+                VpnError::InvalidSetting(format!("There is no adapter called {}", name))
+            })?;
+        adapter.remove(&info)
+    })
+    .await
+    .map_err(|e| VpnError::Adapter(format!("Failed to remove the adapter: {}", e)))?
+}
+
+/// Adapters kept in memory, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeAdapter {
+    pub adapters: std::sync::Mutex<Vec<AdapterInfo>>,
+    pub unavailable: Option<String>,
+}
+
+#[cfg(test)]
+impl NetworkAdapter for FakeAdapter {
+    fn ensure_exists(&self) -> Result<AdapterInfo, VpnError> {
+        match self.list()?.into_iter().next() {
+            Some(adapter) => Ok(adapter),
+            None => self.create(ADAPTER_NAME),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<AdapterInfo>, VpnError> {
+        match &self.unavailable {
+            Some(reason) => Err(VpnError::Adapter(reason.clone())),
+            None => Ok(self.adapters.lock().unwrap().clone()),
+        }
+    }
+
+    fn create(&self, name: &str) -> Result<AdapterInfo, VpnError> {
+        if let Some(reason) = &self.unavailable {
+            return Err(VpnError::Adapter(reason.clone()));
+        }
+        let adapter = AdapterInfo {
+            name: name.to_string(),
+            dev_node: name.to_string(),
+        };
+        self.adapters.lock().unwrap().push(adapter.clone());
+        Ok(adapter)
+    }
+
+    fn remove(&self, adapter: &AdapterInfo) -> Result<(), VpnError> {
+        let mut adapters = self.adapters.lock().unwrap();
+        let count = adapters.len();
+        adapters.retain(|existing| existing != adapter);
+        if adapters.len() == count {
+            return Err(VpnError::Adapter(format!(
+                "No adapter named {}",
+                adapter.name
+            )));
+        }
+        Ok(())
+    }
+
+    fn health(&self) -> AdapterHealth {
+        match self.list() {
+            Ok(adapters) if adapters.is_empty() => AdapterHealth::Missing,
+            Ok(_) => AdapterHealth::Ready,
+            Err(e) => AdapterHealth::Unavailable(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_an_adapter_once_and_passes_it_as_dev_node() {
+        let adapter = FakeAdapter::default();
+        assert_eq!(adapter.health(), AdapterHealth::Missing);
+
+        let created = adapter.ensure_exists().unwrap();
+        assert_eq!(created.name, ADAPTER_NAME);
+        assert_eq!(adapter.ensure_exists().unwrap(), created);
+        assert_eq!(adapter.list().unwrap().len(), 1);
+        assert_eq!(adapter.health(), AdapterHealth::Ready);
+        assert_eq!(adapter.openvpn_args(&created), ["--dev-node", "GekkoVPN"]);
+
+        adapter.remove(&created).unwrap();
+        assert!(adapter.remove(&created).is_err());
+        assert_eq!(adapter.health(), AdapterHealth::Missing);
+    }
+
+    #[test]
+    fn reports_an_unavailable_driver() {
+        let adapter = FakeAdapter {
+            unavailable: Some("TAP driver is not installed".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(adapter.ensure_exists(), Err(VpnError::Adapter(_))));
+        assert!(matches!(
+            adapter.health(),
+            AdapterHealth::Unavailable(reason) if reason.contains("TAP driver is not installed")
+        ));
+    }
+}
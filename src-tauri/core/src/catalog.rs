@@ -0,0 +1,490 @@
+//! The servers that can be connected to, read from the profiles in the config
+//! directory. Besides openvpn's own `remote`, `proto` and `port`, a profile may
+//! describe its server in comments:
+//!
+//! ```text
+//! # location: Amsterdam
+//! # country: NL
+//! # flags: p2p, streaming
+//! ```
+
+use crate::error::VpnError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+/// What openvpn uses when a profile does not say
+const DEFAULT_PORT: u16 = 1194;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    Udp,
+    Tcp,
+}
+
+impl Protocol {
+    /// openvpn's names, which may pin the address family or the TCP role
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "udp" | "udp4" | "udp6" => Some(Protocol::Udp),
+            "tcp" | "tcp4" | "tcp6" | "tcp-client" | "tcp4-client" | "tcp6-client" => {
+                Some(Protocol::Tcp)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Udp => "udp",
+            Protocol::Tcp => "tcp",
+        })
+    }
+}
+
+/// One address openvpn tries, in the order the profile lists them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Remote {
+    pub host: String,
+    pub port: u16,
+    pub protocol: Protocol,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Server {
+    /// The directory of the profile, which is what connecting takes
+    pub name: String,
+    pub remotes: Vec<Remote>,
+    pub location: Option<String>,
+    /// As written in the profile, usually an ISO 3166 code
+    pub country: Option<String>,
+    pub flags: Vec<String>,
+}
+
+const CONFIG_FILE: &str = "gekko-vpn-server_openvpn_remote_access_l3.ovpn";
+
+/// The profile of `server_name` in the config directory
+pub fn config_path(config_dir: &Path, server_name: &str) -> PathBuf {
+    config_dir.join(server_name).join(CONFIG_FILE)
+}
+
+/// Servers with a profile in the config directory, sorted by name
+fn server_names(config_dir: &Path) -> Result<Vec<String>, VpnError> {
+    let entries =
+        std::fs::read_dir(config_dir).map_err(|_| VpnError::ConfigNotFound(config_dir.into()))?;
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| config_path(config_dir, name).is_file())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Every server with a profile in `config_dir`, sorted by name. Profiles that
+/// cannot be read are left out.
+pub fn list(config_dir: &Path) -> Result<Vec<Server>, VpnError> {
+    Ok(server_names(config_dir)?
+        .into_iter()
+        .filter_map(|name| {
+            let profile = std::fs::read_to_string(config_path(config_dir, &name)).ok()?;
+            Some(parse(name, &profile))
+        })
+        .collect())
+}
+
+/// The profile of `server_name`, which has to be a server of the catalog. Names
+/// come from the frontends and the daemon starts openvpn as root with the profile,
+/// so anything that could lead outside `config_dir` is refused, including a
+/// server directory that is a link to somewhere else.
+pub fn profile(config_dir: &Path, server_name: &str) -> Result<PathBuf, VpnError> {
+    let unknown = || VpnError::UnknownServer(server_name.to_string());
+
+    // A plain directory name, whatever the platform takes for a separator
+    let mut components = Path::new(server_name).components();
+    let plain = matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !server_name.contains(['/', '\\', ':', '\0']);
+    if !plain
+        || !server_names(config_dir)?
+            .iter()
+            .any(|name| name == server_name)
+    {
+        return Err(unknown());
+    }
+
+    let path = config_path(config_dir, server_name);
+    let root = config_dir
+        .canonicalize()
+        .map_err(|_| VpnError::ConfigNotFound(config_dir.into()))?;
+    let profile = path
+        .canonicalize()
+        .map_err(|_| VpnError::ConfigNotFound(path.clone()))?;
+    if !profile.starts_with(&root) {
+        return Err(unknown());
+    }
+    Ok(profile)
+}
+
+/// A `remote` whose port or protocol is left to the rest of the profile
+struct PartialRemote {
+    host: String,
+    port: Option<u16>,
+    protocol: Option<Protocol>,
+}
+
+/// `port` and `proto` as set at the top level or in a `<connection>` block
+#[derive(Default, Clone, Copy)]
+struct Defaults {
+    port: Option<u16>,
+    protocol: Option<Protocol>,
+}
+
+impl Defaults {
+    fn read(&mut self, directive: &str, arguments: &[&str]) {
+        match (directive, arguments.first()) {
+            ("port", Some(port)) => self.port = port.parse().ok().or(self.port),
+            ("proto", Some(name)) => self.protocol = Protocol::parse(name).or(self.protocol),
+            _ => {}
+        }
+    }
+
+    fn fill(self, remote: PartialRemote, outer: Defaults) -> Remote {
+        Remote {
+            host: remote.host,
+            port: remote
+                .port
+                .or(self.port)
+                .or(outer.port)
+                .unwrap_or(DEFAULT_PORT),
+            protocol: remote
+                .protocol
+                .or(self.protocol)
+                .or(outer.protocol)
+                .unwrap_or(Protocol::Udp),
+        }
+    }
+}
+
+/// Reads the server `name` from its profile. openvpn applies `port` and `proto`
+/// wherever they appear, so remotes are completed once the whole profile is read.
+pub fn parse(name: String, profile: &str) -> Server {
+    let mut server = Server {
+        name,
+        remotes: Vec::new(),
+        location: None,
+        country: None,
+        flags: Vec::new(),
+    };
+    let mut global = Defaults::default();
+    let mut top_level = Vec::new();
+    // Remotes of finished `<connection>` blocks, with the block's own defaults
+    let mut blocks: Vec<(Vec<PartialRemote>, Defaults)> = Vec::new();
+    let mut block: Option<(Vec<PartialRemote>, Defaults)> = None;
+    // Inside an inline file such as `<ca>`, whose lines are not directives
+    let mut inline = false;
+
+    for line in profile.lines().map(str::trim) {
+        if let Some(tag) = line.strip_prefix("</") {
+            if tag.trim_end_matches('>') == "connection" {
+                blocks.extend(block.take());
+            }
+            inline = false;
+            continue;
+        }
+        if let Some(tag) = line.strip_prefix('<') {
+            if tag.trim_end_matches('>') == "connection" {
+                block = Some((Vec::new(), Defaults::default()));
+            } else {
+                inline = true;
+            }
+            continue;
+        }
+        if inline {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix(['#', ';']) {
+            read_metadata(&mut server, comment);
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&directive, arguments)) = words.split_first() else {
+            continue;
+        };
+        let (remotes, defaults) = match &mut block {
+            Some((remotes, defaults)) => (remotes, defaults),
+            None => (&mut top_level, &mut global),
+        };
+        if directive == "remote" {
+            if let Some(host) = arguments.first() {
+                remotes.push(PartialRemote {
+                    host: host.to_string(),
+                    port: arguments.get(1).and_then(|port| port.parse().ok()),
+                    protocol: arguments.get(2).and_then(|name| Protocol::parse(name)),
+                });
+            }
+        } else {
+            defaults.read(directive, arguments);
+        }
+    }
+
+    // openvpn ignores top-level remotes when there are connection blocks
+    server.remotes = if blocks.is_empty() {
+        top_level
+            .into_iter()
+            .map(|remote| global.fill(remote, Defaults::default()))
+            .collect()
+    } else {
+        blocks
+            .into_iter()
+            .flat_map(|(remotes, defaults)| {
+                remotes
+                    .into_iter()
+                    .map(move |remote| defaults.fill(remote, global))
+            })
+            .collect()
+    };
+    server
+}
+
+fn read_metadata(server: &mut Server, comment: &str) {
+    let Some((key, value)) = comment.split_once(':') else {
+        return;
+    };
+    let value = value.trim();
+    if value.is_empty() {
+        return;
+    }
+    match key.trim().to_ascii_lowercase().as_str() {
+        "location" => server.location = Some(value.to_string()),
+        "country" => server.country = Some(value.to_string()),
+        "flags" => server.flags.extend(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|flag| !flag.is_empty())
+                .map(str::to_string),
+        ),
+        _ => {}
+    }
+}
+
+/// How long `remote` takes to answer a TCP handshake, or `None` when it does not
+/// within `timeout`. A refused handshake is an answer too, so servers that only
+/// speak UDP are measured as long as the port is not firewalled.
+pub async fn latency(remote: &Remote, timeout: Duration) -> Option<Duration> {
+    handshake(
+        tokio::net::lookup_host((remote.host.as_str(), remote.port)),
+        timeout,
+    )
+    .await
+}
+
+/// [`latency`] with the address found by `resolve`
+async fn handshake<A>(
+    resolve: impl Future<Output = std::io::Result<A>>,
+    timeout: Duration,
+) -> Option<Duration>
+where
+    A: Iterator<Item = SocketAddr>,
+{
+    let address = tokio::time::timeout(timeout, resolve)
+        .await
+        .ok()?
+        .ok()?
+        .next()?;
+
+    let start = Instant::now();
+    match tokio::time::timeout(timeout, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => Some(start.elapsed()),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => Some(start.elapsed()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(host: &str, port: u16, protocol: Protocol) -> Remote {
+        Remote {
+            host: host.to_string(),
+            port,
+            protocol,
+        }
+    }
+
+    #[test]
+    fn reads_remotes_and_metadata() {
+        let profile = "\
+# GekkoVPN Amsterdam
+# Location: Amsterdam
+# country: NL
+# flags: p2p, streaming
+client
+dev tun
+remote nl1.gekkovpn.eu 443 tcp
+remote 185.107.56.21
+proto udp
+port 1195
+<ca>
+-----BEGIN CERTIFICATE-----
+remote not-a-directive 1
+-----END CERTIFICATE-----
+</ca>
+";
+        let server = parse("nl1".to_string(), profile);
+
+        assert_eq!(
+            server,
+            Server {
+                name: "nl1".to_string(),
+                remotes: vec![
+                    remote("nl1.gekkovpn.eu", 443, Protocol::Tcp),
+                    remote("185.107.56.21", 1195, Protocol::Udp),
+                ],
+                location: Some("Amsterdam".to_string()),
+                country: Some("NL".to_string()),
+                flags: vec!["p2p".to_string(), "streaming".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn prefers_connection_blocks_over_top_level_remotes() {
+        let profile = "\
+proto tcp-client
+remote ignored.example
+<connection>
+remote de1.gekkovpn.eu 1194 udp
+</connection>
+<connection>
+remote de1.gekkovpn.eu
+port 443
+</connection>
+";
+        let server = parse("de1".to_string(), profile);
+
+        assert_eq!(
+            server.remotes,
+            [
+                remote("de1.gekkovpn.eu", 1194, Protocol::Udp),
+                remote("de1.gekkovpn.eu", 443, Protocol::Tcp),
+            ]
+        );
+        assert_eq!(server.location, None);
+        assert!(server.flags.is_empty());
+    }
+
+    #[test]
+    fn lists_servers_that_have_a_profile() {
+        let dir = std::env::temp_dir().join(format!("gekkovpn-servers-{}", std::process::id()));
+        for server in ["nl1", "de2"] {
+            std::fs::create_dir_all(dir.join(server)).unwrap();
+            std::fs::write(config_path(&dir, server), "remote vpn.example 1194").unwrap();
+        }
+        std::fs::create_dir_all(dir.join("empty")).unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let names = server_names(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names.unwrap(), ["de2", "nl1"]);
+        assert!(matches!(
+            server_names(&dir),
+            Err(VpnError::ConfigNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn lists_the_servers_in_the_config_directory() {
+        let dir = std::env::temp_dir().join(format!("gekkovpn-catalog-{}", std::process::id()));
+        for (name, profile) in [
+            ("nl1", "# location: Amsterdam\nremote nl1.example 1194"),
+            ("de2", "remote de2.example 443 tcp"),
+        ] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            std::fs::write(config_path(&dir, name), profile).unwrap();
+        }
+
+        let servers = list(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let servers = servers.unwrap();
+        assert_eq!(
+            servers.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            ["de2", "nl1"]
+        );
+        assert_eq!(servers[1].location.as_deref(), Some("Amsterdam"));
+    }
+
+    #[test]
+    fn resolves_only_servers_of_the_catalog() {
+        let root = std::env::temp_dir().join(format!("gekkovpn-profile-{}", std::process::id()));
+        let dir = root.join("config");
+        std::fs::create_dir_all(dir.join("nl1")).unwrap();
+        std::fs::write(config_path(&dir, "nl1"), "remote nl1.example").unwrap();
+        // A profile next to the config directory, as a traversal would reach it
+        std::fs::create_dir_all(root.join("outside")).unwrap();
+        std::fs::write(config_path(&root, "outside"), "up /bin/sh").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("outside"), dir.join("linked")).unwrap();
+
+        let resolved = profile(&dir, "nl1").unwrap();
+        let expected = config_path(&dir, "nl1").canonicalize().unwrap();
+        let mut accepted = Vec::new();
+        for name in [
+            "../outside",
+            "..\\outside",
+            "nl1/../../outside",
+            "nl1/.",
+            "/etc",
+            "C:\\Windows",
+            "c:outside",
+            "..",
+            ".",
+            "",
+            "nl1\0",
+            "missing",
+            "linked",
+        ] {
+            if !matches!(profile(&dir, name), Err(VpnError::UnknownServer(_))) {
+                accepted.push(name);
+            }
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(accepted.is_empty(), "not refused: {:?}", accepted);
+        assert_eq!(resolved, expected);
+    }
+
+    #[tokio::test]
+    async fn measures_how_long_a_handshake_takes() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let open = remote("127.0.0.1", port, Protocol::Tcp);
+        assert!(latency(&open, Duration::from_secs(5)).await.is_some());
+
+        // Nothing listens anymore, so the handshake is refused
+        drop(listener);
+        assert!(latency(&open, Duration::from_secs(5)).await.is_some());
+
+        let unknown = async {
+            Err::<std::vec::IntoIter<SocketAddr>, _>(std::io::Error::other("no such host"))
+        };
+        assert!(handshake(unknown, Duration::from_secs(5)).await.is_none());
+
+        let unanswered = std::future::pending::<std::io::Result<std::vec::IntoIter<SocketAddr>>>();
+        assert!(handshake(unanswered, Duration::from_millis(50))
+            .await
+            .is_none());
+    }
+}
@@ -0,0 +1,1119 @@
+use crate::adapter::{self, AdapterInfo, AdapterReport, NetworkAdapter};
+use crate::catalog::{self, Server};
+use crate::connection::{self, Connector, Tunnel, TunnelDetails};
+use crate::credentials::CredentialStore;
+use crate::diagnosis::{Diagnosis, FAILURE_EVENT};
+use crate::error::VpnError;
+use crate::events::Events;
+use crate::logbuffer::{LogBuffer, Severity};
+use crate::management::ManagementClient;
+use crate::paths::{Environment, PathResolver, Platform, SystemEnvironment};
+use crate::redact::Remembered;
+use crate::shutdown::{self, ShutdownOutcome, SHUTDOWN_EVENT};
+use crate::states::{ConnectionState, StateMachine};
+use crate::supervisor::{self, ReconnectEvent, ReconnectPolicy};
+use crate::traffic::{TrafficMonitor, TrafficStats};
+use crate::usage::{self, UsageLedger};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::process::Child;
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tracing::{error, info, warn, Instrument};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub state: ConnectionState,
+    pub server: Option<String>,
+    /// Unix timestamp in seconds of when the current tunnel came up
+    pub connected_at: Option<u64>,
+    pub uptime_secs: Option<u64>,
+    pub tunnel: Option<TunnelDetails>,
+    pub pid: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct Settings {
+    reconnect_policy: ReconnectPolicy,
+    shutdown_grace_period: Duration,
+}
+
+/// Everything that has to be reachable without waiting in the command queue
+struct Shared {
+    events: Events,
+    connector: Connector,
+    state: StateMachine,
+    traffic: TrafficMonitor,
+    logs: LogBuffer,
+    paths: PathResolver,
+    settings: Mutex<Settings>,
+    cancel: Notify,
+    attempt_in_progress: AtomicBool,
+    last_failure: Mutex<Option<Diagnosis>>,
+}
+
+type Reply<T> = oneshot::Sender<T>;
+
+enum Command {
+    Connect {
+        server_name: String,
+        username: String,
+        password: Option<String>,
+        reply: Reply<Result<String, VpnError>>,
+    },
+    Disconnect {
+        reply: Reply<Result<String, VpnError>>,
+    },
+    SwitchServer {
+        server_name: String,
+        username: String,
+        password: Option<String>,
+        reply: Reply<Result<String, VpnError>>,
+    },
+    Reconnect {
+        reply: Reply<Result<String, VpnError>>,
+    },
+    Status {
+        reply: Reply<ConnectionInfo>,
+    },
+    SetVerb {
+        verb: u8,
+        reply: Reply<Result<(), VpnError>>,
+    },
+    /// Sent by the watcher of the tunnel started in `session`
+    TunnelLost {
+        session: u64,
+        reason: String,
+    },
+    /// Sent by the backoff timer scheduled in `session`
+    RetryReconnect {
+        session: u64,
+        attempt: u32,
+    },
+}
+
+/// Handle to the task that owns the tunnel. Every tunnel operation goes through its
+/// queue, so operations run one at a time in the order they were requested.
+#[derive(Clone)]
+pub struct VpnClient {
+    commands: mpsc::UnboundedSender<Command>,
+    shared: Arc<Shared>,
+}
+
+/// What the client runs against: the real system, or fakes in tests
+pub struct Backend {
+    pub events: Events,
+    pub paths: PathResolver,
+    pub usage: UsageLedger,
+    pub connector: Connector,
+}
+
+impl Backend {
+    /// The real system, with directories from `env`
+    pub fn new(events: Events, env: impl Environment + 'static) -> Self {
+        let data_dir = env.app_data_dir();
+        if data_dir.is_none() {
+            warn!("No app data directory, usage is not persisted");
+        }
+        Backend {
+            events,
+            usage: UsageLedger::open(data_dir.as_deref()),
+            paths: PathResolver::new(env, Platform::current()),
+            connector: Connector::system(),
+        }
+    }
+
+    /// The real system, for front-ends without Tauri
+    pub fn system(events: Events) -> Self {
+        Self::new(events, SystemEnvironment)
+    }
+}
+
+impl VpnClient {
+    /// Starts the task that owns the tunnel. Has to be called from within a tokio runtime.
+    pub fn start(backend: Backend) -> Self {
+        let (commands, queue) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared {
+            state: StateMachine::new(backend.events.clone()),
+            traffic: TrafficMonitor::new(backend.events.clone(), backend.usage),
+            logs: LogBuffer::new(backend.events.clone()),
+            events: backend.events,
+            connector: backend.connector,
+            paths: backend.paths,
+            settings: Mutex::new(Settings {
+                reconnect_policy: ReconnectPolicy::default(),
+                shutdown_grace_period: shutdown::DEFAULT_GRACE_PERIOD,
+            }),
+            cancel: Notify::new(),
+            attempt_in_progress: AtomicBool::new(false),
+            last_failure: Mutex::new(None),
+        });
+
+        let actor = Actor {
+            shared: shared.clone(),
+            commands: commands.clone(),
+            tunnel: None,
+            target: None,
+            session: 0,
+            attempts: 0,
+        };
+        tokio::spawn(actor.run(queue));
+
+        // Usage is flushed regularly so a crash loses at most one interval of it
+        let flusher = shared.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(usage::FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = flusher.traffic.usage().flush() {
+                    warn!("{}", e);
+                }
+            }
+        });
+
+        VpnClient { commands, shared }
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(Reply<T>) -> Command) -> Result<T, VpnError> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .map_err(|_| VpnError::ManagerUnavailable)?;
+        response.await.map_err(|_| VpnError::ManagerUnavailable)
+    }
+
+    pub async fn connect(&self, server_name: String, username: String) -> Result<String, VpnError> {
+        self.request(|reply| Command::Connect {
+            server_name,
+            username,
+            password: None,
+            reply,
+        })
+        .await?
+    }
+
+    /// Connects with `password` instead of the saved one. Reconnects of this tunnel
+    /// use it too; it is never saved.
+    pub async fn connect_with_password(
+        &self,
+        server_name: String,
+        username: String,
+        password: String,
+    ) -> Result<String, VpnError> {
+        self.request(|reply| Command::Connect {
+            server_name,
+            username,
+            password: Some(password),
+            reply,
+        })
+        .await?
+    }
+
+    pub async fn disconnect(&self) -> Result<String, VpnError> {
+        // A connect in progress holds up the queue, so it is aborted directly
+        if self.cancel_connect() {
+            return Ok("Connection cancelled".to_string());
+        }
+        self.request(|reply| Command::Disconnect { reply }).await?
+    }
+
+    pub async fn switch_server(
+        &self,
+        server_name: String,
+        username: String,
+    ) -> Result<String, VpnError> {
+        self.request(|reply| Command::SwitchServer {
+            server_name,
+            username,
+            password: None,
+            reply,
+        })
+        .await?
+    }
+
+    /// Switches with `password` instead of the saved one, like `connect_with_password`
+    pub async fn switch_server_with_password(
+        &self,
+        server_name: String,
+        username: String,
+        password: String,
+    ) -> Result<String, VpnError> {
+        self.request(|reply| Command::SwitchServer {
+            server_name,
+            username,
+            password: Some(password),
+            reply,
+        })
+        .await?
+    }
+
+    pub async fn reconnect(&self) -> Result<String, VpnError> {
+        self.request(|reply| Command::Reconnect { reply }).await?
+    }
+
+    pub async fn status(&self) -> Result<ConnectionInfo, VpnError> {
+        self.request(|reply| Command::Status { reply }).await
+    }
+
+    /// Applies openvpn's `--verb` to the running tunnel; later tunnels start with it
+    pub async fn set_openvpn_verb(&self, verb: u8) -> Result<(), VpnError> {
+        self.request(|reply| Command::SetVerb { verb, reply })
+            .await?
+    }
+
+    /// Aborts the connect or reconnect attempt in progress.
+    /// Returns whether there was one to abort.
+    pub fn cancel_connect(&self) -> bool {
+        if self.shared.attempt_in_progress.load(Ordering::SeqCst) {
+            self.shared.cancel.notify_waiters();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Current state, answered immediately even while an operation is running
+    pub fn state(&self) -> ConnectionState {
+        self.shared.state.current()
+    }
+
+    pub fn traffic_stats(&self) -> TrafficStats {
+        self.shared.traffic.stats()
+    }
+
+    pub fn usage(&self) -> &UsageLedger {
+        self.shared.traffic.usage()
+    }
+
+    pub fn logs(&self) -> &LogBuffer {
+        &self.shared.logs
+    }
+
+    pub fn paths(&self) -> &PathResolver {
+        &self.shared.paths
+    }
+
+    /// The servers with a profile in the config directory
+    pub fn servers(&self) -> Result<Vec<Server>, VpnError> {
+        catalog::list(&self.paths().resolve()?.config_dir)
+    }
+
+    /// Where connects get passwords from
+    pub fn credentials(&self) -> Arc<dyn CredentialStore> {
+        self.shared.connector.credentials.clone()
+    }
+
+    /// The password saved for `username`, if there is one
+    pub fn password(&self, username: &str) -> Result<Option<String>, VpnError> {
+        match self.shared.connector.credentials.password(username) {
+            Ok(password) => Ok(Some(password)),
+            Err(VpnError::NoSavedPassword { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Saves the password connects as `username` use
+    pub fn save_password(&self, username: &str, password: &str) -> Result<(), VpnError> {
+        self.shared
+            .connector
+            .credentials
+            .set_password(username, password)
+    }
+
+    pub fn forget_password(&self, username: &str) -> Result<(), VpnError> {
+        self.shared.connector.credentials.delete_password(username)
+    }
+
+    /// The platform's network adapter, or the one tests replace it with
+    fn adapter(&self) -> Result<Box<dyn NetworkAdapter>, VpnError> {
+        let paths = self.shared.paths.resolve()?;
+        self.shared.connector.launcher.adapter(&paths)
+    }
+
+    pub async fn adapters(&self) -> Result<AdapterReport, VpnError> {
+        adapter::report(self.adapter()?).await
+    }
+
+    /// Creates an adapter called `name`, or `GekkoVPN`, ahead of the first connect
+    pub async fn create_adapter(&self, name: Option<String>) -> Result<AdapterInfo, VpnError> {
+        adapter::create(self.adapter()?, name).await
+    }
+
+    pub async fn remove_adapter(&self, name: String) -> Result<(), VpnError> {
+        adapter::remove(self.adapter()?, name).await
+    }
+
+    /// Diagnosis of the most recent failed connect or reconnect attempt
+    pub fn last_failure(&self) -> Option<Diagnosis> {
+        self.shared.last_failure.lock().unwrap().clone()
+    }
+
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        self.shared
+            .settings
+            .lock()
+            .unwrap()
+            .reconnect_policy
+            .clone()
+    }
+
+    pub fn set_reconnect_policy(&self, policy: ReconnectPolicy) -> Result<(), VpnError> {
+        if policy.multiplier < 1.0 {
+            return Err(VpnError::InvalidSetting(
+                "Backoff multiplier must be at least 1".to_string(),
+            ));
+        }
+        self.shared.settings.lock().unwrap().reconnect_policy = policy;
+        Ok(())
+    }
+
+    pub fn shutdown_grace_period(&self) -> Duration {
+        self.shared.settings.lock().unwrap().shutdown_grace_period
+    }
+
+    pub fn set_shutdown_grace_period(&self, grace_period: Duration) {
+        self.shared.settings.lock().unwrap().shutdown_grace_period = grace_period;
+    }
+}
+
+struct ActiveTunnel {
+    child: Child,
+    management: ManagementClient,
+    stop_watcher: watch::Sender<bool>,
+    details: TunnelDetails,
+    connected_at: SystemTime,
+    /// Monotonic twin of `connected_at`, so uptime survives clock changes
+    started: Instant,
+    /// Dropped, and so no longer masked, with the tunnel
+    _redacted: Remembered,
+}
+
+#[derive(Clone)]
+struct Target {
+    server_name: String,
+    username: String,
+    /// Sent with the connect, otherwise the saved one is read for every attempt
+    password: Option<String>,
+}
+
+struct Actor {
+    shared: Arc<Shared>,
+    commands: mpsc::UnboundedSender<Command>,
+    tunnel: Option<ActiveTunnel>,
+    /// Server and user the tunnel should be up for, kept while reconnecting
+    target: Option<Target>,
+    /// Bumped whenever a tunnel starts or stops, so messages from old watchers
+    /// and backoff timers are ignored
+    session: u64,
+    /// Numbers connect attempts in the logs
+    attempts: u64,
+}
+
+impl Actor {
+    async fn run(mut self, mut queue: mpsc::UnboundedReceiver<Command>) {
+        while let Some(command) = queue.recv().await {
+            match command {
+                Command::Connect {
+                    server_name,
+                    username,
+                    password,
+                    reply,
+                } => {
+                    let _ = reply.send(self.connect(server_name, username, password).await);
+                }
+                Command::Disconnect { reply } => {
+                    let _ = reply.send(self.disconnect().await);
+                }
+                Command::SwitchServer {
+                    server_name,
+                    username,
+                    password,
+                    reply,
+                } => {
+                    let _ = reply.send(self.switch_server(server_name, username, password).await);
+                }
+                Command::Reconnect { reply } => {
+                    let _ = reply.send(self.reconnect().await);
+                }
+                Command::Status { reply } => {
+                    let _ = reply.send(self.status());
+                }
+                Command::SetVerb { verb, reply } => {
+                    let _ = reply.send(self.set_verb(verb).await);
+                }
+                Command::TunnelLost { session, reason } => {
+                    if session == self.session {
+                        self.tunnel_lost(reason).await;
+                    }
+                }
+                Command::RetryReconnect { session, attempt } => {
+                    if session == self.session {
+                        let _ = self.reconnect_attempt(attempt).await;
+                    }
+                }
+            }
+        }
+    }
+
+    fn state(&self) -> &StateMachine {
+        &self.shared.state
+    }
+
+    fn transition(&self, next: ConnectionState) -> Result<(), VpnError> {
+        self.state()
+            .transition(next)
+            .map_err(VpnError::InvalidState)
+    }
+
+    fn settings(&self) -> Settings {
+        self.shared.settings.lock().unwrap().clone()
+    }
+
+    /// Refuses a name outside the catalog before the state or the tunnel changes.
+    /// Anything else that is wrong with the profile fails the attempt as before.
+    fn check_server(&self, server_name: &str) -> Result<(), VpnError> {
+        let Ok(paths) = self.shared.paths.resolve() else {
+            return Ok(());
+        };
+        match catalog::profile(&paths.config_dir, server_name) {
+            Err(e @ VpnError::UnknownServer(_)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs one connect attempt that `cancel_connect` can abort. Returns `None` when
+    /// cancelled; the half-started openvpn is killed when the attempt is dropped.
+    async fn attempt(&mut self, target: &Target) -> Option<Result<Tunnel, VpnError>> {
+        self.attempts += 1;
+        let span = tracing::info_span!("connect", id = self.attempts, server = %target.server_name);
+        let cancelled = self.shared.cancel.notified();
+        tokio::pin!(cancelled);
+        // Listening before the attempt shows, so a cancel right away is not lost
+        cancelled.as_mut().enable();
+        self.shared
+            .attempt_in_progress
+            .store(true, Ordering::SeqCst);
+
+        let establish = connection::establish(
+            &self.shared.connector,
+            self.state(),
+            &self.shared.logs,
+            &self.shared.paths,
+            &target.server_name,
+            &target.username,
+            target.password.as_deref(),
+        );
+        let result = tokio::select! {
+            result = establish.instrument(span.clone()) => Some(result),
+            _ = &mut cancelled => None,
+        };
+
+        self.shared
+            .attempt_in_progress
+            .store(false, Ordering::SeqCst);
+        if let Some(Err(e)) = &result {
+            span.in_scope(|| warn!(error = %e, "Connection failed"));
+            self.shared
+                .logs
+                .app(Severity::Error, format!("Connection failed: {}", e));
+            if let VpnError::ConnectionFailed(diagnosis) = e {
+                *self.shared.last_failure.lock().unwrap() = Some(diagnosis.clone());
+                let _ = self.shared.events.emit(FAILURE_EVENT, diagnosis);
+            }
+        }
+        result
+    }
+
+    async fn connect(
+        &mut self,
+        server_name: String,
+        username: String,
+        password: Option<String>,
+    ) -> Result<String, VpnError> {
+        self.check_server(&server_name)?;
+        // Only one attempt can leave the idle state, so this also guards against double connects
+        if self.tunnel.is_some()
+            || self
+                .state()
+                .transition(ConnectionState::Connecting)
+                .is_err()
+        {
+            return Err(VpnError::AlreadyConnected);
+        }
+
+        let target = Target {
+            server_name,
+            username,
+            password,
+        };
+        match self.attempt(&target).await {
+            Some(Ok(tunnel)) => {
+                let message = format!(
+                    "Connected to {} with user {}",
+                    target.server_name, target.username
+                );
+                // Reconnects keep counting, only a new connect starts a new session
+                self.shared.traffic.reset();
+                self.start(tunnel, target);
+                Ok(message)
+            }
+            Some(Err(e)) => {
+                let _ = self
+                    .state()
+                    .transition(ConnectionState::Failed(e.message()));
+                Err(e)
+            }
+            None => {
+                self.finish_cancelled();
+                Err(VpnError::Cancelled)
+            }
+        }
+    }
+
+    async fn disconnect(&mut self) -> Result<String, VpnError> {
+        if self.tunnel.is_none() {
+            self.session += 1;
+            self.target = None;
+            if self.state().current() != ConnectionState::Reconnecting {
+                return Ok("Not connected to VPN".to_string());
+            }
+            // Waiting for the next reconnect attempt, so there is no process to stop
+            self.transition(ConnectionState::Disconnecting)?;
+            self.transition(ConnectionState::Disconnected)?;
+            return Ok("Disconnected from VPN".to_string());
+        }
+
+        self.transition(ConnectionState::Disconnecting)?;
+        let outcome = match self.stop_tunnel().await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                // openvpn still runs, and the tunnel with it
+                let _ = self.state().transition(ConnectionState::Connected);
+                return Err(e);
+            }
+        };
+        self.target = None;
+        self.transition(ConnectionState::Disconnected)?;
+
+        match outcome {
+            Some(ShutdownOutcome::Forced { reason }) => Ok(format!(
+                "Disconnected from VPN ({}, the process was killed)",
+                reason
+            )),
+            _ => Ok("Disconnected from VPN".to_string()),
+        }
+    }
+
+    async fn switch_server(
+        &mut self,
+        server_name: String,
+        username: String,
+        password: Option<String>,
+    ) -> Result<String, VpnError> {
+        // A bad name must not take the running tunnel down
+        self.check_server(&server_name)?;
+        if self.tunnel.is_some() || self.state().current() == ConnectionState::Reconnecting {
+            self.disconnect().await?;
+        }
+        self.connect(server_name, username, password).await
+    }
+
+    async fn reconnect(&mut self) -> Result<String, VpnError> {
+        if self.target.is_none() {
+            return Err(VpnError::NotConnected);
+        }
+
+        self.stop_tunnel().await?;
+        // Also invalidates a backoff timer that may already be pending
+        self.session += 1;
+        let _ = self.state().transition(ConnectionState::Reconnecting);
+        self.reconnect_attempt(1).await
+    }
+
+    fn status(&self) -> ConnectionInfo {
+        let tunnel = self.tunnel.as_ref();
+        ConnectionInfo {
+            state: self.state().current(),
+            server: self.target.as_ref().map(|t| t.server_name.clone()),
+            connected_at: tunnel.and_then(|t| {
+                t.connected_at
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|d| d.as_secs())
+            }),
+            uptime_secs: tunnel.map(|t| t.started.elapsed().as_secs()),
+            tunnel: tunnel.map(|t| t.details.clone()),
+            pid: tunnel.and_then(|t| t.child.id()),
+        }
+    }
+
+    async fn set_verb(&self, verb: u8) -> Result<(), VpnError> {
+        match &self.tunnel {
+            Some(tunnel) => tunnel
+                .management
+                .set_verb(verb)
+                .await
+                .map_err(VpnError::Management),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes ownership of a freshly connected tunnel and starts watching it
+    fn start(&mut self, tunnel: Tunnel, target: Target) {
+        self.session += 1;
+        let session = self.session;
+        let stall_timeout = self.settings().reconnect_policy.stall_timeout();
+        let (stop_watcher, stopped) = watch::channel(false);
+
+        let shared = self.shared.clone();
+        let commands = self.commands.clone();
+        let events = tunnel.events;
+        tokio::spawn(async move {
+            let lost = supervisor::watch_tunnel(
+                &shared.state,
+                &shared.traffic,
+                events,
+                stopped,
+                stall_timeout,
+            )
+            .await;
+            if let Some(reason) = lost {
+                let _ = commands.send(Command::TunnelLost { session, reason });
+            }
+        });
+
+        self.shared.traffic.begin_tunnel(&target.server_name);
+        self.tunnel = Some(ActiveTunnel {
+            child: tunnel.child,
+            management: tunnel.client,
+            stop_watcher,
+            details: tunnel.details,
+            connected_at: SystemTime::now(),
+            started: Instant::now(),
+            _redacted: tunnel.redacted,
+        });
+        self.target = Some(target);
+    }
+
+    /// Stops the current tunnel, if any, giving openvpn the grace period to exit cleanly.
+    /// An openvpn that cannot be stopped keeps its tunnel, which is still running.
+    async fn stop_tunnel(&mut self) -> Result<Option<ShutdownOutcome>, VpnError> {
+        let grace_period = self.settings().shutdown_grace_period;
+        let Some(tunnel) = self.tunnel.as_mut() else {
+            return Ok(None);
+        };
+        // The watcher keeps running meanwhile; what it reports once openvpn exits
+        // belongs to this session and is ignored after it
+        let outcome = shutdown::shutdown(&mut tunnel.child, Some(&tunnel.management), grace_period)
+            .await
+            .map_err(|e| VpnError::Process(format!("{}. OpenVPN is still running.", e)))?;
+        let Some(tunnel) = self.tunnel.take() else {
+            return Ok(None);
+        };
+        self.session += 1;
+        let _ = tunnel.stop_watcher.send(true);
+        self.shared.traffic.end_tunnel();
+        info!(outcome = ?outcome, "OpenVPN shut down");
+        self.shared
+            .logs
+            .app(Severity::Info, format!("OpenVPN shutdown: {:?}", outcome));
+        let _ = self.shared.events.emit(SHUTDOWN_EVENT, &outcome);
+        Ok(Some(outcome))
+    }
+
+    fn finish_cancelled(&mut self) {
+        self.target = None;
+        let _ = self.state().transition(ConnectionState::Disconnecting);
+        let _ = self.state().transition(ConnectionState::Disconnected);
+    }
+
+    async fn tunnel_lost(&mut self, reason: String) {
+        supervisor::report(
+            &self.shared.events,
+            &self.shared.logs,
+            ReconnectEvent::TunnelLost {
+                reason: reason.clone(),
+            },
+        );
+        // A stalled openvpn is still running and gets the chance to restore routes
+        if let Err(e) = self.stop_tunnel().await {
+            error!(error = %e, "Failed to stop OpenVPN");
+            self.shared
+                .logs
+                .app(Severity::Error, format!("Failed to stop OpenVPN: {}", e));
+            // A second openvpn must not start next to the one that is still running
+            return;
+        }
+
+        let policy = self.settings().reconnect_policy;
+        if !policy.enabled || policy.max_attempts == 0 {
+            self.target = None;
+            let _ = self.state().transition(ConnectionState::Failed(reason));
+            return;
+        }
+        let _ = self.state().transition(ConnectionState::Reconnecting);
+        self.schedule_reconnect(1);
+    }
+
+    fn schedule_reconnect(&self, attempt: u32) {
+        let policy = self.settings().reconnect_policy;
+        let delay = policy.delay_for(attempt);
+        supervisor::report(
+            &self.shared.events,
+            &self.shared.logs,
+            ReconnectEvent::Scheduled {
+                attempt,
+                max_attempts: policy.max_attempts,
+                delay_ms: delay.as_millis() as u64,
+            },
+        );
+
+        let commands = self.commands.clone();
+        let session = self.session;
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _ = commands.send(Command::RetryReconnect { session, attempt });
+        });
+    }
+
+    /// Runs reconnect attempt `attempt` to the current target and schedules the
+    /// next one with backoff if it fails
+    async fn reconnect_attempt(&mut self, attempt: u32) -> Result<String, VpnError> {
+        let Some(target) = self.target.clone() else {
+            return Err(VpnError::NotConnected);
+        };
+        let policy = self.settings().reconnect_policy;
+        supervisor::report(
+            &self.shared.events,
+            &self.shared.logs,
+            ReconnectEvent::Attempting {
+                attempt,
+                max_attempts: policy.max_attempts,
+            },
+        );
+
+        match self.attempt(&target).await {
+            Some(Ok(tunnel)) => {
+                supervisor::report(
+                    &self.shared.events,
+                    &self.shared.logs,
+                    ReconnectEvent::Reconnected { attempt },
+                );
+                let message = format!("Reconnected to {}", target.server_name);
+                self.start(tunnel, target);
+                Ok(message)
+            }
+            Some(Err(e)) => {
+                supervisor::report(
+                    &self.shared.events,
+                    &self.shared.logs,
+                    ReconnectEvent::AttemptFailed {
+                        attempt,
+                        error: e.message(),
+                    },
+                );
+                if attempt >= policy.max_attempts {
+                    supervisor::report(
+                        &self.shared.events,
+                        &self.shared.logs,
+                        ReconnectEvent::GaveUp { attempts: attempt },
+                    );
+                    self.target = None;
+                    let _ = self.state().transition(ConnectionState::Failed(format!(
+                        "Could not reconnect after {} attempts: {}",
+                        attempt,
+                        e.message()
+                    )));
+                } else {
+                    let _ = self.state().transition(ConnectionState::Reconnecting);
+                    self.schedule_reconnect(attempt + 1);
+                }
+                Err(e)
+            }
+            None => {
+                self.finish_cancelled();
+                Err(VpnError::Cancelled)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Timeouts;
+    use crate::diagnosis::FailureCause;
+    use crate::redact;
+    use crate::testing::{Harness, CONNECTS, SERVER};
+
+    fn diagnosis(result: Result<String, VpnError>) -> Diagnosis {
+        match result {
+            Err(VpnError::ConnectionFailed(diagnosis)) => diagnosis,
+            other => panic!("Expected a failed connection, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn connects_and_disconnects() {
+        let harness = Harness::new("connect", CONNECTS, Timeouts::default());
+        assert_eq!(
+            harness.connect().await.unwrap(),
+            "Connected to fake with user alice"
+        );
+        let status = harness.client.status().await.unwrap();
+        assert_eq!(status.state, ConnectionState::Connected);
+        assert!(status.pid.is_some());
+        let tunnel = status.tunnel.unwrap();
+        assert_eq!(tunnel.local_ip.as_deref(), Some("10.8.0.2"));
+        assert_eq!(tunnel.remote.as_deref(), Some("185.107.56.21:1194"));
+
+        assert_eq!(
+            harness.client.disconnect().await.unwrap(),
+            "Disconnected from VPN"
+        );
+
+        assert_eq!(harness.client.state(), ConnectionState::Disconnected);
+        assert_eq!(
+            harness.states(),
+            [
+                "connecting",
+                "resolving",
+                "connecting",
+                "authenticating",
+                "connected",
+                "disconnecting",
+                "disconnected"
+            ]
+        );
+        let shutdowns = harness.events.named(SHUTDOWN_EVENT);
+        assert_eq!(shutdowns.len(), 1);
+        assert_eq!(shutdowns[0]["outcome"], "graceful");
+    }
+
+    #[tokio::test]
+    async fn refuses_servers_outside_the_catalog() {
+        let harness = Harness::new("unknown", CONNECTS, Timeouts::default());
+        for name in ["../fake", "missing"] {
+            assert!(matches!(
+                harness
+                    .client
+                    .connect(name.to_string(), "alice".to_string())
+                    .await,
+                Err(VpnError::UnknownServer(_))
+            ));
+        }
+        assert!(harness.states().is_empty());
+
+        harness.connect().await.unwrap();
+        assert!(matches!(
+            harness
+                .client
+                .switch_server("/etc".to_string(), "alice".to_string())
+                .await,
+            Err(VpnError::UnknownServer(_))
+        ));
+        assert_eq!(harness.client.state(), ConnectionState::Connected);
+        harness.client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_an_openvpn_that_users_can_change() {
+        let harness = Harness::writable_by_users("writable", CONNECTS, Timeouts::default());
+        let error = harness.connect().await.unwrap_err();
+        assert!(matches!(error, VpnError::InvalidSetting(_)), "{:?}", error);
+        assert!(!harness.states().contains(&"connected".to_string()));
+        assert!(harness.client.status().await.unwrap().pid.is_none());
+    }
+
+    #[tokio::test]
+    async fn only_one_connect_wins() {
+        let harness = Harness::new("double", CONNECTS, Timeouts::default());
+        let (first, second) = tokio::join!(harness.connect(), harness.connect());
+        assert!(first.is_ok(), "{:?}", first);
+        assert!(matches!(second, Err(VpnError::AlreadyConnected)));
+        assert!(matches!(
+            harness.connect().await,
+            Err(VpnError::AlreadyConnected)
+        ));
+        harness.client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn answers_a_second_credentials_prompt() {
+        let scenario = "
+            management
+            auth
+            auth
+            state CONNECTED,SUCCESS,10.8.0.2,185.107.56.21,1194,,
+            serve
+        ";
+        let harness = Harness::new("auth-retry", scenario, Timeouts::default());
+        harness.connect().await.unwrap();
+        harness.client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_two_credentials_prompts() {
+        let scenario = "
+            management
+            auth
+            auth
+            auth
+            serve
+        ";
+        let harness = Harness::new("auth-loop", scenario, Timeouts::default());
+        let result = harness.connect().await;
+        assert_eq!(diagnosis(result).cause, FailureCause::AuthenticationFailed);
+        assert!(matches!(harness.client.state(), ConnectionState::Failed(_)));
+        assert_eq!(harness.events.named(FAILURE_EVENT).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_credentials() {
+        let scenario = "
+            management
+            auth
+            stdout AUTH: Received control message: AUTH_FAILED
+            auth-failed
+            hang
+        ";
+        let harness = Harness::new("auth-failed", scenario, Timeouts::default());
+        let result = harness.connect().await;
+        assert_eq!(diagnosis(result).cause, FailureCause::AuthenticationFailed);
+        assert!(harness.client.last_failure().is_some());
+    }
+
+    #[tokio::test]
+    async fn times_out_when_openvpn_hangs() {
+        let timeouts = Timeouts {
+            management: Duration::from_millis(500),
+            connect: Duration::from_millis(500),
+        };
+
+        let hangs_before_management = Harness::new("no-management", "hang", timeouts);
+        let result = hangs_before_management.connect().await;
+        assert_eq!(
+            diagnosis(result).error,
+            "OpenVPN did not connect to the management interface"
+        );
+
+        let scenario = "
+            management
+            state WAIT,,,,
+            hang
+        ";
+        let hangs_while_connecting = Harness::new("hang", scenario, timeouts);
+        let result = hangs_while_connecting.connect().await;
+        assert_eq!(
+            diagnosis(result).error,
+            "Connection timed out waiting for authentication"
+        );
+    }
+
+    #[tokio::test]
+    async fn cancels_a_connect_in_progress() {
+        let scenario = "
+            management
+            state WAIT,,,,
+            hang
+        ";
+        let harness = Harness::new("cancel", scenario, Timeouts::default());
+        let disconnect = async {
+            harness
+                .wait_for_state(|state| *state == ConnectionState::Connecting)
+                .await;
+            harness.client.disconnect().await
+        };
+        let (connected, disconnected) = tokio::join!(harness.connect(), disconnect);
+        assert!(matches!(connected, Err(VpnError::Cancelled)));
+        assert_eq!(disconnected.unwrap(), "Connection cancelled");
+        assert_eq!(harness.client.state(), ConnectionState::Disconnected);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cancels_right_after_connecting() {
+        let harness = Harness::new("cancel-early", "hang", Timeouts::default());
+        let cancel = async {
+            while !harness.client.cancel_connect() {
+                std::hint::spin_loop();
+            }
+        };
+        let (connected, _) = tokio::join!(harness.connect(), cancel);
+        assert!(matches!(connected, Err(VpnError::Cancelled)));
+        assert_eq!(harness.client.state(), ConnectionState::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn masks_the_password_only_while_its_tunnel_runs() {
+        let harness = Harness::new("forget", CONNECTS, Timeouts::default());
+        harness
+            .client
+            .connect_with_password(
+                SERVER.to_string(),
+                "alice".to_string(),
+                "only for this tunnel".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            redact::redact("sent only for this tunnel"),
+            "sent [PASSWORD]"
+        );
+
+        harness.client.disconnect().await.unwrap();
+        assert_eq!(
+            redact::redact("sent only for this tunnel"),
+            "sent only for this tunnel"
+        );
+    }
+
+    #[tokio::test]
+    async fn kills_an_openvpn_that_ignores_the_exit_request() {
+        let scenario = "
+            management
+            auth
+            state CONNECTED,SUCCESS,10.8.0.2,185.107.56.21,1194,,
+            hang
+        ";
+        let harness = Harness::new("stuck", scenario, Timeouts::default());
+        harness
+            .client
+            .set_shutdown_grace_period(Duration::from_millis(300));
+        harness.connect().await.unwrap();
+        let message = harness.client.disconnect().await.unwrap();
+        assert!(message.contains("the process was killed"), "{}", message);
+        assert_eq!(harness.events.named(SHUTDOWN_EVENT)[0]["outcome"], "forced");
+    }
+
+    #[tokio::test]
+    async fn fails_when_openvpn_crashes_mid_session() {
+        let scenario = "
+            management
+            auth
+            state CONNECTED,SUCCESS,10.8.0.2,185.107.56.21,1194,,
+            sleep 200
+            exit 1
+        ";
+        let harness = Harness::new("crash", scenario, Timeouts::default());
+        harness
+            .client
+            .set_reconnect_policy(ReconnectPolicy {
+                enabled: false,
+                ..ReconnectPolicy::default()
+            })
+            .unwrap();
+        harness.connect().await.unwrap();
+        harness
+            .wait_for_state(|state| matches!(state, ConnectionState::Failed(_)))
+            .await;
+        assert_eq!(
+            harness.client.state(),
+            ConnectionState::Failed("OpenVPN exited unexpectedly".to_string())
+        );
+        assert_eq!(
+            harness.events.named(SHUTDOWN_EVENT)[0]["outcome"],
+            "already_exited"
+        );
+    }
+}
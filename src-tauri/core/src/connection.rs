@@ -0,0 +1,571 @@
+use crate::adapter::{self, NetworkAdapter};
+use crate::catalog;
+use crate::credentials::{CredentialStore, Keyring};
+use crate::diagnosis::{self, FailureCause};
+use crate::error::VpnError;
+#[cfg(target_os = "linux")]
+use crate::linux;
+use crate::logbuffer::{LogBuffer, Severity};
+use crate::logging;
+use crate::logparser::PushReply;
+use crate::logparser::{self, LogLine};
+use crate::management::{
+    ManagementClient, ManagementEvent, ManagementListener, OpenVpnState, PasswordRequest,
+    StateChange,
+};
+use crate::paths::{AppPaths, PathResolver};
+use crate::redact::{Remembered, Secret};
+#[cfg(target_os = "linux")]
+use crate::runner::SystemRunner;
+use crate::states::{ConnectionState, StateMachine};
+use crate::supervisor::BYTECOUNT_INTERVAL_SECS;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{debug, info, warn, Instrument};
+
+/// An openvpn process that reached CONNECTED, together with its management connection
+pub struct Tunnel {
+    pub child: Child,
+    pub client: ManagementClient,
+    pub events: UnboundedReceiver<ManagementEvent>,
+    pub details: TunnelDetails,
+    /// The credentials, masked in the log until the tunnel is gone
+    pub redacted: Remembered,
+}
+
+/// Addresses and settings of an established tunnel, mostly pushed by the server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TunnelDetails {
+    pub local_ip: Option<String>,
+    pub local_ipv6: Option<String>,
+    /// Server address and port the tunnel runs to
+    pub remote: Option<String>,
+    pub gateway: Option<String>,
+    pub dns_servers: Vec<String>,
+    pub cipher: Option<String>,
+}
+
+impl TunnelDetails {
+    fn apply_push_reply(&mut self, reply: PushReply) {
+        self.local_ip = reply.local_ip.or(self.local_ip.take());
+        self.local_ipv6 = reply.local_ipv6.or(self.local_ipv6.take());
+        self.gateway = reply.gateway.or(self.gateway.take());
+        self.dns_servers = reply.dns_servers;
+        // The data channel line that follows has the final say on the cipher
+        if self.cipher.is_none() {
+            self.cipher = reply.cipher;
+        }
+    }
+
+    fn apply_connected(&mut self, change: &StateChange) {
+        if self.local_ip.is_none() {
+            self.local_ip = change.local_ip.clone();
+        }
+        if self.local_ipv6.is_none() {
+            self.local_ipv6 = change.local_ipv6.clone();
+        }
+        self.remote = match (&change.remote_ip, change.remote_port) {
+            (Some(ip), Some(port)) => Some(format!("{}:{}", ip, port)),
+            (ip, _) => ip.clone(),
+        };
+    }
+}
+
+#[derive(Default)]
+struct Recorded {
+    failures: Vec<LogLine>,
+    details: TunnelDetails,
+}
+
+/// What openvpn logged during one attempt: the lines that can explain a failure,
+/// and the tunnel details if it succeeds
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Recorded>>);
+
+impl Output {
+    fn record(&self, line: &str) {
+        let line = logparser::parse_line(line);
+        let mut recorded = self.0.lock().unwrap();
+        match line {
+            LogLine::PushReply(reply) => recorded.details.apply_push_reply(reply),
+            LogLine::CipherNegotiated(cipher) => recorded.details.cipher = Some(cipher),
+            line if line.is_failure()
+                || matches!(
+                    line,
+                    LogLine::LinkRemote { .. } | LogLine::InitialPacket { .. }
+                ) =>
+            {
+                recorded.failures.push(line)
+            }
+            _ => {}
+        }
+    }
+
+    fn connected(&self, change: &StateChange) {
+        self.0.lock().unwrap().details.apply_connected(change);
+    }
+
+    fn lines(&self) -> Vec<LogLine> {
+        self.0.lock().unwrap().failures.clone()
+    }
+
+    fn details(&self) -> TunnelDetails {
+        self.0.lock().unwrap().details.clone()
+    }
+}
+
+/// How long a connect attempt waits for openvpn
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// For openvpn to connect to the management interface
+    pub management: Duration,
+    /// For the tunnel to come up once openvpn is connected
+    pub connect: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            management: Duration::from_secs(10),
+            connect: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Sets up the system for openvpn and builds the command that starts it. Tests
+/// replace it to run a scripted fake openvpn without an adapter or admin rights.
+pub trait Launcher: Send + Sync {
+    fn adapter(&self, paths: &AppPaths) -> Result<Box<dyn NetworkAdapter>, VpnError>;
+
+    /// openvpn, started with the rights it needs to create the tunnel. May block.
+    fn command(&self, openvpn: &Path) -> Result<Command, VpnError>;
+
+    /// openvpn arguments that differ between platforms
+    fn platform_args(&self) -> Vec<String>;
+
+    /// Whether the started process `pid` still waits for the user to authenticate,
+    /// as pkexec does before it becomes openvpn
+    fn authenticating(&self, _pid: u32) -> bool {
+        false
+    }
+}
+
+/// The platform's adapter and a real openvpn
+pub struct SystemLauncher;
+
+impl Launcher for SystemLauncher {
+    fn adapter(&self, paths: &AppPaths) -> Result<Box<dyn NetworkAdapter>, VpnError> {
+        adapter::for_platform(paths)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn command(&self, openvpn: &Path) -> Result<Command, VpnError> {
+        let privilege = linux::privilege(&SystemRunner, openvpn)?;
+        Ok(linux::command(openvpn, &privilege))
+    }
+
+    /// On Windows the rights come from the TAP driver and the app running as administrator
+    #[cfg(not(target_os = "linux"))]
+    fn command(&self, openvpn: &Path) -> Result<Command, VpnError> {
+        Ok(Command::new(openvpn))
+    }
+
+    fn platform_args(&self) -> Vec<String> {
+        #[cfg(target_os = "linux")]
+        return linux::dns_args();
+        #[cfg(not(target_os = "linux"))]
+        Vec::new()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn authenticating(&self, pid: u32) -> bool {
+        linux::authenticating(pid)
+    }
+}
+
+/// How often a launch that asks for a password is checked on
+const AUTHENTICATION_POLL: Duration = Duration::from_millis(200);
+
+/// Waits while the user authenticates for openvpn, however long that takes, and
+/// fails when they refuse or dismiss the prompt
+async fn wait_for_authentication(launcher: &dyn Launcher, child: &mut Child) -> Result<(), String> {
+    let Some(pid) = child.id() else {
+        return Ok(());
+    };
+    while launcher.authenticating(pid) {
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!(
+                "OpenVPN was not started because authentication failed ({})",
+                status
+            ));
+        }
+        tokio::time::sleep(AUTHENTICATION_POLL).await;
+    }
+    Ok(())
+}
+
+/// What connect attempts run against: where passwords come from, how openvpn is
+/// started and how long it gets
+#[derive(Clone)]
+pub struct Connector {
+    pub launcher: Arc<dyn Launcher>,
+    pub credentials: Arc<dyn CredentialStore>,
+    pub timeouts: Timeouts,
+}
+
+impl Connector {
+    /// The keyring and a real openvpn
+    pub fn system() -> Self {
+        Connector {
+            launcher: Arc::new(SystemLauncher),
+            credentials: Arc::new(Keyring),
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+/// Starts openvpn for `server_name` and waits until the tunnel is up.
+/// Once openvpn runs, a failure is diagnosed from what it logged.
+pub async fn establish(
+    connector: &Connector,
+    state: &StateMachine,
+    logs: &LogBuffer,
+    paths: &PathResolver,
+    server_name: &str,
+    username: &str,
+    password: Option<&str>,
+) -> Result<Tunnel, VpnError> {
+    let mut username = username.to_string();
+
+    // Get application paths
+    let paths = paths.resolve()?;
+
+    // Make sure there is an adapter for openvpn; tapctl blocks, so keep it off the async runtime
+    let launcher = connector.launcher.clone();
+    let adapter = launcher.adapter(&paths)?;
+    let adapter_args = tokio::task::spawn_blocking(move || {
+        let info = adapter.ensure_exists()?;
+        debug!(adapter = %info.name, "Using network adapter");
+        Ok::<_, VpnError>(adapter.openvpn_args(&info))
+    })
+    .await
+    .map_err(|e| VpnError::Adapter(format!("Adapter setup failed: {}", e)))??;
+
+    // The password sent with the connect, or else the one saved for the username
+    let password = match password {
+        Some(password) => password.to_string(),
+        None => connector.credentials.password(&username)?,
+    };
+
+    // Keep them out of every log for as long as the tunnel runs
+    let mut redacted = Remembered::default();
+    redacted.remember(Secret::Username, &username);
+    redacted.remember(Secret::Password, &password);
+    username.push_str("@GekkoVPN");
+    redacted.remember(Secret::Username, &username);
+
+    debug!(username = %username, "Password retrieved from keyring");
+
+    // Setup OpenVPN paths
+    let openvpn_path = paths.openvpn_binary;
+    let config_path = catalog::profile(&paths.config_dir, server_name)?;
+
+    debug!(binary = ?openvpn_path, config = ?config_path, "OpenVPN paths");
+
+    // Validate paths
+    if !openvpn_path.exists() {
+        return Err(VpnError::OpenVpnNotFound(openvpn_path));
+    }
+
+    // openvpn connects back to this listener and is driven through it
+    let management = ManagementListener::bind()
+        .await
+        .map_err(VpnError::Management)?;
+
+    // Start OpenVPN process; finding the rights to start it with may run getcap
+    let command_path = openvpn_path.clone();
+    let mut command = tokio::task::spawn_blocking(move || launcher.command(&command_path))
+        .await
+        .map_err(|e| VpnError::Process(format!("Failed to prepare OpenVPN: {}", e)))??;
+    let mut child = command
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--auth-nocache")
+        .arg("--auth-retry")
+        .arg("none")
+        .arg("--connect-retry")
+        .arg("1")
+        .arg("--data-ciphers")
+        .arg("AES-256-GCM:AES-128-GCM:AES-128-CBC")
+        .arg("--cipher")
+        .arg("AES-128-CBC")
+        .arg("--verb")
+        .arg(logging::verbosity().openvpn_verb().to_string())
+        .args(management.openvpn_args())
+        .args(adapter_args)
+        .args(connector.launcher.platform_args())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| VpnError::Process(format!("Failed to start OpenVPN: {}", e)))?;
+
+    logs.app(
+        Severity::Info,
+        format!(
+            "Starting OpenVPN for {} with user {}",
+            server_name, username
+        ),
+    );
+
+    // Echo stdout/stderr to the console and the log buffer; state is tracked over the
+    // management interface. Output is recorded from here only, since openvpn can fail
+    // before the management connection and logs every line here either way.
+    let output = Output::default();
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| VpnError::Process("Failed to get stdout".to_string()))?;
+    let stdout_output = output.clone();
+    let stdout_logs = logs.clone();
+    tokio::spawn(
+        async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                info!(target: "openvpn", "{}", line);
+                stdout_logs.openvpn(&line);
+                stdout_output.record(&line);
+            }
+        }
+        .in_current_span(),
+    );
+
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| VpnError::Process("Failed to get stderr".to_string()))?;
+    let stderr_output = output.clone();
+    let stderr_logs = logs.clone();
+    tokio::spawn(
+        async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                warn!(target: "openvpn", "{}", line);
+                stderr_logs.openvpn(&line);
+                stderr_output.record(&line);
+            }
+        }
+        .in_current_span(),
+    );
+
+    // pkexec's password prompt does not count against the management timeout
+    if let Err(e) = wait_for_authentication(connector.launcher.as_ref(), &mut child).await {
+        return Err(diagnosis::diagnose(&e, None, &output.lines()).into());
+    }
+    let accepted = management
+        .accept(connector.timeouts.management, child.id())
+        .await;
+    let (client, mut events) = match accepted {
+        Ok(connection) => connection,
+        Err(e) => {
+            let _ = child.kill().await;
+            return Err(diagnosis::diagnose(&e, None, &output.lines()).into());
+        }
+    };
+
+    // Handle connection with timeout
+    let result = match tokio::time::timeout(
+        connector.timeouts.connect,
+        wait_for_connection(state, &client, &mut events, &username, &password, &output),
+    )
+    .await
+    {
+        Ok(result) => result,
+        Err(_) => Err(WaitError::from(
+            "Connection timed out waiting for authentication".to_string(),
+        )),
+    };
+
+    if let Err(e) = result {
+        let _ = child.kill().await;
+        return Err(diagnosis::diagnose(&e.message, e.cause, &output.lines()).into());
+    }
+
+    info!("VPN connection established successfully");
+    logs.app(Severity::Info, "VPN connection established successfully");
+    Ok(Tunnel {
+        child,
+        client,
+        events,
+        details: output.details(),
+        redacted,
+    })
+}
+
+/// Why waiting for CONNECTED failed. Rejected credentials are only reported over
+/// the management interface, so that cause is known without openvpn's log.
+struct WaitError {
+    cause: Option<FailureCause>,
+    message: String,
+}
+
+impl WaitError {
+    fn rejected_credentials() -> Self {
+        WaitError {
+            cause: Some(FailureCause::AuthenticationFailed),
+            message: "The server rejected the credentials".to_string(),
+        }
+    }
+}
+
+impl From<String> for WaitError {
+    fn from(message: String) -> Self {
+        WaitError {
+            cause: None,
+            message,
+        }
+    }
+}
+
+/// Releases the management hold, answers credential requests and waits for CONNECTED
+async fn wait_for_connection(
+    state: &StateMachine,
+    client: &ManagementClient,
+    events: &mut UnboundedReceiver<ManagementEvent>,
+    username: &str,
+    password: &str,
+    output: &Output,
+) -> Result<(), WaitError> {
+    const MAX_AUTH_ATTEMPTS: i32 = 2;
+    let mut auth_attempts = 0;
+
+    client.enable_notifications().await?;
+    client.enable_bytecount(BYTECOUNT_INTERVAL_SECS).await?;
+    client.hold_release().await?;
+
+    while let Some(event) = events.recv().await {
+        match event {
+            ManagementEvent::Password(PasswordRequest::NeedUsernamePassword { realm }) => {
+                if auth_attempts >= MAX_AUTH_ATTEMPTS {
+                    return Err(WaitError::rejected_credentials());
+                }
+                debug!(realm = %realm, "Credentials requested");
+                let _ = state.transition(ConnectionState::Authenticating);
+                client.send_credentials(&realm, username, password).await?;
+                auth_attempts += 1;
+            }
+            ManagementEvent::Password(PasswordRequest::VerificationFailed { .. }) => {
+                warn!("Authentication failed");
+                return Err(WaitError::rejected_credentials());
+            }
+            ManagementEvent::State(change) => {
+                debug!(state = ?change.state, description = %change.description, "OpenVPN state");
+                if let Some(next) = ConnectionState::from_openvpn(&change.state) {
+                    let _ = state.transition(next);
+                }
+                match change.state {
+                    OpenVpnState::Connected => {
+                        output.connected(&change);
+                        return Ok(());
+                    }
+                    OpenVpnState::Exiting => {
+                        return Err(format!("OpenVPN exited: {}", change.description).into())
+                    }
+                    _ => {}
+                }
+            }
+            ManagementEvent::Fatal(message) => {
+                return Err(format!("OpenVPN reported a fatal error: {}", message).into());
+            }
+            _ => {}
+        }
+    }
+
+    Err("OpenVPN closed the management interface".to_string().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Authenticates until `until`, like pkexec asking for a password
+    struct Prompting {
+        until: std::time::Instant,
+    }
+
+    impl Launcher for Prompting {
+        fn adapter(&self, _: &AppPaths) -> Result<Box<dyn NetworkAdapter>, VpnError> {
+            unreachable!()
+        }
+
+        fn command(&self, _: &Path) -> Result<Command, VpnError> {
+            unreachable!()
+        }
+
+        fn platform_args(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn authenticating(&self, _pid: u32) -> bool {
+            std::time::Instant::now() < self.until
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn waits_for_the_user_to_authenticate() {
+        let started = std::time::Instant::now();
+        let launcher = Prompting {
+            until: started + Duration::from_millis(500),
+        };
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        wait_for_authentication(&launcher, &mut child)
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        child.kill().await.unwrap();
+
+        // The prompt was dismissed and pkexec exited
+        let mut child = Command::new("false").spawn().unwrap();
+        let launcher = Prompting {
+            until: std::time::Instant::now() + Duration::from_secs(30),
+        };
+        let error = wait_for_authentication(&launcher, &mut child)
+            .await
+            .unwrap_err();
+        assert!(error.contains("authentication failed"), "{}", error);
+    }
+
+    #[test]
+    fn collects_tunnel_details_from_the_log() {
+        let output = Output::default();
+        for line in include_str!("../tests/fixtures/openvpn/connected_linux.log").lines() {
+            output.record(line);
+        }
+        output.connected(&StateChange {
+            timestamp: 1715677922,
+            state: OpenVpnState::Connected,
+            description: "SUCCESS".to_string(),
+            local_ip: Some("10.8.0.2".to_string()),
+            remote_ip: Some("185.107.56.21".to_string()),
+            remote_port: Some(1194),
+            local_ipv6: Some("fd00:8::1000".to_string()),
+        });
+
+        let details = output.details();
+        assert_eq!(details.local_ip.as_deref(), Some("10.8.0.2"));
+        assert_eq!(details.local_ipv6.as_deref(), Some("fd00:8::1000/64"));
+        assert_eq!(details.remote.as_deref(), Some("185.107.56.21:1194"));
+        assert_eq!(details.gateway.as_deref(), Some("10.8.0.1"));
+        assert_eq!(details.dns_servers, vec!["10.8.0.1", "1.1.1.1"]);
+        assert_eq!(details.cipher.as_deref(), Some("AES-256-GCM"));
+        assert!(output.lines().iter().all(|line| !line.is_failure()));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::error::VpnError;
+use keyring::Entry;
+
+const SERVICE_NAME: &str = "GekkoVPN";
+
+/// Where passwords are saved, per username
+pub trait CredentialStore: Send + Sync {
+    /// Fails with `NoSavedPassword` when there is none for `username`
+    fn password(&self, username: &str) -> Result<String, VpnError>;
+    fn set_password(&self, username: &str, password: &str) -> Result<(), VpnError>;
+    /// Succeeds when there was no password to delete
+    fn delete_password(&self, username: &str) -> Result<(), VpnError>;
+}
+
+/// The system keyring, with one entry per username under the `GekkoVPN` service
+pub struct Keyring;
+
+impl Keyring {
+    fn entry(username: &str) -> Result<Entry, VpnError> {
+        Entry::new(SERVICE_NAME, username)
+            .map_err(|e| VpnError::Keyring(format!("Failed to access the keyring: {}", e)))
+    }
+}
+
+impl CredentialStore for Keyring {
+    fn password(&self, username: &str) -> Result<String, VpnError> {
+        Self::entry(username)?.get_password().map_err(|e| match e {
+            keyring::Error::NoEntry => VpnError::NoSavedPassword {
+                username: username.to_string(),
+            },
+            e => VpnError::Keyring(format!("Failed to read the password: {}", e)),
+        })
+    }
+
+    fn set_password(&self, username: &str, password: &str) -> Result<(), VpnError> {
+        Self::entry(username)?
+            .set_password(password)
+            .map_err(|e| VpnError::Keyring(format!("Failed to save the password: {}", e)))
+    }
+
+    fn delete_password(&self, username: &str) -> Result<(), VpnError> {
+        match Self::entry(username)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(VpnError::Keyring(format!(
+                "Failed to delete the password: {}",
+                e
+            ))),
+        }
+    }
+}
+
+/// Passwords kept in memory, for the daemon, which gets them with each connect,
+/// and for tests
+#[derive(Default)]
+pub struct MemoryStore(std::sync::Mutex<std::collections::HashMap<String, String>>);
+
+impl MemoryStore {
+    pub fn with(username: &str, password: &str) -> Self {
+        let store = MemoryStore::default();
+        store.set_password(username, password).unwrap();
+        store
+    }
+}
+
+impl CredentialStore for MemoryStore {
+    fn password(&self, username: &str) -> Result<String, VpnError> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(username)
+            .cloned()
+            .ok_or_else(|| VpnError::NoSavedPassword {
+                username: username.to_string(),
+            })
+    }
+
+    fn set_password(&self, username: &str, password: &str) -> Result<(), VpnError> {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(username.to_string(), password.to_string());
+        Ok(())
+    }
+
+    fn delete_password(&self, username: &str) -> Result<(), VpnError> {
+        self.0.lock().unwrap().remove(username);
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use crate::logparser::LogLine;
+use serde::{Deserialize, Serialize};
+
+/// Event emitted to the frontend with the diagnosis of every failed connection attempt
+pub const FAILURE_EVENT: &str = "vpn-failure";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCause {
+    DnsResolution,
+    UdpBlocked,
+    TlsHandshakeTimeout,
+    TlsHandshakeFailed,
+    CipherMismatch,
+    ClockSkew,
+    CertificateExpired,
+    AuthenticationFailed,
+    MissingAdapter,
+    PortInUse,
+    Unknown,
+}
+
+impl FailureCause {
+    fn summary(self) -> &'static str {
+        match self {
+            FailureCause::DnsResolution => "The VPN server's address could not be resolved.",
+            FailureCause::UdpBlocked => {
+                "The VPN server did not answer; UDP traffic appears to be blocked."
+            }
+            FailureCause::TlsHandshakeTimeout => {
+                "The VPN server answered but the secure handshake timed out."
+            }
+            FailureCause::TlsHandshakeFailed => "The secure handshake with the VPN server failed.",
+            FailureCause::CipherMismatch => {
+                "The client and server could not agree on an encryption cipher."
+            }
+            FailureCause::ClockSkew => {
+                "The server certificate is not valid yet, so the system clock is probably wrong."
+            }
+            FailureCause::CertificateExpired => "The server certificate has expired.",
+            FailureCause::AuthenticationFailed => {
+                "Authentication failed. Please check your credentials."
+            }
+            FailureCause::MissingAdapter => "No usable VPN network adapter was found.",
+            FailureCause::PortInUse => "The VPN port is already in use by another program.",
+            FailureCause::Unknown => "The connection failed.",
+        }
+    }
+
+    fn suggestion(self) -> Option<&'static str> {
+        match self {
+            FailureCause::DnsResolution => Some("Check your internet connection and DNS settings, then try again."),
+            FailureCause::UdpBlocked => Some("Your network may block VPN traffic. Try another network, or disable firewalls that filter UDP."),
+            FailureCause::TlsHandshakeTimeout => Some("The server may be overloaded. Try again later or pick another server."),
+            FailureCause::TlsHandshakeFailed => Some("Update GekkoVPN so your server profiles are current, then try again."),
+            FailureCause::CipherMismatch => Some("Update GekkoVPN so your server profiles match the server's settings."),
+            FailureCause::ClockSkew => Some("Set your system date, time and time zone correctly, then try again."),
+            FailureCause::CertificateExpired => Some("Check that your system clock is correct. If it is, the server needs a new certificate; contact support."),
+            FailureCause::AuthenticationFailed => Some("Log in again with your current username and password."),
+            FailureCause::MissingAdapter => Some("Restart GekkoVPN as administrator so it can install the TAP adapter."),
+            FailureCause::PortInUse => Some("Close other VPN clients that may be running, then try again."),
+            FailureCause::Unknown => None,
+        }
+    }
+}
+
+/// Why a connection attempt failed, in a form the dashboard can render
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnosis {
+    pub cause: FailureCause,
+    pub summary: String,
+    pub suggestion: Option<String>,
+    /// The openvpn log line the diagnosis is based on
+    pub evidence: Option<String>,
+    /// The error the attempt failed with
+    pub error: String,
+}
+
+impl Diagnosis {
+    fn new(cause: FailureCause, evidence: Option<&LogLine>, error: &str) -> Self {
+        Diagnosis {
+            cause,
+            summary: cause.summary().to_string(),
+            suggestion: cause.suggestion().map(str::to_string),
+            evidence: evidence.map(|line| line.to_string()),
+            error: error.to_string(),
+        }
+    }
+
+    fn unclassified(error: &str) -> Self {
+        let error = error.to_string();
+        Diagnosis {
+            cause: FailureCause::Unknown,
+            summary: error.clone(),
+            suggestion: None,
+            evidence: None,
+            error,
+        }
+    }
+}
+
+type Rule = (FailureCause, fn(&LogLine) -> bool);
+
+/// Causes in order of precedence; a failed certificate check also shows up as a
+/// TLS error, so the specific causes have to win
+const RULES: &[Rule] = &[
+    (
+        FailureCause::ClockSkew,
+        |line| matches!(line, LogLine::VerifyError { error, .. } if error.contains("not yet valid")),
+    ),
+    (
+        FailureCause::CertificateExpired,
+        |line| matches!(line, LogLine::VerifyError { error, .. } if error.contains("expired")),
+    ),
+    (FailureCause::CipherMismatch, |line| {
+        matches!(line, LogLine::CipherMismatch(_))
+    }),
+    (FailureCause::AuthenticationFailed, |line| {
+        matches!(line, LogLine::AuthFailed(_))
+    }),
+    (FailureCause::MissingAdapter, |line| {
+        matches!(line, LogLine::AdapterError(_))
+    }),
+    (FailureCause::PortInUse, |line| {
+        matches!(line, LogLine::BindFailed(_))
+    }),
+    (FailureCause::DnsResolution, |line| {
+        matches!(line, LogLine::ResolveFailed { .. })
+    }),
+    (
+        FailureCause::TlsHandshakeTimeout,
+        |line| matches!(line, LogLine::TlsError(message) if message.contains("key negotiation failed")),
+    ),
+    (FailureCause::TlsHandshakeFailed, |line| {
+        matches!(line, LogLine::TlsError(_) | LogLine::VerifyError { .. })
+    }),
+];
+
+fn classify(output: &[LogLine]) -> Option<(FailureCause, &LogLine)> {
+    let (cause, evidence) = RULES.iter().find_map(|(cause, matches)| {
+        output
+            .iter()
+            .rev()
+            .find(|line| matches(line))
+            .map(|line| (*cause, line))
+    })?;
+
+    // A handshake that times out without a single packet from the server means
+    // the packets never got through
+    if cause == FailureCause::TlsHandshakeTimeout {
+        let answered = output
+            .iter()
+            .any(|line| matches!(line, LogLine::InitialPacket { .. }));
+        let over_udp = output.iter().any(
+            |line| matches!(line, LogLine::LinkRemote { protocol, .. } if protocol.starts_with("UDP")),
+        );
+        if !answered && over_udp {
+            return Some((FailureCause::UdpBlocked, evidence));
+        }
+    }
+    Some((cause, evidence))
+}
+
+/// Explains `error` using the openvpn output collected during the attempt, or else
+/// the cause the attempt `reported` itself
+pub fn diagnose(error: &str, reported: Option<FailureCause>, output: &[LogLine]) -> Diagnosis {
+    match (classify(output), reported) {
+        (Some((cause, evidence)), _) => Diagnosis::new(cause, Some(evidence), error),
+        // Rejected credentials are also reported over the management interface only
+        (None, Some(cause)) => Diagnosis::new(cause, None, error),
+        (None, None) => Diagnosis::unclassified(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logparser::parse_line;
+
+    fn diagnose_fixture(log: &str) -> Diagnosis {
+        let output: Vec<LogLine> = log.lines().map(parse_line).collect();
+        diagnose(
+            "Connection timed out waiting for authentication",
+            None,
+            &output,
+        )
+    }
+
+    #[test]
+    fn classifies_recorded_failures() {
+        let cases = [
+            (
+                include_str!("../tests/fixtures/openvpn/resolve_failed.log"),
+                FailureCause::DnsResolution,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/tls_timeout.log"),
+                FailureCause::UdpBlocked,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/tls_handshake_stalled.log"),
+                FailureCause::TlsHandshakeTimeout,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/cipher_mismatch.log"),
+                FailureCause::CipherMismatch,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/clock_skew.log"),
+                FailureCause::ClockSkew,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/cert_expired.log"),
+                FailureCause::CertificateExpired,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/auth_failed.log"),
+                FailureCause::AuthenticationFailed,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/no_adapter_windows.log"),
+                FailureCause::MissingAdapter,
+            ),
+            (
+                include_str!("../tests/fixtures/openvpn/port_in_use.log"),
+                FailureCause::PortInUse,
+            ),
+        ];
+
+        for (log, expected) in cases {
+            let diagnosis = diagnose_fixture(log);
+            assert_eq!(diagnosis.cause, expected, "{:?}", diagnosis);
+            assert!(diagnosis.suggestion.is_some());
+            assert!(diagnosis.evidence.is_some());
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_original_error() {
+        let diagnosis = diagnose_fixture(include_str!(
+            "../tests/fixtures/openvpn/connected_linux.log"
+        ));
+        assert_eq!(diagnosis.cause, FailureCause::Unknown);
+        assert_eq!(
+            diagnosis.summary,
+            "Connection timed out waiting for authentication"
+        );
+
+        let diagnosis = diagnose(
+            "The server rejected the credentials",
+            Some(FailureCause::AuthenticationFailed),
+            &[],
+        );
+        assert_eq!(diagnosis.cause, FailureCause::AuthenticationFailed);
+        assert!(diagnosis.evidence.is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use crate::catalog;
+use crate::redact;
+use crate::service::Service;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Commands the bundle runs are given up on after this long
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Files that go into the diagnostics zip. Contents are redacted as they are added.
+#[derive(Debug, Default)]
+pub struct Bundle {
+    entries: Vec<(String, String)>,
+}
+
+impl Bundle {
+    pub fn add(&mut self, name: &str, contents: &str) {
+        self.entries
+            .push((name.to_string(), redact::redact(contents)));
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+        let mut zip = ZipWriter::new(file);
+        for (name, contents) in &self.entries {
+            zip.start_file(name.as_str(), SimpleFileOptions::default())
+                .and_then(|_| Ok(zip.write_all(contents.as_bytes())?))
+                .map_err(|e| format!("Failed to add {} to {:?}: {}", name, path, e))?;
+        }
+        zip.finish()
+            .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        Ok(())
+    }
+}
+
+/// Collects logs, the profile of `server_name` (or the current server) and
+/// everything about the system that helps to tell why a connection fails.
+/// `version` is the front-end's, `log_dir` is the directory given to `logging::init`.
+pub async fn collect(
+    service: &Service,
+    version: &str,
+    log_dir: Option<&Path>,
+    server_name: Option<&str>,
+) -> Bundle {
+    let mut bundle = Bundle::default();
+
+    bundle.add(
+        "logs/recent.log",
+        &service
+            .export_logs()
+            .await
+            .unwrap_or_else(|e| e.to_string()),
+    );
+    if let Some(dir) = log_dir {
+        if let Ok(log) = std::fs::read_to_string(dir.join("gekkovpn.log")) {
+            bundle.add("logs/gekkovpn.log", &log);
+        }
+    }
+
+    let status = service.status().await.ok();
+    let server = server_name
+        .map(str::to_string)
+        .or_else(|| status.as_ref().and_then(|status| status.server.clone()));
+    if let Some(status) = &status {
+        bundle.add(
+            "connection.json",
+            &serde_json::to_string_pretty(status).unwrap_or_default(),
+        );
+    }
+    bundle.add(
+        "last_failure.json",
+        &serde_json::to_string_pretty(&service.last_failure().await.ok().flatten())
+            .unwrap_or_default(),
+    );
+
+    let mut system = format!(
+        "GekkoVPN {}\nOS: {} ({})\nArch: {}\n",
+        version,
+        std::env::consts::OS,
+        std::env::consts::FAMILY,
+        std::env::consts::ARCH
+    );
+
+    match service.app_paths().await {
+        Ok(paths) => {
+            bundle.add(
+                "paths.json",
+                &serde_json::to_string_pretty(&paths).unwrap_or_default(),
+            );
+
+            let version = run(&paths.openvpn_binary.to_string_lossy(), &["--version"]).await;
+            system.push_str(&format!(
+                "OpenVPN: {}\n",
+                version.lines().next().unwrap_or_default()
+            ));
+
+            if let Some(server) = &server {
+                // Only a server of the catalog names an entry of the archive
+                match catalog::profile(&paths.config_dir, server) {
+                    Ok(profile) => {
+                        let contents = std::fs::read_to_string(&profile)
+                            .unwrap_or_else(|e| format!("Failed to read {:?}: {}", profile, e));
+                        bundle.add(&format!("config/{}.ovpn", server), &contents);
+                    }
+                    Err(e) => bundle.add("config/error.txt", &e.to_string()),
+                }
+            }
+
+            bundle.add(
+                "adapters.json",
+                &match service.adapters().await {
+                    Ok(report) => serde_json::to_string_pretty(&report).unwrap_or_default(),
+                    Err(e) => e.to_string(),
+                },
+            );
+        }
+        Err(e) => bundle.add("paths.json", &e.to_string()),
+    }
+    bundle.add("system.txt", &system);
+
+    let mut network = String::new();
+    for (program, args) in network_commands() {
+        network.push_str(&format!("$ {} {}\n", program, args.join(" ")));
+        network.push_str(&run(program, args).await);
+        network.push('\n');
+    }
+    bundle.add("network.txt", &network);
+
+    bundle
+}
+
+/// Commands that print the route table and DNS setup
+fn network_commands() -> &'static [(&'static str, &'static [&'static str])] {
+    if cfg!(target_os = "windows") {
+        &[("route", &["print"]), ("ipconfig", &["/all"])]
+    } else if cfg!(target_os = "macos") {
+        &[("netstat", &["-rn"]), ("scutil", &["--dns"])]
+    } else {
+        &[
+            ("ip", &["route", "show", "table", "all"]),
+            ("cat", &["/etc/resolv.conf"]),
+        ]
+    }
+}
+
+/// Output of a command, or why it could not be run
+async fn run(program: &str, args: &[&str]) -> String {
+    let output = Command::new(program).args(args).kill_on_drop(true).output();
+    match tokio::time::timeout(COMMAND_TIMEOUT, output).await {
+        Ok(Ok(output)) => format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Ok(Err(e)) => format!("Failed to run {}: {}", program, e),
+        Err(_) => format!("{} did not finish within {:?}", program, COMMAND_TIMEOUT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn writes_redacted_entries_to_a_zip() {
+        let path =
+            std::env::temp_dir().join(format!("gekkovpn-diagnostics-{}.zip", std::process::id()));
+        let mut bundle = Bundle::default();
+        bundle.add(
+            "config/nl1.ovpn",
+            "remote 185.107.56.21 1194\n<key>\nMIIEvQIBADANBgkqhkiG9w0BAQEFAASC\n</key>\n",
+        );
+        bundle.add("system.txt", "OS: windows\n");
+        bundle.write(&path).unwrap();
+
+        let mut zip = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(zip.len(), 2);
+        let mut profile = String::new();
+        zip.by_name("config/nl1.ovpn")
+            .unwrap()
+            .read_to_string(&mut profile)
+            .unwrap();
+        assert_eq!(
+            profile,
+            "remote 185.107.x.x 1194\n<key>[PRIVATE KEY]</key>\n"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+use crate::diagnosis::{Diagnosis, FailureCause};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Connection,
+    Credentials,
+    Configuration,
+    Adapter,
+    Process,
+    Internal,
+}
+
+/// Error returned by every command. The frontend gets it as
+/// `{ code, category, message, detail, diagnosis? }` and branches on `code`,
+/// which must not change once released.
+#[derive(Debug, Clone)]
+pub enum VpnError {
+    AlreadyConnected,
+    NotConnected,
+    Cancelled,
+    /// openvpn was started but the tunnel did not come up
+    ConnectionFailed(Diagnosis),
+    /// The operation is not possible in the current connection state
+    InvalidState(String),
+    NoSavedPassword {
+        username: String,
+    },
+    Keyring(String),
+    AppPaths(String),
+    OpenVpnNotFound(PathBuf),
+    ConfigNotFound(PathBuf),
+    /// A server name that is not in the catalog, such as one with a path in it
+    UnknownServer(String),
+    InvalidSetting(String),
+    AdminRequired,
+    Adapter(String),
+    Process(String),
+    Management(String),
+    Storage(String),
+    ManagerUnavailable,
+    /// The daemon refused a client, or a request the client is not allowed to make
+    PermissionDenied(String),
+    /// The daemon and a client speak different protocol versions or sent garbage
+    Protocol(String),
+}
+
+impl VpnError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            VpnError::AlreadyConnected => "already_connected",
+            VpnError::NotConnected => "not_connected",
+            VpnError::Cancelled => "cancelled",
+            VpnError::ConnectionFailed(_) => "connection_failed",
+            VpnError::InvalidState(_) => "invalid_state",
+            VpnError::NoSavedPassword { .. } => "no_saved_password",
+            VpnError::Keyring(_) => "keyring_error",
+            VpnError::AppPaths(_) => "app_paths_unavailable",
+            VpnError::OpenVpnNotFound(_) => "openvpn_not_found",
+            VpnError::ConfigNotFound(_) => "config_not_found",
+            VpnError::UnknownServer(_) => "unknown_server",
+            VpnError::InvalidSetting(_) => "invalid_setting",
+            VpnError::AdminRequired => "admin_required",
+            VpnError::Adapter(_) => "adapter_error",
+            VpnError::Process(_) => "process_error",
+            VpnError::Management(_) => "management_error",
+            VpnError::Storage(_) => "storage_error",
+            VpnError::ManagerUnavailable => "manager_unavailable",
+            VpnError::PermissionDenied(_) => "permission_denied",
+            VpnError::Protocol(_) => "protocol_error",
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            VpnError::AlreadyConnected
+            | VpnError::NotConnected
+            | VpnError::Cancelled
+            | VpnError::InvalidState(_) => ErrorCategory::Connection,
+            VpnError::ConnectionFailed(diagnosis) => match diagnosis.cause {
+                FailureCause::AuthenticationFailed => ErrorCategory::Credentials,
+                FailureCause::MissingAdapter => ErrorCategory::Adapter,
+                _ => ErrorCategory::Connection,
+            },
+            VpnError::NoSavedPassword { .. } | VpnError::Keyring(_) => ErrorCategory::Credentials,
+            VpnError::AppPaths(_)
+            | VpnError::OpenVpnNotFound(_)
+            | VpnError::ConfigNotFound(_)
+            | VpnError::UnknownServer(_)
+            | VpnError::InvalidSetting(_)
+            | VpnError::PermissionDenied(_)
+            | VpnError::Protocol(_) => ErrorCategory::Configuration,
+            VpnError::AdminRequired | VpnError::Adapter(_) => ErrorCategory::Adapter,
+            VpnError::Process(_) | VpnError::Management(_) => ErrorCategory::Process,
+            VpnError::Storage(_) | VpnError::ManagerUnavailable => ErrorCategory::Internal,
+        }
+    }
+
+    /// Message meant for the user
+    pub fn message(&self) -> String {
+        match self {
+            VpnError::AlreadyConnected => {
+                "VPN is already running. Please disconnect first.".to_string()
+            }
+            VpnError::NotConnected => "Not connected to VPN".to_string(),
+            VpnError::Cancelled => "Connection cancelled".to_string(),
+            VpnError::ConnectionFailed(diagnosis) => diagnosis.summary.clone(),
+            VpnError::InvalidState(_) => {
+                "The VPN is busy. Please wait a moment and try again.".to_string()
+            }
+            VpnError::NoSavedPassword { .. } => {
+                "VPN credentials not found. Please log in again.".to_string()
+            }
+            VpnError::Keyring(_) => "Could not access the system keyring.".to_string(),
+            VpnError::AppPaths(_) => "Could not locate the application files.".to_string(),
+            VpnError::OpenVpnNotFound(_) => {
+                "OpenVPN was not found. Please reinstall GekkoVPN.".to_string()
+            }
+            VpnError::ConfigNotFound(_) => {
+                "The configuration for this server was not found.".to_string()
+            }
+            VpnError::UnknownServer(_) => {
+                "This server is not available. Please pick another one.".to_string()
+            }
+            VpnError::InvalidSetting(message) => message.clone(),
+            VpnError::AdminRequired if cfg!(windows) => "No TAP adapter found. Please run the application as administrator to set up the VPN adapter.".to_string(),
+            VpnError::AdminRequired => "OpenVPN needs administrator rights to create the VPN device. Install polkit (pkexec) or give openvpn the CAP_NET_ADMIN capability.".to_string(),
+            VpnError::Adapter(_) => "The VPN network adapter could not be set up.".to_string(),
+            VpnError::Process(_) => "Could not control the OpenVPN process.".to_string(),
+            VpnError::Management(_) => "Could not communicate with OpenVPN.".to_string(),
+            VpnError::Storage(_) => "Could not read or write GekkoVPN's data.".to_string(),
+            VpnError::ManagerUnavailable => "The VPN service is not running.".to_string(),
+            VpnError::PermissionDenied(_) => "You are not allowed to control the VPN. Ask an administrator to add you to the gekkovpn group.".to_string(),
+            VpnError::Protocol(_) => {
+                "The VPN service does not match this version of GekkoVPN. Please update GekkoVPN."
+                    .to_string()
+            }
+        }
+    }
+
+    /// Technical detail for logs and bug reports
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            VpnError::ConnectionFailed(diagnosis) => Some(diagnosis.error.clone()),
+            VpnError::InvalidState(detail)
+            | VpnError::Keyring(detail)
+            | VpnError::AppPaths(detail)
+            | VpnError::Adapter(detail)
+            | VpnError::Process(detail)
+            | VpnError::Management(detail)
+            | VpnError::Storage(detail)
+            | VpnError::PermissionDenied(detail)
+            | VpnError::Protocol(detail) => Some(detail.clone()),
+            VpnError::NoSavedPassword { username } => {
+                Some(format!("No password stored for {}", username))
+            }
+            VpnError::OpenVpnNotFound(path) | VpnError::ConfigNotFound(path) => {
+                Some(format!("{:?} does not exist", path))
+            }
+            VpnError::UnknownServer(name) => Some(format!(
+                "{:?} is not a server of the configuration directory",
+                name
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VpnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())?;
+        if let Some(detail) = self.detail() {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VpnError {}
+
+impl Serialize for VpnError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Payload<'a> {
+            code: &'static str,
+            category: ErrorCategory,
+            message: String,
+            detail: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            diagnosis: Option<&'a Diagnosis>,
+        }
+
+        Payload {
+            code: self.code(),
+            category: self.category(),
+            message: self.message(),
+            detail: self.detail(),
+            diagnosis: match self {
+                VpnError::ConnectionFailed(diagnosis) => Some(diagnosis),
+                _ => None,
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<Diagnosis> for VpnError {
+    fn from(diagnosis: Diagnosis) -> Self {
+        VpnError::ConnectionFailed(diagnosis)
+    }
+}
+
+impl From<keyring::Error> for VpnError {
+    fn from(error: keyring::Error) -> Self {
+        VpnError::Keyring(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_code_category_and_message() {
+        let error = VpnError::ConfigNotFound(PathBuf::from("nl1/profile.ovpn"));
+        let json = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(json["code"], "config_not_found");
+        assert_eq!(json["category"], "configuration");
+        assert_eq!(
+            json["message"],
+            "The configuration for this server was not found."
+        );
+        assert!(json["detail"].as_str().unwrap().contains("nl1"));
+        assert!(json.get("diagnosis").is_none());
+    }
+
+    #[test]
+    fn connection_failures_carry_their_diagnosis() {
+        let diagnosis = crate::diagnosis::diagnose(
+            "The server rejected the credentials",
+            Some(crate::diagnosis::FailureCause::AuthenticationFailed),
+            &[],
+        );
+        let json = serde_json::to_value(VpnError::from(diagnosis)).unwrap();
+
+        assert_eq!(json["code"], "connection_failed");
+        assert_eq!(json["category"], "credentials");
+        assert_eq!(json["diagnosis"]["cause"], "authentication_failed");
+    }
+}
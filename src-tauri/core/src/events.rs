@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Events a subscriber has not received yet are dropped past this many
+const BROADCAST_CAPACITY: usize = 256;
+
+/// Where events for the front-end go: the webview in the app, a channel for
+/// terminal front-ends, a recorder in tests
+pub trait EventSink: Send + Sync {
+    fn emit_json(&self, event: &str, payload: serde_json::Value) -> Result<(), String>;
+}
+
+/// Emits events to a shared sink
+#[derive(Clone)]
+pub struct Events(Arc<dyn EventSink>);
+
+impl Events {
+    pub fn new(sink: Arc<dyn EventSink>) -> Self {
+        Events(sink)
+    }
+
+    pub fn emit(&self, event: &str, payload: impl Serialize) -> Result<(), String> {
+        let payload = serde_json::to_value(payload).map_err(|e| e.to_string())?;
+        self.0.emit_json(event, payload)
+    }
+}
+
+/// An event as it was emitted, e.g. `vpn-state` with `{"state": "connected"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub name: String,
+    pub payload: serde_json::Value,
+}
+
+/// Hands every event to all current subscribers
+#[derive(Clone)]
+pub struct Broadcast(broadcast::Sender<Event>);
+
+impl Default for Broadcast {
+    fn default() -> Self {
+        Broadcast(broadcast::channel(BROADCAST_CAPACITY).0)
+    }
+}
+
+impl Broadcast {
+    /// Events emitted from now on. A subscriber that falls too far behind gets
+    /// `RecvError::Lagged` and then continues with the latest events.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.0.subscribe()
+    }
+}
+
+impl EventSink for Broadcast {
+    fn emit_json(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        // Nobody listening is not an error
+        let _ = self.0.send(Event {
+            name: event.to_string(),
+            payload,
+        });
+        Ok(())
+    }
+}
+
+/// Keeps every event emitted, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordedEvents(std::sync::Mutex<Vec<(String, serde_json::Value)>>);
+
+#[cfg(test)]
+impl RecordedEvents {
+    /// Payloads of the events called `event`, oldest first
+    pub fn named(&self, event: &str) -> Vec<serde_json::Value> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == event)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl EventSink for RecordedEvents {
+    fn emit_json(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        self.0.lock().unwrap().push((event.to_string(), payload));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broadcasts_to_every_subscriber() {
+        let broadcast = Broadcast::default();
+        let events = Events::new(Arc::new(broadcast.clone()));
+        events.emit("vpn-state", "before anyone listens").unwrap();
+
+        let mut first = broadcast.subscribe();
+        let mut second = broadcast.subscribe();
+        events
+            .emit("vpn-state", serde_json::json!({ "state": "connected" }))
+            .unwrap();
+
+        for subscriber in [&mut first, &mut second] {
+            let event = subscriber.try_recv().unwrap();
+            assert_eq!(event.name, "vpn-state");
+            assert_eq!(event.payload["state"], "connected");
+            assert!(subscriber.try_recv().is_err());
+        }
+    }
+}
@@ -0,0 +1,768 @@
+//! The protocol front-ends use to control a [`VpnClient`] in another process, such
+//! as the privileged daemon. Messages are JSON, one per line. The client opens with
+//! a [`Hello`] naming its protocol version; the server answers with a welcome or
+//! with why it refuses. Then the client sends numbered requests, which are
+//! answered as they finish, and the server pushes events once subscribed.
+
+use crate::client::VpnClient;
+use crate::diagnosis::Diagnosis;
+use crate::error::VpnError;
+use crate::events::{Broadcast, Event};
+use crate::logbuffer::LogFilter;
+use crate::paths::PathOverrides;
+use crate::supervisor::ReconnectPolicy;
+use crate::usage::{Granularity, UsageCap};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+/// Bumped whenever a message changes in a way older peers cannot read
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Overrides where the daemon listens
+pub const SOCKET_ENV: &str = "GEKKOVPN_SOCKET";
+
+#[cfg(target_os = "macos")]
+const DEFAULT_SOCKET: &str = "/var/run/gekkovpnd.sock";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_SOCKET: &str = "/run/gekkovpn/gekkovpnd.sock";
+
+/// Events a client has not read yet are dropped past this many
+const EVENT_CAPACITY: usize = 256;
+
+/// The Unix socket the daemon listens on
+pub fn socket_path() -> PathBuf {
+    std::env::var_os(SOCKET_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET))
+}
+
+/// The first message of a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u32,
+}
+
+/// What a client may do once accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Everything, including the settings and adapters of the server
+    Full,
+    /// Bringing the tunnel up and down, and reading what the server reports
+    User,
+}
+
+/// The operations of [`VpnClient`], as sent over the wire
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    Connect {
+        server_name: String,
+        username: String,
+        /// A daemon cannot read the user's keyring, so the client sends the password
+        /// along. It is used for this tunnel only and not saved.
+        password: Option<String>,
+    },
+    Disconnect,
+    SwitchServer {
+        server_name: String,
+        username: String,
+        password: Option<String>,
+    },
+    Reconnect,
+    CancelConnect,
+    ListServers,
+    Status,
+    State,
+    TrafficStats,
+    LastFailure,
+    Logs {
+        filter: LogFilter,
+    },
+    ExportLogs,
+    SetOpenVpnVerb {
+        verb: u8,
+    },
+    Usage {
+        granularity: Granularity,
+        server: Option<String>,
+    },
+    UsageCsv,
+    UsageCap,
+    SetUsageCap {
+        cap: Option<UsageCap>,
+    },
+    AppPaths,
+    PathOverrides,
+    SetPathOverrides {
+        overrides: PathOverrides,
+    },
+    Adapters,
+    CreateAdapter {
+        name: Option<String>,
+    },
+    RemoveAdapter {
+        name: String,
+    },
+    ReconnectPolicy,
+    SetReconnectPolicy {
+        policy: ReconnectPolicy,
+    },
+    ShutdownGracePeriod,
+    SetShutdownGracePeriod {
+        seconds: u64,
+    },
+    /// Starts pushing every event the client emits
+    Subscribe,
+}
+
+impl Request {
+    /// Whether the request changes the server beyond the tunnel itself. The server
+    /// may run openvpn as root for several users, so those are for `Access::Full`.
+    fn changes_the_service(&self) -> bool {
+        matches!(
+            self,
+            Request::SetOpenVpnVerb { .. }
+                | Request::SetUsageCap { .. }
+                | Request::SetPathOverrides { .. }
+                | Request::CreateAdapter { .. }
+                | Request::RemoveAdapter { .. }
+                | Request::SetReconnectPolicy { .. }
+                | Request::SetShutdownGracePeriod { .. }
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    id: u64,
+    request: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ServerMessage {
+    Welcome { version: u32 },
+    Rejected(#[serde(with = "WireError")] VpnError),
+    Reply { id: u64, result: Outcome },
+    Event(Event),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Ok(Value),
+    Err(#[serde(with = "WireError")] VpnError),
+}
+
+/// `VpnError` as it crosses the wire, every variant intact. Its own serialization
+/// is what the GUI gets, which cannot be read back.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "VpnError", rename_all = "snake_case")]
+enum WireError {
+    AlreadyConnected,
+    NotConnected,
+    Cancelled,
+    ConnectionFailed(Diagnosis),
+    InvalidState(String),
+    NoSavedPassword { username: String },
+    Keyring(String),
+    AppPaths(String),
+    OpenVpnNotFound(PathBuf),
+    ConfigNotFound(PathBuf),
+    UnknownServer(String),
+    InvalidSetting(String),
+    AdminRequired,
+    Adapter(String),
+    Process(String),
+    Management(String),
+    Storage(String),
+    ManagerUnavailable,
+    PermissionDenied(String),
+    Protocol(String),
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, VpnError> {
+    serde_json::to_value(value).map_err(|e| VpnError::Protocol(e.to_string()))
+}
+
+/// Runs `request` against `client` and returns its result as JSON
+pub async fn handle(
+    client: &VpnClient,
+    access: Access,
+    request: Request,
+) -> Result<Value, VpnError> {
+    if access != Access::Full && request.changes_the_service() {
+        return Err(VpnError::PermissionDenied(
+            "Only root can change the settings of the VPN service".to_string(),
+        ));
+    }
+    match request {
+        Request::Connect {
+            server_name,
+            username,
+            password,
+        } => to_value(match password {
+            // Only for this tunnel, so no later peer connects with it
+            Some(password) => {
+                client
+                    .connect_with_password(server_name, username, password)
+                    .await?
+            }
+            None => client.connect(server_name, username).await?,
+        }),
+        Request::Disconnect => to_value(client.disconnect().await?),
+        Request::SwitchServer {
+            server_name,
+            username,
+            password,
+        } => to_value(match password {
+            Some(password) => {
+                client
+                    .switch_server_with_password(server_name, username, password)
+                    .await?
+            }
+            None => client.switch_server(server_name, username).await?,
+        }),
+        Request::Reconnect => to_value(client.reconnect().await?),
+        Request::CancelConnect => to_value(client.cancel_connect()),
+        Request::ListServers => to_value(client.servers()?),
+        Request::Status => to_value(client.status().await?),
+        Request::State => to_value(client.state()),
+        Request::TrafficStats => to_value(client.traffic_stats()),
+        Request::LastFailure => to_value(client.last_failure()),
+        Request::Logs { filter } => to_value(client.logs().query(&filter)),
+        Request::ExportLogs => to_value(client.logs().export()),
+        Request::SetOpenVpnVerb { verb } => to_value(client.set_openvpn_verb(verb).await?),
+        Request::Usage {
+            granularity,
+            server,
+        } => to_value(client.usage().records(granularity, server.as_deref())),
+        Request::UsageCsv => to_value(client.usage().to_csv()),
+        Request::UsageCap => to_value(client.usage().cap()),
+        Request::SetUsageCap { cap } => {
+            if let Some(cap) = cap {
+                if cap.monthly_bytes == 0 || !(1..=100).contains(&cap.warn_at_percent) {
+                    return Err(VpnError::InvalidSetting(
+                        "The cap must be above zero and warn between 1 and 100 percent".to_string(),
+                    ));
+                }
+            }
+            client.usage().set_cap(cap);
+            to_value(client.usage().flush().map_err(VpnError::Storage)?)
+        }
+        Request::AppPaths => to_value(client.paths().resolve()?),
+        Request::PathOverrides => to_value(client.paths().overrides()),
+        Request::SetPathOverrides { overrides } => {
+            to_value(client.paths().set_overrides(overrides)?)
+        }
+        Request::Adapters => to_value(client.adapters().await?),
+        Request::CreateAdapter { name } => to_value(client.create_adapter(name).await?),
+        Request::RemoveAdapter { name } => to_value(client.remove_adapter(name).await?),
+        Request::ReconnectPolicy => to_value(client.reconnect_policy()),
+        Request::SetReconnectPolicy { policy } => to_value(client.set_reconnect_policy(policy)?),
+        Request::ShutdownGracePeriod => to_value(client.shutdown_grace_period().as_secs()),
+        Request::SetShutdownGracePeriod { seconds } => {
+            client.set_shutdown_grace_period(Duration::from_secs(seconds));
+            to_value(())
+        }
+        // Events already go to the sink of a client in this process; `serve`
+        // forwards them to the others
+        Request::Subscribe => to_value(()),
+    }
+}
+
+async fn write_line(
+    write: &mut (impl AsyncWrite + Unpin),
+    message: &impl Serialize,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    write.write_all(&line).await
+}
+
+/// Serves one connection until the peer hangs up. `authorize` decides, given the
+/// hello, what the peer may do, or refuses it.
+pub async fn serve<S>(
+    stream: S,
+    client: VpnClient,
+    broadcast: Broadcast,
+    authorize: impl FnOnce(&Hello) -> Result<Access, VpnError>,
+) where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+    let (outbox, mut outgoing) = mpsc::unbounded_channel::<ServerMessage>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            if write_line(&mut write, &message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let Ok(Some(line)) = lines.next_line().await else {
+        return;
+    };
+    let accepted = serde_json::from_str::<Hello>(&line)
+        .map_err(|e| VpnError::Protocol(format!("Invalid hello: {}", e)))
+        .and_then(|hello| {
+            if hello.version != PROTOCOL_VERSION {
+                return Err(VpnError::Protocol(format!(
+                    "The service speaks protocol version {}, the client {}",
+                    PROTOCOL_VERSION, hello.version
+                )));
+            }
+            authorize(&hello)
+        });
+    let access = match accepted {
+        Ok(access) => access,
+        Err(e) => {
+            let _ = outbox.send(ServerMessage::Rejected(e));
+            drop(outbox);
+            let _ = writer.await;
+            return;
+        }
+    };
+    let _ = outbox.send(ServerMessage::Welcome {
+        version: PROTOCOL_VERSION,
+    });
+
+    let mut forwarder = None;
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(envelope) = serde_json::from_str::<Envelope>(&line) else {
+            break;
+        };
+        let id = envelope.id;
+        let request = match serde_json::from_value::<Request>(envelope.request) {
+            Ok(request) => request,
+            Err(e) => {
+                let _ = outbox.send(ServerMessage::Reply {
+                    id,
+                    result: Outcome::Err(VpnError::Protocol(format!("Unknown request: {}", e))),
+                });
+                continue;
+            }
+        };
+        if matches!(request, Request::Subscribe) && forwarder.is_none() {
+            forwarder = Some(tokio::spawn(forward(broadcast.subscribe(), outbox.clone())));
+        }
+
+        let (client, outbox) = (client.clone(), outbox.clone());
+        tokio::spawn(async move {
+            let result = match handle(&client, access, request).await {
+                Ok(value) => Outcome::Ok(value),
+                Err(e) => Outcome::Err(e),
+            };
+            let _ = outbox.send(ServerMessage::Reply { id, result });
+        });
+    }
+
+    if let Some(forwarder) = forwarder {
+        forwarder.abort();
+    }
+    writer.abort();
+}
+
+async fn forward(
+    mut events: broadcast::Receiver<Event>,
+    outbox: mpsc::UnboundedSender<ServerMessage>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if outbox.send(ServerMessage::Event(event)).is_err() {
+                    return;
+                }
+            }
+            // A slow client misses events; state and logs can be asked for again
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+type Pending = HashMap<u64, oneshot::Sender<Result<Value, VpnError>>>;
+
+struct Inner {
+    outbox: mpsc::UnboundedSender<String>,
+    /// `None` once the server hung up
+    pending: Mutex<Option<Pending>>,
+    events: Mutex<Option<broadcast::Sender<Event>>>,
+    next_id: AtomicU64,
+}
+
+/// A connection to a server, shared by everything that makes requests on it
+#[derive(Clone)]
+pub struct Connection(Arc<Inner>);
+
+impl Connection {
+    /// Says hello and waits to be welcomed
+    pub async fn open<S>(stream: S) -> Result<Self, VpnError>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (read, mut write) = tokio::io::split(stream);
+        let mut lines = BufReader::new(read).lines();
+        let lost = |e: std::io::Error| VpnError::Protocol(format!("Handshake failed: {}", e));
+
+        let hello = Hello {
+            version: PROTOCOL_VERSION,
+        };
+        write_line(&mut write, &hello).await.map_err(lost)?;
+        let line = lines
+            .next_line()
+            .await
+            .map_err(lost)?
+            .ok_or(VpnError::ManagerUnavailable)?;
+        match serde_json::from_str::<ServerMessage>(&line) {
+            Ok(ServerMessage::Welcome { version }) if version == PROTOCOL_VERSION => {}
+            Ok(ServerMessage::Welcome { version }) => {
+                return Err(VpnError::Protocol(format!(
+                    "The service speaks protocol version {}, the client {}",
+                    version, PROTOCOL_VERSION
+                )))
+            }
+            Ok(ServerMessage::Rejected(e)) => return Err(e),
+            _ => return Err(VpnError::Protocol(format!("Unexpected hello {:?}", line))),
+        }
+
+        let (outbox, mut outgoing) = mpsc::unbounded_channel::<String>();
+        let inner = Arc::new(Inner {
+            outbox,
+            pending: Mutex::new(Some(HashMap::new())),
+            events: Mutex::new(Some(broadcast::channel(EVENT_CAPACITY).0)),
+            next_id: AtomicU64::new(1),
+        });
+
+        tokio::spawn(async move {
+            while let Some(line) = outgoing.recv().await {
+                if write.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let reader = inner.clone();
+        tokio::spawn(async move {
+            while let Ok(Some(line)) = lines.next_line().await {
+                match serde_json::from_str::<ServerMessage>(&line) {
+                    Ok(ServerMessage::Reply { id, result }) => {
+                        let waiting = reader
+                            .pending
+                            .lock()
+                            .unwrap()
+                            .as_mut()
+                            .and_then(|pending| pending.remove(&id));
+                        if let Some(waiting) = waiting {
+                            let _ = waiting.send(match result {
+                                Outcome::Ok(value) => Ok(value),
+                                Outcome::Err(e) => Err(e),
+                            });
+                        }
+                    }
+                    Ok(ServerMessage::Event(event)) => {
+                        if let Some(events) = &*reader.events.lock().unwrap() {
+                            let _ = events.send(event);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // Requests still waiting fail, and subscribers see the end
+            reader.pending.lock().unwrap().take();
+            reader.events.lock().unwrap().take();
+        });
+
+        Ok(Connection(inner))
+    }
+
+    /// Connects to the daemon, or returns `None` when none is running
+    #[cfg(unix)]
+    pub async fn daemon() -> Result<Option<Self>, VpnError> {
+        let path = socket_path();
+        match tokio::net::UnixStream::connect(&path).await {
+            Ok(stream) => Connection::open(stream).await.map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                Err(VpnError::PermissionDenied(format!("{:?}: {}", path, e)))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub async fn call(&self, request: Request) -> Result<Value, VpnError> {
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply, response) = oneshot::channel();
+        self.0
+            .pending
+            .lock()
+            .unwrap()
+            .as_mut()
+            .ok_or(VpnError::ManagerUnavailable)?
+            .insert(id, reply);
+
+        let envelope = Envelope {
+            id,
+            request: to_value(request)?,
+        };
+        let mut line =
+            serde_json::to_string(&envelope).map_err(|e| VpnError::Protocol(e.to_string()))?;
+        line.push('\n');
+        self.0
+            .outbox
+            .send(line)
+            .map_err(|_| VpnError::ManagerUnavailable)?;
+        response.await.map_err(|_| VpnError::ManagerUnavailable)?
+    }
+
+    /// Events the server pushes from now on. The receiver is closed when the
+    /// server hangs up.
+    pub async fn subscribe(&self) -> Result<broadcast::Receiver<Event>, VpnError> {
+        let events = self
+            .0
+            .events
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok_or(VpnError::ManagerUnavailable)?
+            .subscribe();
+        self.call(Request::Subscribe).await?;
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ConnectionInfo;
+    use crate::connection::Timeouts;
+    use crate::logbuffer::{LogRecord, Severity};
+    use crate::states::ConnectionState;
+    use crate::testing::{Harness, CONNECTS, SERVER};
+
+    /// A connection to `serve` over an in-memory pipe
+    async fn connect(
+        harness: &Harness,
+        authorize: impl FnOnce(&Hello) -> Result<Access, VpnError> + Send + 'static,
+    ) -> Result<Connection, VpnError> {
+        let (server, client) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve(
+            server,
+            harness.client.clone(),
+            harness.broadcast.clone(),
+            authorize,
+        ));
+        Connection::open(client).await
+    }
+
+    #[tokio::test]
+    async fn connects_with_the_password_it_was_sent_without_keeping_it() {
+        let harness = Harness::new("ipc-connect", CONNECTS, Timeouts::default());
+        let connection = connect(&harness, |_| Ok(Access::User)).await.unwrap();
+
+        let state = connection.call(Request::State).await.unwrap();
+        assert_eq!(
+            serde_json::from_value::<ConnectionState>(state).unwrap(),
+            ConnectionState::Disconnected
+        );
+
+        let reply = connection
+            .call(Request::Connect {
+                server_name: SERVER.to_string(),
+                username: "bob".to_string(),
+                password: Some("hunter2".to_string()),
+            })
+            .await;
+        assert!(reply.is_ok(), "{:?}", reply);
+        assert_eq!(harness.client.password("bob").unwrap(), None);
+
+        let status: ConnectionInfo =
+            serde_json::from_value(connection.call(Request::Status).await.unwrap()).unwrap();
+        assert_eq!(status.state, ConnectionState::Connected);
+        connection.call(Request::Disconnect).await.unwrap();
+
+        // Another peer cannot connect as bob without knowing the password
+        let other = connect(&harness, |_| Ok(Access::User)).await.unwrap();
+        let error = other
+            .call(Request::Connect {
+                server_name: SERVER.to_string(),
+                username: "bob".to_string(),
+                password: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, VpnError::NoSavedPassword { .. }),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_errors_intact() {
+        let harness = Harness::new("ipc-errors", CONNECTS, Timeouts::default());
+        let connection = connect(&harness, |_| Ok(Access::User)).await.unwrap();
+
+        let error = connection
+            .call(Request::SetPathOverrides {
+                overrides: PathOverrides::default(),
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, VpnError::PermissionDenied(_)));
+
+        let error = connection
+            .call(Request::Connect {
+                server_name: SERVER.to_string(),
+                username: "nobody".to_string(),
+                password: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, VpnError::NoSavedPassword { username } if username == "nobody"),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_the_settings_of_the_service_to_full_access() {
+        let harness = Harness::new("ipc-access", CONNECTS, Timeouts::default());
+        let user = connect(&harness, |_| Ok(Access::User)).await.unwrap();
+        let full = connect(&harness, |_| Ok(Access::Full)).await.unwrap();
+
+        let requests = [
+            Request::SetOpenVpnVerb { verb: 11 },
+            Request::SetUsageCap { cap: None },
+            Request::SetPathOverrides {
+                overrides: PathOverrides::default(),
+            },
+            Request::CreateAdapter { name: None },
+            Request::RemoveAdapter {
+                name: "GekkoVPN".to_string(),
+            },
+            Request::SetReconnectPolicy {
+                policy: ReconnectPolicy {
+                    max_attempts: 0,
+                    ..ReconnectPolicy::default()
+                },
+            },
+            Request::SetShutdownGracePeriod { seconds: 0 },
+        ];
+        for request in requests {
+            let error = user.call(request).await.unwrap_err();
+            assert!(
+                matches!(error, VpnError::PermissionDenied(_)),
+                "{:?}",
+                error
+            );
+        }
+        assert_eq!(harness.client.reconnect_policy().max_attempts, 5);
+
+        full.call(Request::SetShutdownGracePeriod { seconds: 1 })
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.client.shutdown_grace_period(),
+            Duration::from_secs(1)
+        );
+        // The tunnel itself stays theirs
+        assert!(user.call(Request::CancelConnect).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn pushes_events_to_subscribers() {
+        let harness = Harness::new("ipc-events", CONNECTS, Timeouts::default());
+        let connection = connect(&harness, |_| Ok(Access::User)).await.unwrap();
+        let mut events = connection.subscribe().await.unwrap();
+
+        harness.client.logs().app(Severity::Info, "hello");
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.name, crate::logbuffer::LOG_EVENT);
+        let record: LogRecord = serde_json::from_value(event.payload).unwrap();
+        assert_eq!(record.message, "hello");
+    }
+
+    #[tokio::test]
+    async fn refuses_who_authorize_rejects() {
+        let harness = Harness::new("ipc-reject", CONNECTS, Timeouts::default());
+        let result = connect(&harness, |_| {
+            Err(VpnError::PermissionDenied(
+                "uid 1001 is not in the gekkovpn group".to_string(),
+            ))
+        })
+        .await;
+
+        assert!(matches!(result, Err(VpnError::PermissionDenied(_))));
+    }
+
+    #[tokio::test]
+    async fn refuses_other_protocol_versions() {
+        let harness = Harness::new("ipc-version", CONNECTS, Timeouts::default());
+        let (server, client) = tokio::io::duplex(4096);
+        tokio::spawn(serve(
+            server,
+            harness.client.clone(),
+            harness.broadcast.clone(),
+            |_| Ok(Access::Full),
+        ));
+        let (read, mut write) = tokio::io::split(client);
+        write_line(
+            &mut write,
+            &Hello {
+                version: PROTOCOL_VERSION + 1,
+            },
+        )
+        .await
+        .unwrap();
+
+        let line = BufReader::new(read)
+            .lines()
+            .next_line()
+            .await
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str::<ServerMessage>(&line).unwrap() {
+            ServerMessage::Rejected(VpnError::Protocol(_)) => {}
+            _ => panic!("unexpected answer {}", line),
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_waiting_requests_when_the_server_goes_away() {
+        let (server, client) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            let (read, mut write) = tokio::io::split(server);
+            let mut lines = BufReader::new(read).lines();
+            lines.next_line().await.unwrap();
+            write_line(
+                &mut write,
+                &ServerMessage::Welcome {
+                    version: PROTOCOL_VERSION,
+                },
+            )
+            .await
+            .unwrap();
+            // Reads the request, then hangs up without answering
+            lines.next_line().await.unwrap();
+        });
+        let connection = Connection::open(client).await.unwrap();
+
+        let result = connection.call(Request::Status).await;
+        server.await.unwrap();
+
+        assert!(matches!(result, Err(VpnError::ManagerUnavailable)));
+        assert!(matches!(
+            connection.call(Request::Status).await,
+            Err(VpnError::ManagerUnavailable)
+        ));
+    }
+}
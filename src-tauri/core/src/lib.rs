@@ -0,0 +1,56 @@
+//! Everything GekkoVPN does besides drawing it: the openvpn tunnel, credentials,
+//! network adapters, logs and usage. Front-ends start a [`VpnClient`] with a
+//! [`Backend`] and receive its events through an [`EventSink`]:
+//!
+//! ```no_run
+//! use gekkovpn_core::{Backend, Broadcast, Events, VpnClient};
+//! use std::sync::Arc;
+//!
+//! # async fn run() -> Result<(), gekkovpn_core::VpnError> {
+//! let broadcast = Broadcast::default();
+//! let mut updates = broadcast.subscribe();
+//! let client = VpnClient::start(Backend::system(Events::new(Arc::new(broadcast))));
+//! client.connect("nl1".to_string(), "alice".to_string()).await?;
+//! while let Ok(event) = updates.recv().await {
+//!     println!("{}: {}", event.name, event.payload);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod adapter;
+pub mod catalog;
+pub mod client;
+pub mod connection;
+pub mod credentials;
+pub mod diagnosis;
+pub mod diagnostics;
+pub mod error;
+pub mod events;
+pub mod ipc;
+#[cfg(target_os = "linux")]
+pub mod linux;
+pub mod logbuffer;
+pub mod logging;
+pub mod logparser;
+pub mod management;
+pub mod paths;
+pub mod redact;
+pub mod runner;
+pub mod service;
+pub mod shutdown;
+pub mod states;
+pub mod supervisor;
+#[cfg(any(windows, test))]
+pub mod tapadapter;
+#[cfg(test)]
+mod testing;
+pub mod traffic;
+#[cfg(target_os = "linux")]
+pub mod tunadapter;
+pub mod usage;
+
+pub use client::{Backend, ConnectionInfo, VpnClient};
+pub use error::VpnError;
+pub use events::{Broadcast, Event, EventSink, Events};
+pub use service::Service;
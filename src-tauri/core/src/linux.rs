@@ -0,0 +1,143 @@
+use crate::error::VpnError;
+use crate::runner::CommandRunner;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::debug;
+
+/// Scripts that apply the DNS servers pushed by the server, in order of preference.
+/// openvpn does not touch DNS by itself on Linux.
+const DNS_SCRIPTS: &[&str] = &[
+    "/etc/openvpn/update-systemd-resolved",
+    "/etc/openvpn/update-resolv-conf",
+    "/etc/openvpn/scripts/update-systemd-resolved",
+];
+
+/// getcap usually lives in sbin, which is not on every user's PATH
+const GETCAP: &[&str] = &["getcap", "/usr/sbin/getcap", "/sbin/getcap"];
+
+const GETCAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How openvpn gets the rights to open the tun device and change routes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Privilege {
+    /// The app already runs as root
+    Root,
+    /// The openvpn binary has been given CAP_NET_ADMIN with setcap
+    Capabilities,
+    /// openvpn is started through pkexec, which asks for a password via polkit
+    Pkexec(PathBuf),
+}
+
+/// Picks how to start `openvpn`, preferring what does not prompt the user
+pub fn privilege(runner: &dyn CommandRunner, openvpn: &Path) -> Result<Privilege, VpnError> {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    if effective_uid(&status) == Some(0) {
+        return Ok(Privilege::Root);
+    }
+
+    let openvpn_arg = openvpn.to_string_lossy();
+    for getcap in GETCAP {
+        if let Ok(output) = runner.output(Path::new(getcap), &[&openvpn_arg], GETCAP_TIMEOUT) {
+            if has_net_admin(&output.stdout) {
+                return Ok(Privilege::Capabilities);
+            }
+            break;
+        }
+    }
+
+    find_program("pkexec")
+        .map(Privilege::Pkexec)
+        .ok_or(VpnError::AdminRequired)
+}
+
+/// Command that starts `openvpn` with `privilege`. pkexec execs the program, so the
+/// child is openvpn itself and it can still be asked to exit over the management interface.
+pub fn command(openvpn: &Path, privilege: &Privilege) -> Command {
+    debug!(?privilege, "Starting OpenVPN");
+    match privilege {
+        Privilege::Pkexec(pkexec) => {
+            let mut command = Command::new(pkexec);
+            command.arg(openvpn);
+            command
+        }
+        Privilege::Root | Privilege::Capabilities => Command::new(openvpn),
+    }
+}
+
+/// Whether `pid` is still pkexec, which asks for the password through polkit
+/// before it execs openvpn
+pub fn authenticating(pid: u32) -> bool {
+    std::fs::read_to_string(format!("/proc/{}/comm", pid))
+        .is_ok_and(|comm| comm.trim_end() == "pkexec")
+}
+
+/// openvpn arguments that run the DNS script, if one is installed
+pub fn dns_args() -> Vec<String> {
+    let Some(&script) = DNS_SCRIPTS.iter().find(|script| Path::new(script).exists()) else {
+        return Vec::new();
+    };
+    [
+        "--script-security",
+        "2",
+        "--up",
+        script,
+        "--down",
+        script,
+        "--down-pre",
+    ]
+    .map(str::to_string)
+    .to_vec()
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .chain([PathBuf::from("/usr/bin")])
+        .map(|dir| dir.join(name))
+        .find(|path| path.exists())
+}
+
+/// The effective uid from the `Uid:` line of `/proc/<pid>/status`
+fn effective_uid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|uids| uids.split_whitespace().nth(1))
+        .and_then(|uid| uid.parse().ok())
+}
+
+/// Whether getcap output grants CAP_NET_ADMIN, e.g.
+/// `/usr/sbin/openvpn cap_net_admin,cap_net_raw=ep` or `/usr/sbin/openvpn = cap_net_admin+ep`
+fn has_net_admin(getcap: &str) -> bool {
+    getcap
+        .split_whitespace()
+        .filter_map(|clause| clause.split_once(['=', '+']))
+        .any(|(caps, flags)| {
+            caps.split(',').any(|cap| cap == "cap_net_admin") && flags.contains('e')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_effective_uid() {
+        let status = "Name:\tgekkovpn\nUmask:\t0022\nState:\tS (sleeping)\nUid:\t1000\t0\t0\t0\nGid:\t1000\t1000\t1000\t1000\n";
+        assert_eq!(effective_uid(status), Some(0));
+        assert_eq!(effective_uid("Name:\tgekkovpn\n"), None);
+    }
+
+    #[test]
+    fn detects_cap_net_admin_in_getcap_output() {
+        assert!(has_net_admin(
+            "/usr/sbin/openvpn cap_net_admin,cap_net_raw=ep\n"
+        ));
+        assert!(has_net_admin("/usr/sbin/openvpn = cap_net_admin+ep\n"));
+        assert!(!has_net_admin("/usr/sbin/openvpn cap_net_raw=ep\n"));
+        assert!(!has_net_admin(""));
+    }
+}
@@ -0,0 +1,326 @@
+use crate::events::Events;
+use crate::logparser;
+use crate::redact;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Event emitted to the frontend for every line added to the buffer
+pub const LOG_EVENT: &str = "vpn-log";
+
+/// Number of lines kept; older lines are dropped first
+pub const LOG_CAPACITY: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSource {
+    OpenVpn,
+    App,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// Severity of a line openvpn printed, which carries no level of its own
+    fn of_openvpn_line(line: &str) -> Self {
+        if logparser::parse_line(line).is_failure() {
+            Severity::Error
+        } else if line.contains("WARNING") {
+            Severity::Warning
+        } else {
+            Severity::Info
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Debug => "DEBUG",
+            Severity::Info => "INFO",
+            Severity::Warning => "WARN",
+            Severity::Error => "ERROR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Increases by one per line, so a viewer can ask for what it has not seen yet
+    pub seq: u64,
+    /// Unix timestamp in milliseconds
+    pub timestamp: u64,
+    pub source: LogSource,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// The line as exported, e.g. `2024-05-14T09:12:02.123Z INFO  [openvpn] Initialization Sequence Completed`
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<5} [{}] {}",
+            format_timestamp(self.timestamp),
+            self.severity.label(),
+            match self.source {
+                LogSource::OpenVpn => "openvpn",
+                LogSource::App => "app",
+            },
+            self.message
+        )
+    }
+}
+
+/// Which lines `get_vpn_logs` returns. Every field is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFilter {
+    pub min_severity: Option<Severity>,
+    pub source: Option<LogSource>,
+    /// Case-insensitive text the message must contain
+    pub contains: Option<String>,
+    /// Only lines after this sequence number
+    pub after_seq: Option<u64>,
+    /// Only the newest lines, up to this many
+    pub limit: Option<usize>,
+}
+
+impl LogFilter {
+    fn matches(&self, record: &LogRecord, contains: Option<&str>) -> bool {
+        self.min_severity.map_or(true, |min| record.severity >= min)
+            && self.source.map_or(true, |source| record.source == source)
+            && self.after_seq.map_or(true, |seq| record.seq > seq)
+            && contains.map_or(true, |text| record.message.to_lowercase().contains(text))
+    }
+}
+
+/// The last `capacity` log lines, oldest first
+#[derive(Debug)]
+pub struct LogRing {
+    capacity: usize,
+    next_seq: u64,
+    lines: VecDeque<LogRecord>,
+}
+
+impl LogRing {
+    pub fn new(capacity: usize) -> Self {
+        LogRing {
+            capacity,
+            next_seq: 1,
+            lines: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        source: LogSource,
+        severity: Severity,
+        message: String,
+        now: SystemTime,
+    ) -> LogRecord {
+        let record = LogRecord {
+            seq: self.next_seq,
+            timestamp: now
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            source,
+            severity,
+            message,
+        };
+        self.next_seq += 1;
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(record.clone());
+        record
+    }
+
+    pub fn query(&self, filter: &LogFilter) -> Vec<LogRecord> {
+        let contains = filter.contains.as_ref().map(|text| text.to_lowercase());
+        let mut lines: Vec<LogRecord> = self
+            .lines
+            .iter()
+            .filter(|record| filter.matches(record, contains.as_deref()))
+            .cloned()
+            .collect();
+        if let Some(limit) = filter.limit {
+            lines.drain(..lines.len().saturating_sub(limit));
+        }
+        lines
+    }
+
+    /// All lines as plain text, one per line
+    pub fn export(&self) -> String {
+        let mut text = String::new();
+        for record in &self.lines {
+            let _ = writeln!(text, "{}", record);
+        }
+        text
+    }
+}
+
+/// Recent openvpn and app log lines, shared by the manager and the openvpn readers.
+/// Lines are redacted when added, and then also streamed to the frontend.
+#[derive(Clone)]
+pub struct LogBuffer {
+    events: Events,
+    ring: Arc<Mutex<LogRing>>,
+}
+
+impl LogBuffer {
+    pub fn new(events: Events) -> Self {
+        LogBuffer {
+            events,
+            ring: Arc::new(Mutex::new(LogRing::new(LOG_CAPACITY))),
+        }
+    }
+
+    fn push(&self, source: LogSource, severity: Severity, message: String) {
+        let record = self.ring.lock().unwrap().push(
+            source,
+            severity,
+            redact::redact(&message),
+            SystemTime::now(),
+        );
+        let _ = self.events.emit(LOG_EVENT, record);
+    }
+
+    /// Adds a line openvpn printed to stdout or stderr
+    pub fn openvpn(&self, line: &str) {
+        self.push(
+            LogSource::OpenVpn,
+            Severity::of_openvpn_line(line),
+            line.to_string(),
+        );
+    }
+
+    /// Adds a line about what the app itself did
+    pub fn app(&self, severity: Severity, message: impl Into<String>) {
+        self.push(LogSource::App, severity, message.into());
+    }
+
+    pub fn query(&self, filter: &LogFilter) -> Vec<LogRecord> {
+        self.ring.lock().unwrap().query(filter)
+    }
+
+    pub fn export(&self) -> String {
+        self.ring.lock().unwrap().export()
+    }
+}
+
+/// `YYYY-MM-DDTHH:MM:SS.mmmZ` of a Unix timestamp in milliseconds
+fn format_timestamp(millis: u64) -> String {
+    let time = UNIX_EPOCH + Duration::from_millis(millis);
+    let secs = millis / 1_000;
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        crate::usage::day_key(time),
+        secs / 3_600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        millis % 1_000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    fn fill(ring: &mut LogRing, fixture: &str) {
+        for (i, line) in fixture.lines().enumerate() {
+            ring.push(
+                LogSource::OpenVpn,
+                Severity::of_openvpn_line(line),
+                line.to_string(),
+                at(1_715_677_920_000 + i as u64),
+            );
+        }
+    }
+
+    #[test]
+    fn drops_the_oldest_lines_when_full() {
+        let mut ring = LogRing::new(3);
+        for i in 1..=5 {
+            ring.push(LogSource::App, Severity::Info, format!("line {}", i), at(i));
+        }
+
+        let lines = ring.query(&LogFilter::default());
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].seq, 3);
+        assert_eq!(lines[0].message, "line 3");
+        assert_eq!(lines[2].seq, 5);
+    }
+
+    #[test]
+    fn filters_by_severity_source_text_and_position() {
+        let mut ring = LogRing::new(LOG_CAPACITY);
+        fill(
+            &mut ring,
+            include_str!("../tests/fixtures/openvpn/auth_failed.log"),
+        );
+        ring.push(
+            LogSource::App,
+            Severity::Error,
+            "Connection failed".to_string(),
+            at(1_715_677_930_000),
+        );
+
+        let errors = ring.query(&LogFilter {
+            min_severity: Some(Severity::Error),
+            source: Some(LogSource::OpenVpn),
+            ..LogFilter::default()
+        });
+        assert!(!errors.is_empty());
+        assert!(errors.iter().all(|r| r.source == LogSource::OpenVpn));
+        assert!(errors.iter().any(|r| r.message.contains("AUTH_FAILED")));
+
+        let matching = ring.query(&LogFilter {
+            contains: Some("connection FAILED".to_string()),
+            ..LogFilter::default()
+        });
+        assert_eq!(matching.len(), 1);
+        assert_eq!(matching[0].source, LogSource::App);
+
+        let newest = ring.query(&LogFilter {
+            limit: Some(2),
+            ..LogFilter::default()
+        });
+        assert_eq!(newest.len(), 2);
+        assert_eq!(newest[1].message, "Connection failed");
+
+        let unseen = ring.query(&LogFilter {
+            after_seq: Some(newest[0].seq),
+            ..LogFilter::default()
+        });
+        assert_eq!(unseen, newest[1..]);
+    }
+
+    #[test]
+    fn exports_one_line_per_record() {
+        let mut ring = LogRing::new(LOG_CAPACITY);
+        ring.push(
+            LogSource::OpenVpn,
+            Severity::Warning,
+            "WARNING: --ping should normally be used with --ping-restart".to_string(),
+            at(1_715_677_921_042),
+        );
+
+        assert_eq!(
+            ring.export(),
+            "2024-05-14T09:12:01.042Z WARN  [openvpn] WARNING: --ping should normally be used with --ping-restart\n"
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::redact;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+const LOG_FILE_STEM: &str = "gekkovpn";
+
+/// A log file is moved aside once it grows past this
+const MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Rotated files kept next to the current one
+const KEPT_LOG_FILES: usize = 4;
+
+/// How much the app logs, and how much openvpn logs with it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Verbosity {
+    fn level_filter(self) -> LevelFilter {
+        match self {
+            Verbosity::Error => LevelFilter::ERROR,
+            Verbosity::Warn => LevelFilter::WARN,
+            Verbosity::Info => LevelFilter::INFO,
+            Verbosity::Debug => LevelFilter::DEBUG,
+            Verbosity::Trace => LevelFilter::TRACE,
+        }
+    }
+
+    /// openvpn's `--verb`. 3 is its recommended default, 5 and up log every packet.
+    pub fn openvpn_verb(self) -> u8 {
+        match self {
+            Verbosity::Error => 1,
+            Verbosity::Warn => 2,
+            Verbosity::Info => 3,
+            Verbosity::Debug => 4,
+            Verbosity::Trace => 6,
+        }
+    }
+}
+
+struct Logging {
+    filter: reload::Handle<LevelFilter, Registry>,
+    verbosity: Mutex<Verbosity>,
+}
+
+static LOGGING: OnceLock<Logging> = OnceLock::new();
+
+/// Logs to stdout and, given a directory, to size-rotated files in it.
+/// Everything written is redacted first.
+pub fn init(dir: Option<&Path>) {
+    let verbosity = Verbosity::default();
+    let (filter, handle) = reload::Layer::new(verbosity.level_filter());
+
+    let file = dir.and_then(|dir| {
+        RotatingFile::open(dir, MAX_LOG_FILE_BYTES, KEPT_LOG_FILES)
+            .map_err(|e| eprintln!("Logging to {:?} is not possible: {}", dir, e))
+            .ok()
+    });
+    let file_layer = file.map(|file| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(Redacted(file)))
+    });
+
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(|| Redacted(io::stdout())))
+        .with(file_layer)
+        .try_init();
+    if let Err(e) = installed {
+        eprintln!("Logging was already set up: {}", e);
+        return;
+    }
+
+    let _ = LOGGING.set(Logging {
+        filter: handle,
+        verbosity: Mutex::new(verbosity),
+    });
+}
+
+pub fn verbosity() -> Verbosity {
+    LOGGING
+        .get()
+        .map(|logging| *logging.verbosity.lock().unwrap())
+        .unwrap_or_default()
+}
+
+pub fn set_verbosity(verbosity: Verbosity) -> Result<(), String> {
+    let logging = LOGGING
+        .get()
+        .ok_or_else(|| "Logging is not set up".to_string())?;
+    logging
+        .filter
+        .reload(verbosity.level_filter())
+        .map_err(|e| format!("Failed to change the log level: {}", e))?;
+    *logging.verbosity.lock().unwrap() = verbosity;
+    Ok(())
+}
+
+/// Redacts what is written to `W`. The fmt layer writes each event in one go,
+/// so a secret is never split across writes.
+struct Redacted<W>(W);
+
+impl<W: Write> Write for Redacted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .write_all(redact::redact(&String::from_utf8_lossy(buf)).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// `gekkovpn.log`, moved to `gekkovpn.1.log` once it reaches `max_bytes`. Older
+/// files shift up to `gekkovpn.<keep>.log` and are then deleted.
+struct RotatingFile {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut file = RotatingFile {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            keep,
+            file: None,
+            size: 0,
+        };
+        file.reopen()?;
+        Ok(file)
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        if index == 0 {
+            self.dir.join(format!("{}.log", LOG_FILE_STEM))
+        } else {
+            self.dir.join(format!("{}.{}.log", LOG_FILE_STEM, index))
+        }
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(0))?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Windows cannot rename a file that is still open
+        self.file = None;
+        let _ = fs::remove_file(self.path(self.keep));
+        for index in (0..self.keep).rev() {
+            let from = self.path(index);
+            if from.exists() {
+                fs::rename(from, self.path(index + 1))?;
+            }
+        }
+        if self.keep == 0 {
+            fs::remove_file(self.path(0))?;
+        }
+        self.reopen()
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            self.reopen()?;
+        }
+        let file = self.file.as_mut().expect("log file was just opened");
+        file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_by_size_and_keeps_a_bounded_number_of_files() {
+        let dir = std::env::temp_dir().join(format!("gekkovpn-logs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut file = RotatingFile::open(&dir, 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("gekkovpn.log"), "fourth\n");
+        assert_eq!(read("gekkovpn.1.log"), "third\n");
+        assert_eq!(read("gekkovpn.2.log"), "second\n");
+        assert!(!dir.join("gekkovpn.3.log").exists());
+
+        // Appends to what is already there after a restart
+        let mut file = RotatingFile::open(&dir, 100, 2).unwrap();
+        file.write_all(b"fifth\n").unwrap();
+        assert_eq!(read("gekkovpn.log"), "fourth\nfifth\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn redacts_what_is_written() {
+        let mut output = Redacted(Vec::new());
+        output
+            .write_all(b"link remote: [AF_INET]185.107.56.21:1194\n")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.0).unwrap(),
+            "link remote: [AF_INET]185.107.x.x:1194\n"
+        );
+    }
+}
@@ -0,0 +1,630 @@
+use serde::Serialize;
+use std::fmt;
+
+/// A route openvpn added to the system routing table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Route {
+    /// Network in CIDR (`0.0.0.0/1`) or plain (`0.0.0.0`) form
+    pub network: String,
+    pub netmask: Option<String>,
+    pub gateway: Option<String>,
+}
+
+/// Options from a server `PUSH_REPLY` that matter to the client
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PushReply {
+    pub local_ip: Option<String>,
+    pub netmask: Option<String>,
+    pub local_ipv6: Option<String>,
+    pub gateway: Option<String>,
+    pub dns_servers: Vec<String>,
+    pub cipher: Option<String>,
+    pub routes: Vec<Route>,
+    pub redirect_gateway: bool,
+}
+
+impl PushReply {
+    /// Parses the comma separated option list after `PUSH_REPLY,`
+    pub fn parse(options: &str) -> Self {
+        let mut reply = PushReply::default();
+
+        for option in options.split(',') {
+            let words: Vec<&str> = option.split_whitespace().collect();
+            match words.as_slice() {
+                ["ifconfig", local, netmask, ..] => {
+                    reply.local_ip = Some(local.to_string());
+                    reply.netmask = Some(netmask.to_string());
+                }
+                ["ifconfig-ipv6", local, ..] => reply.local_ipv6 = Some(local.to_string()),
+                ["route-gateway", gateway, ..] => reply.gateway = Some(gateway.to_string()),
+                ["dhcp-option", "DNS" | "DNS6", server, ..] => {
+                    reply.dns_servers.push(server.to_string())
+                }
+                ["dns", "server", _, "address", servers @ ..] => reply
+                    .dns_servers
+                    .extend(servers.iter().map(|s| s.to_string())),
+                ["cipher", cipher, ..] => reply.cipher = Some(cipher.to_string()),
+                ["route", network, rest @ ..] => reply.routes.push(Route {
+                    network: network.to_string(),
+                    netmask: rest.first().map(|m| m.to_string()),
+                    gateway: rest
+                        .get(1)
+                        .filter(|g| **g != "vpn_gateway")
+                        .map(|g| g.to_string()),
+                }),
+                ["redirect-gateway", ..] => reply.redirect_gateway = true,
+                _ => {}
+            }
+        }
+        reply
+    }
+}
+
+/// A single openvpn log line, classified
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "details", rename_all = "snake_case")]
+pub enum LogLine {
+    /// TLS handshake or control channel failure
+    TlsError(String),
+    /// Certificate verification failed, e.g. expired or not yet valid
+    VerifyError {
+        depth: Option<u32>,
+        error: String,
+    },
+    ResolveFailed {
+        host: String,
+        error: String,
+    },
+    /// Data channel cipher agreed with the server
+    CipherNegotiated(String),
+    /// No common data channel cipher, or packets failing to decrypt
+    CipherMismatch(String),
+    PushReply(PushReply),
+    RouteAdded(Route),
+    AssignedIp {
+        address: String,
+        netmask: Option<String>,
+    },
+    AuthFailed(String),
+    /// No usable TUN/TAP adapter
+    AdapterError(String),
+    /// Could not bind the local socket, usually because the port is taken
+    BindFailed(String),
+    Restarting {
+        signal: String,
+        reason: String,
+    },
+    Exiting {
+        signal: Option<String>,
+        reason: String,
+    },
+    InitializationCompleted {
+        with_errors: bool,
+    },
+    /// Transport and address of the server openvpn is talking to
+    LinkRemote {
+        protocol: String,
+        address: String,
+    },
+    /// First packet received from the server, so it is reachable
+    InitialPacket {
+        from: String,
+    },
+    Other(String),
+}
+
+impl LogLine {
+    /// Whether this line explains why a connection could fail
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            LogLine::TlsError(_)
+                | LogLine::VerifyError { .. }
+                | LogLine::ResolveFailed { .. }
+                | LogLine::CipherMismatch(_)
+                | LogLine::AuthFailed(_)
+                | LogLine::AdapterError(_)
+                | LogLine::BindFailed(_)
+        )
+    }
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLine::TlsError(message) => write!(f, "TLS error: {}", message),
+            LogLine::VerifyError { depth, error } => match depth {
+                Some(depth) => write!(f, "certificate error at depth {}: {}", depth, error),
+                None => write!(f, "certificate error: {}", error),
+            },
+            LogLine::ResolveFailed { host, error } => {
+                write!(f, "could not resolve {}: {}", host, error)
+            }
+            LogLine::CipherNegotiated(cipher) => write!(f, "data channel cipher {}", cipher),
+            LogLine::CipherMismatch(message) => write!(f, "cipher mismatch: {}", message),
+            LogLine::PushReply(reply) => {
+                write!(
+                    f,
+                    "server pushed ip {} netmask {}",
+                    reply.local_ip.as_deref().unwrap_or("-"),
+                    reply.netmask.as_deref().unwrap_or("-")
+                )?;
+                if let Some(ipv6) = &reply.local_ipv6 {
+                    write!(f, ", ipv6 {}", ipv6)?;
+                }
+                if let Some(gateway) = &reply.gateway {
+                    write!(f, ", gateway {}", gateway)?;
+                }
+                if !reply.dns_servers.is_empty() {
+                    write!(f, ", dns {}", reply.dns_servers.join(" "))?;
+                }
+                if let Some(cipher) = &reply.cipher {
+                    write!(f, ", cipher {}", cipher)?;
+                }
+                write!(f, ", {} route(s)", reply.routes.len())?;
+                if reply.redirect_gateway {
+                    write!(f, ", redirect-gateway")?;
+                }
+                Ok(())
+            }
+            LogLine::RouteAdded(route) => {
+                write!(f, "route {}", route.network)?;
+                if let Some(netmask) = &route.netmask {
+                    write!(f, " mask {}", netmask)?;
+                }
+                if let Some(gateway) = &route.gateway {
+                    write!(f, " via {}", gateway)?;
+                }
+                Ok(())
+            }
+            LogLine::AssignedIp { address, netmask } => match netmask {
+                Some(netmask) => write!(f, "assigned {} netmask {}", address, netmask),
+                None => write!(f, "assigned {}", address),
+            },
+            LogLine::AuthFailed(message) => write!(f, "authentication failed: {}", message),
+            LogLine::AdapterError(message) => write!(f, "adapter error: {}", message),
+            LogLine::BindFailed(message) => write!(f, "socket bind failed: {}", message),
+            LogLine::Restarting { signal, reason } => {
+                write!(f, "restarting ({} {})", signal, reason)
+            }
+            LogLine::Exiting { signal, reason } => match signal {
+                Some(signal) => write!(f, "exiting ({} {})", signal, reason),
+                None => write!(f, "exiting ({})", reason),
+            },
+            LogLine::InitializationCompleted { with_errors } => {
+                if *with_errors {
+                    write!(f, "initialization completed with errors")
+                } else {
+                    write!(f, "initialization completed")
+                }
+            }
+            LogLine::LinkRemote { protocol, address } => {
+                write!(f, "{} link to {}", protocol, address)
+            }
+            LogLine::InitialPacket { from } => write!(f, "initial packet from {}", from),
+            LogLine::Other(line) => write!(f, "{}", line),
+        }
+    }
+}
+
+/// Strips the `2024-05-14 09:12:03 ` or `Tue May 14 09:12:03 2024 ` prefix
+/// openvpn puts in front of lines written to stdout or a log file
+fn strip_timestamp(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let is_iso = bytes.len() > 20
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && bytes[10] == b' '
+        && bytes[13] == b':'
+        && bytes[16] == b':'
+        && bytes[19] == b' '
+        && bytes[..4].iter().all(u8::is_ascii_digit);
+    if is_iso {
+        return &line[20..];
+    }
+
+    let is_ctime = bytes.len() > 25
+        && bytes[3] == b' '
+        && bytes[7] == b' '
+        && bytes[13] == b':'
+        && bytes[16] == b':'
+        && bytes[24] == b' '
+        && bytes[20..24].iter().all(u8::is_ascii_digit);
+    if is_ctime {
+        return &line[25..];
+    }
+    line
+}
+
+/// Drops the `[AF_INET]` in front of addresses openvpn prints
+fn strip_address_family(address: &str) -> &str {
+    match address.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(address, |(_, address)| address),
+        None => address,
+    }
+}
+
+/// Parses `SIGUSR1[soft,ping-restart]` into the signal and its reason
+fn parse_signal(text: &str) -> Option<(String, String)> {
+    let (signal, rest) = text.split_once('[')?;
+    let (details, _) = rest.split_once(']')?;
+    let reason = match details.split_once(',') {
+        Some((kind, "")) => kind,
+        Some((_, reason)) => reason,
+        None => details,
+    };
+    Some((signal.trim().to_string(), reason.to_string()))
+}
+
+/// Classifies one openvpn log line, with or without its timestamp
+pub fn parse_line(line: &str) -> LogLine {
+    let line = strip_timestamp(line.trim());
+
+    if let Some(rest) = line.strip_prefix("VERIFY ERROR: ") {
+        let (depth, error) = match rest.split_once(", error=") {
+            Some((depth, error)) => (
+                depth.trim_start_matches("depth=").parse().ok(),
+                error.to_string(),
+            ),
+            None => (None, rest.to_string()),
+        };
+        return LogLine::VerifyError { depth, error };
+    }
+    if line.starts_with("TLS Error:") || line.starts_with("TLS_ERROR:") {
+        let (_, message) = line.split_once(':').unwrap();
+        return LogLine::TlsError(message.trim().to_string());
+    }
+    if line.starts_with("OpenSSL:") && line.contains("error") {
+        return LogLine::TlsError(line.to_string());
+    }
+    if let Some((protocol, address)) = line.split_once(" link remote: ") {
+        return LogLine::LinkRemote {
+            protocol: protocol.to_string(),
+            address: strip_address_family(address).to_string(),
+        };
+    }
+    if let Some(rest) = line.strip_prefix("TLS: Initial packet from ") {
+        let address = rest.split(',').next().unwrap_or(rest);
+        return LogLine::InitialPacket {
+            from: strip_address_family(address).to_string(),
+        };
+    }
+    if let Some(rest) = line.strip_prefix("RESOLVE: Cannot resolve host address: ") {
+        let (host, error) = match rest.split_once(" (") {
+            Some((host, error)) => (host, error.trim_end_matches(')')),
+            None => (rest, ""),
+        };
+        return LogLine::ResolveFailed {
+            host: host.to_string(),
+            error: error.to_string(),
+        };
+    }
+    if line.contains("failed to negotiate cipher")
+        || line.starts_with("AEAD Decrypt error")
+        || line.starts_with("Authenticate/Decrypt packet error")
+    {
+        return LogLine::CipherMismatch(line.to_string());
+    }
+    if let Some(rest) = line.strip_prefix("Data Channel: cipher '") {
+        if let Some((cipher, _)) = rest.split_once('\'') {
+            return LogLine::CipherNegotiated(cipher.to_string());
+        }
+    }
+    if let Some(rest) = line
+        .strip_prefix("Outgoing Data Channel: Cipher '")
+        .or_else(|| line.strip_prefix("Incoming Data Channel: Cipher '"))
+    {
+        if let Some((cipher, _)) = rest.split_once('\'') {
+            return LogLine::CipherNegotiated(cipher.to_string());
+        }
+    }
+    if let Some(index) = line.find("PUSH_REPLY,") {
+        let options = line[index + "PUSH_REPLY,".len()..].trim_end_matches('\'');
+        return LogLine::PushReply(PushReply::parse(options));
+    }
+    if let Some(route) = parse_route(line) {
+        return LogLine::RouteAdded(route);
+    }
+    if let Some(assigned) = parse_assigned_ip(line) {
+        return assigned;
+    }
+    if line.contains("AUTH_FAILED") {
+        let reason = line
+            .split_once("AUTH_FAILED")
+            .map(|(_, reason)| reason.trim_start_matches(',').trim())
+            .filter(|reason| !reason.is_empty())
+            .unwrap_or("credentials rejected by the server");
+        return LogLine::AuthFailed(reason.to_string());
+    }
+    if line.contains("Cannot open TUN/TAP dev")
+        || line.contains("adapters on this system")
+        || (line.contains("TAP-Windows adapter") && line.contains("not found"))
+    {
+        return LogLine::AdapterError(line.to_string());
+    }
+    if line.contains("Socket bind failed") || line.contains("WSAEADDRINUSE") {
+        return LogLine::BindFailed(line.to_string());
+    }
+    if let Some(rest) = line.strip_suffix(" received, process restarting") {
+        if let Some((signal, reason)) = parse_signal(rest) {
+            return LogLine::Restarting { signal, reason };
+        }
+    }
+    if let Some(rest) = line.strip_suffix(" received, process exiting") {
+        if let Some((signal, reason)) = parse_signal(rest) {
+            return LogLine::Exiting {
+                signal: Some(signal),
+                reason,
+            };
+        }
+    }
+    if line.starts_with("Exiting due to fatal error") {
+        return LogLine::Exiting {
+            signal: None,
+            reason: "fatal error".to_string(),
+        };
+    }
+    if line.starts_with("Initialization Sequence Completed") {
+        return LogLine::InitializationCompleted {
+            with_errors: line.contains("With Errors"),
+        };
+    }
+
+    LogLine::Other(line.to_string())
+}
+
+fn parse_route(line: &str) -> Option<Route> {
+    // Windows: C:\WINDOWS\system32\route.exe ADD 0.0.0.0 MASK 128.0.0.0 10.8.0.1
+    if let Some(index) = line.find("route.exe ADD ") {
+        let words: Vec<&str> = line[index + "route.exe ADD ".len()..]
+            .split_whitespace()
+            .collect();
+        return match words.as_slice() {
+            [network, "MASK", netmask, gateway, ..] => Some(Route {
+                network: network.to_string(),
+                netmask: Some(netmask.to_string()),
+                gateway: Some(gateway.to_string()),
+            }),
+            _ => None,
+        };
+    }
+
+    // Linux: net_route_v4_add: 0.0.0.0/1 via 10.8.0.1 dev [NULL] table 0 metric -1
+    //    or: /sbin/ip route add 0.0.0.0/1 via 10.8.0.1
+    let rest = line
+        .strip_prefix("net_route_v4_add: ")
+        .or_else(|| line.strip_prefix("net_route_v6_add: "))
+        .or_else(|| line.split_once("ip route add ").map(|(_, rest)| rest))?;
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    let network = words.first()?;
+    let gateway = words
+        .iter()
+        .position(|w| *w == "via")
+        .and_then(|i| words.get(i + 1));
+    Some(Route {
+        network: network.to_string(),
+        netmask: None,
+        gateway: gateway.map(|g| g.to_string()),
+    })
+}
+
+fn parse_assigned_ip(line: &str) -> Option<LogLine> {
+    // Linux: net_addr_v4_add: 10.8.0.2/24 dev tun0
+    if let Some(rest) = line
+        .strip_prefix("net_addr_v4_add: ")
+        .or_else(|| line.strip_prefix("net_addr_v6_add: "))
+    {
+        let address = rest.split_whitespace().next()?;
+        return Some(LogLine::AssignedIp {
+            address: address.to_string(),
+            netmask: None,
+        });
+    }
+
+    // Windows, subnet topology:
+    // Set TAP-Windows TUN subnet mode network/local/netmask = 10.8.0.0/10.8.0.2/255.255.255.0 [SUCCEEDED]
+    if let Some((_, rest)) = line.split_once("network/local/netmask = ") {
+        let triple = rest.split_whitespace().next()?;
+        let parts: Vec<&str> = triple.split('/').collect();
+        if let [_, local, netmask] = parts.as_slice() {
+            return Some(LogLine::AssignedIp {
+                address: local.to_string(),
+                netmask: Some(netmask.to_string()),
+            });
+        }
+    }
+
+    // Windows, DHCP: Notified TAP-Windows driver to set a DHCP IP/netmask of 10.8.0.2/255.255.255.0 on interface ...
+    if let Some((_, rest)) = line.split_once("DHCP IP/netmask of ") {
+        let pair = rest.split_whitespace().next()?;
+        let (address, netmask) = pair.split_once('/')?;
+        return Some(LogLine::AssignedIp {
+            address: address.to_string(),
+            netmask: Some(netmask.to_string()),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_fixture(log: &str) -> Vec<LogLine> {
+        log.lines().map(parse_line).collect()
+    }
+
+    #[test]
+    fn strips_both_timestamp_formats() {
+        assert_eq!(
+            parse_line("2024-05-14 09:12:03 Initialization Sequence Completed"),
+            LogLine::InitializationCompleted { with_errors: false }
+        );
+        assert_eq!(
+            parse_line("Tue May 14 09:12:03 2024 Initialization Sequence Completed With Errors"),
+            LogLine::InitializationCompleted { with_errors: true }
+        );
+    }
+
+    #[test]
+    fn parses_a_successful_linux_connection() {
+        let lines = parse_fixture(include_str!(
+            "../tests/fixtures/openvpn/connected_linux.log"
+        ));
+
+        assert!(lines.contains(&LogLine::CipherNegotiated("AES-256-GCM".to_string())));
+        assert!(lines.contains(&LogLine::AssignedIp {
+            address: "10.8.0.2/24".to_string(),
+            netmask: None,
+        }));
+        assert!(lines.contains(&LogLine::RouteAdded(Route {
+            network: "0.0.0.0/1".to_string(),
+            netmask: None,
+            gateway: Some("10.8.0.1".to_string()),
+        })));
+        assert!(lines.contains(&LogLine::InitializationCompleted { with_errors: false }));
+        assert!(lines.contains(&LogLine::LinkRemote {
+            protocol: "UDPv4".to_string(),
+            address: "185.107.56.21:1194".to_string(),
+        }));
+        assert!(lines.contains(&LogLine::InitialPacket {
+            from: "185.107.56.21:1194".to_string(),
+        }));
+
+        let push = lines
+            .iter()
+            .find_map(|line| match line {
+                LogLine::PushReply(reply) => Some(reply),
+                _ => None,
+            })
+            .expect("PUSH_REPLY should be parsed");
+        assert_eq!(push.local_ip.as_deref(), Some("10.8.0.2"));
+        assert_eq!(push.netmask.as_deref(), Some("255.255.255.0"));
+        assert_eq!(push.local_ipv6.as_deref(), Some("fd00:8::1000/64"));
+        assert_eq!(push.gateway.as_deref(), Some("10.8.0.1"));
+        assert_eq!(push.dns_servers, vec!["10.8.0.1", "1.1.1.1"]);
+        assert_eq!(push.cipher.as_deref(), Some("AES-256-GCM"));
+        assert!(push.redirect_gateway);
+        assert!(!lines.iter().any(LogLine::is_failure));
+    }
+
+    #[test]
+    fn parses_a_successful_windows_connection() {
+        let lines = parse_fixture(include_str!(
+            "../tests/fixtures/openvpn/connected_windows.log"
+        ));
+
+        assert!(lines.contains(&LogLine::AssignedIp {
+            address: "10.8.0.6".to_string(),
+            netmask: Some("255.255.255.0".to_string()),
+        }));
+        assert!(lines.contains(&LogLine::RouteAdded(Route {
+            network: "128.0.0.0".to_string(),
+            netmask: Some("128.0.0.0".to_string()),
+            gateway: Some("10.8.0.1".to_string()),
+        })));
+        assert!(lines.contains(&LogLine::CipherNegotiated("AES-128-GCM".to_string())));
+        assert!(lines.contains(&LogLine::InitializationCompleted { with_errors: false }));
+    }
+
+    #[test]
+    fn parses_authentication_failures() {
+        let lines = parse_fixture(include_str!("../tests/fixtures/openvpn/auth_failed.log"));
+
+        assert!(lines.contains(&LogLine::AuthFailed(
+            "credentials rejected by the server".to_string()
+        )));
+        assert!(lines.contains(&LogLine::Exiting {
+            signal: Some("SIGTERM".to_string()),
+            reason: "auth-failure".to_string(),
+        }));
+    }
+
+    #[test]
+    fn parses_tls_timeouts_and_restarts() {
+        let lines = parse_fixture(include_str!("../tests/fixtures/openvpn/tls_timeout.log"));
+
+        assert!(lines.contains(&LogLine::TlsError(
+            "TLS key negotiation failed to occur within 60 seconds (check your network connectivity)"
+                .to_string()
+        )));
+        assert!(lines.contains(&LogLine::TlsError("TLS handshake failed".to_string())));
+        assert!(lines.contains(&LogLine::Restarting {
+            signal: "SIGUSR1".to_string(),
+            reason: "tls-error".to_string(),
+        }));
+    }
+
+    #[test]
+    fn parses_resolve_failures() {
+        let lines = parse_fixture(include_str!("../tests/fixtures/openvpn/resolve_failed.log"));
+
+        assert!(lines.contains(&LogLine::ResolveFailed {
+            host: "nl1.gekkovpn.eu:1194".to_string(),
+            error: "Name or service not known".to_string(),
+        }));
+        assert!(lines.contains(&LogLine::Exiting {
+            signal: Some("SIGHUP".to_string()),
+            reason: "init_instance".to_string(),
+        }));
+    }
+
+    #[test]
+    fn parses_certificate_errors() {
+        let expired = parse_fixture(include_str!("../tests/fixtures/openvpn/cert_expired.log"));
+        assert!(expired.contains(&LogLine::VerifyError {
+            depth: Some(0),
+            error: "certificate has expired: CN=nl1.gekkovpn.eu".to_string(),
+        }));
+
+        let skewed = parse_fixture(include_str!("../tests/fixtures/openvpn/clock_skew.log"));
+        assert!(skewed.contains(&LogLine::VerifyError {
+            depth: Some(1),
+            error: "certificate is not yet valid: CN=GekkoVPN CA".to_string(),
+        }));
+    }
+
+    #[test]
+    fn parses_cipher_mismatches() {
+        let lines = parse_fixture(include_str!(
+            "../tests/fixtures/openvpn/cipher_mismatch.log"
+        ));
+
+        assert!(lines
+            .iter()
+            .any(|line| matches!(line, LogLine::CipherMismatch(m) if m.contains("failed to negotiate cipher"))));
+        assert!(lines.contains(&LogLine::Exiting {
+            signal: None,
+            reason: "fatal error".to_string(),
+        }));
+    }
+
+    #[test]
+    fn parses_adapter_and_bind_errors() {
+        let adapter = parse_fixture(include_str!(
+            "../tests/fixtures/openvpn/no_adapter_windows.log"
+        ));
+        assert!(adapter
+            .iter()
+            .any(|line| matches!(line, LogLine::AdapterError(_))));
+
+        let bind = parse_fixture(include_str!("../tests/fixtures/openvpn/port_in_use.log"));
+        assert!(bind
+            .iter()
+            .any(|line| matches!(line, LogLine::BindFailed(_))));
+    }
+
+    #[test]
+    fn parses_ping_restarts() {
+        let lines = parse_fixture(include_str!("../tests/fixtures/openvpn/ping_restart.log"));
+
+        assert!(lines.contains(&LogLine::Restarting {
+            signal: "SIGUSR1".to_string(),
+            reason: "ping-restart".to_string(),
+        }));
+        assert!(lines.contains(&LogLine::Exiting {
+            signal: Some("SIGTERM".to_string()),
+            reason: "hard".to_string(),
+        }));
+    }
+}
@@ -0,0 +1,648 @@
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex};
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// States reported by openvpn in `>STATE:` notifications
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenVpnState {
+    Connecting,
+    Wait,
+    Auth,
+    AuthPending,
+    GetConfig,
+    AssignIp,
+    AddRoutes,
+    Connected,
+    Reconnecting,
+    Exiting,
+    Resolve,
+    TcpConnect,
+    Unknown(String),
+}
+
+impl OpenVpnState {
+    fn parse(name: &str) -> Self {
+        match name {
+            "CONNECTING" => OpenVpnState::Connecting,
+            "WAIT" => OpenVpnState::Wait,
+            "AUTH" => OpenVpnState::Auth,
+            "AUTH_PENDING" => OpenVpnState::AuthPending,
+            "GET_CONFIG" => OpenVpnState::GetConfig,
+            "ASSIGN_IP" => OpenVpnState::AssignIp,
+            "ADD_ROUTES" => OpenVpnState::AddRoutes,
+            "CONNECTED" => OpenVpnState::Connected,
+            "RECONNECTING" => OpenVpnState::Reconnecting,
+            "EXITING" => OpenVpnState::Exiting,
+            "RESOLVE" => OpenVpnState::Resolve,
+            "TCP_CONNECT" => OpenVpnState::TcpConnect,
+            other => OpenVpnState::Unknown(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    pub timestamp: u64,
+    pub state: OpenVpnState,
+    pub description: String,
+    pub local_ip: Option<String>,
+    pub remote_ip: Option<String>,
+    pub remote_port: Option<u16>,
+    pub local_ipv6: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordRequest {
+    /// openvpn wants a username and password for the given realm (usually "Auth")
+    NeedUsernamePassword {
+        realm: String,
+    },
+    /// openvpn wants only a password, e.g. for an encrypted private key
+    NeedPassword {
+        realm: String,
+    },
+    /// The server rejected the credentials sent for the given realm
+    VerificationFailed {
+        realm: String,
+    },
+    /// The server pushed an auth token; the token itself is not kept
+    AuthToken,
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Fatal,
+    NonFatal,
+    Warning,
+    Debug,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub timestamp: u64,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+/// Real-time notification received over the management interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManagementEvent {
+    State(StateChange),
+    Password(PasswordRequest),
+    Log(LogEntry),
+    ByteCount { bytes_in: u64, bytes_out: u64 },
+    Hold(String),
+    Info(String),
+    Fatal(String),
+    Other(String),
+}
+
+/// Parses a single `>SOURCE:payload` notification line
+pub fn parse_notification(line: &str) -> Option<ManagementEvent> {
+    let (source, payload) = line.strip_prefix('>')?.split_once(':')?;
+
+    let event = match source {
+        "STATE" => parse_state(payload)
+            .map(ManagementEvent::State)
+            .unwrap_or_else(|| ManagementEvent::Other(line.to_string())),
+        "PASSWORD" => ManagementEvent::Password(parse_password(payload)),
+        "LOG" => parse_log(payload)
+            .map(ManagementEvent::Log)
+            .unwrap_or_else(|| ManagementEvent::Other(line.to_string())),
+        "BYTECOUNT" => {
+            let (bytes_in, bytes_out) = payload.split_once(',')?;
+            ManagementEvent::ByteCount {
+                bytes_in: bytes_in.trim().parse().ok()?,
+                bytes_out: bytes_out.trim().parse().ok()?,
+            }
+        }
+        "HOLD" => ManagementEvent::Hold(payload.to_string()),
+        "INFO" => ManagementEvent::Info(payload.to_string()),
+        "FATAL" => ManagementEvent::Fatal(payload.to_string()),
+        _ => ManagementEvent::Other(line.to_string()),
+    };
+    Some(event)
+}
+
+fn parse_state(payload: &str) -> Option<StateChange> {
+    let fields: Vec<&str> = payload.split(',').collect();
+    let field = |i: usize| {
+        fields
+            .get(i)
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+            .map(String::from)
+    };
+
+    Some(StateChange {
+        timestamp: fields.first()?.trim().parse().ok()?,
+        state: OpenVpnState::parse(fields.get(1)?.trim()),
+        description: field(2).unwrap_or_default(),
+        local_ip: field(3),
+        remote_ip: field(4),
+        remote_port: field(5).and_then(|p| p.parse().ok()),
+        local_ipv6: field(8),
+    })
+}
+
+fn parse_password(payload: &str) -> PasswordRequest {
+    let realm = || payload.split('\'').nth(1).unwrap_or_default().to_string();
+
+    if payload.starts_with("Verification Failed") {
+        PasswordRequest::VerificationFailed { realm: realm() }
+    } else if payload.starts_with("Need") && payload.contains("username/password") {
+        PasswordRequest::NeedUsernamePassword { realm: realm() }
+    } else if payload.starts_with("Need") && payload.contains("password") {
+        PasswordRequest::NeedPassword { realm: realm() }
+    } else if payload.starts_with("Auth-Token") {
+        PasswordRequest::AuthToken
+    } else {
+        PasswordRequest::Other(payload.to_string())
+    }
+}
+
+fn parse_log(payload: &str) -> Option<LogEntry> {
+    let mut fields = payload.splitn(3, ',');
+    let timestamp = fields.next()?.trim().parse().ok()?;
+    let level = match fields.next()? {
+        "F" => LogLevel::Fatal,
+        "N" => LogLevel::NonFatal,
+        "W" => LogLevel::Warning,
+        "D" => LogLevel::Debug,
+        _ => LogLevel::Info,
+    };
+    Some(LogEntry {
+        timestamp,
+        level,
+        message: fields.next().unwrap_or_default().to_string(),
+    })
+}
+
+/// Quotes a management command argument, escaping backslashes and double quotes.
+/// Control characters are refused, since a line break would end the command and
+/// let the rest of the value through as commands of its own.
+fn quote(value: &str) -> Result<String, String> {
+    if value.chars().any(char::is_control) {
+        return Err("Credentials must not contain control characters".to_string());
+    }
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+/// Where openvpn's management interface is reached. Whoever is on the other end
+/// gets the credentials and can command openvpn, which may run as root, so only
+/// the openvpn started for this connect may be:
+///
+/// - on Unix openvpn connects back to a socket in a directory only we can enter,
+///   and the peer has to be that openvpn process;
+/// - on Windows openvpn connects back to a loopback port that stays bound to us
+///   from before openvpn starts until it connects.
+pub struct ManagementListener {
+    #[cfg(unix)]
+    listener: tokio::net::UnixListener,
+    /// The directory of the socket, which goes when the listener does
+    #[cfg(unix)]
+    private: PathBuf,
+    #[cfg(windows)]
+    listener: tokio::net::TcpListener,
+    #[cfg(windows)]
+    port: u16,
+}
+
+/// A random name that is not guessed ahead of time
+#[cfg(unix)]
+fn random_hex() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| format!("Failed to name the management socket: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(unix)]
+impl ManagementListener {
+    pub async fn bind() -> Result<Self, String> {
+        use std::os::unix::fs::DirBuilderExt;
+
+        // Creating fails rather than reusing whatever is there already
+        let private = std::env::temp_dir().join(format!("gekkovpn-{}", random_hex()?));
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&private)
+            .map_err(|e| format!("Failed to create {:?}: {}", private, e))?;
+        let listener = ManagementListener {
+            listener: tokio::net::UnixListener::bind(private.join("management.sock"))
+                .map_err(|e| format!("Failed to open the management socket: {}", e))?,
+            private,
+        };
+        Ok(listener)
+    }
+
+    /// Arguments that make openvpn connect to this listener, hold until released
+    /// and ask for credentials over the management channel instead of stdin
+    pub fn openvpn_args(&self) -> Vec<String> {
+        vec![
+            "--management".to_string(),
+            self.private
+                .join("management.sock")
+                .to_string_lossy()
+                .into_owned(),
+            "unix".to_string(),
+            "--management-client".to_string(),
+            "--management-query-passwords".to_string(),
+            "--management-hold".to_string(),
+        ]
+    }
+
+    /// Waits for `openvpn`, the process started with `openvpn_args`, to connect and
+    /// returns the client together with its event stream
+    pub async fn accept(
+        self,
+        timeout: Duration,
+        openvpn: Option<u32>,
+    ) -> Result<(ManagementClient, mpsc::UnboundedReceiver<ManagementEvent>), String> {
+        let (stream, _) = tokio::time::timeout(timeout, self.listener.accept())
+            .await
+            .map_err(|_| "OpenVPN did not connect to the management interface".to_string())?
+            .map_err(|e| format!("Failed to accept management connection: {}", e))?;
+
+        let peer = stream
+            .peer_cred()
+            .map_err(|e| format!("Failed to identify the management peer: {}", e))?;
+        // SAFETY: geteuid cannot fail and touches no memory
+        let user = unsafe { libc::geteuid() };
+        verify_peer(user, peer.uid(), peer.pid(), openvpn)?;
+
+        let (reader, writer) = stream.into_split();
+        Ok(start(BufReader::new(reader), writer))
+    }
+}
+
+/// The peer is openvpn when it runs as us, or as root through pkexec, and, where
+/// the platform tells, is the very process that was started. pkexec execs openvpn,
+/// so that keeps the process id.
+#[cfg(unix)]
+fn verify_peer(
+    user: u32,
+    peer_uid: u32,
+    peer_pid: Option<i32>,
+    openvpn: Option<u32>,
+) -> Result<(), String> {
+    if peer_uid != user && peer_uid != 0 {
+        return Err(format!(
+            "Refused a management connection from user {}",
+            peer_uid
+        ));
+    }
+    match (peer_pid, openvpn) {
+        (Some(pid), Some(openvpn)) if u32::try_from(pid).ok() != Some(openvpn) => Err(format!(
+            "Refused a management connection from process {}, which is not OpenVPN",
+            pid
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(windows)]
+impl ManagementListener {
+    pub async fn bind() -> Result<Self, String> {
+        // Bound until openvpn connects, so no one else can take the port meanwhile
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .map_err(|e| format!("Failed to open the management port: {}", e))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to open the management port: {}", e))?
+            .port();
+        Ok(ManagementListener { listener, port })
+    }
+
+    /// Arguments that make openvpn connect to this listener, hold until released
+    /// and ask for credentials over the management channel instead of stdin
+    pub fn openvpn_args(&self) -> Vec<String> {
+        vec![
+            "--management".to_string(),
+            "127.0.0.1".to_string(),
+            self.port.to_string(),
+            "--management-client".to_string(),
+            "--management-query-passwords".to_string(),
+            "--management-hold".to_string(),
+        ]
+    }
+
+    /// Waits for openvpn to connect and returns the client together with its
+    /// event stream
+    pub async fn accept(
+        self,
+        timeout: Duration,
+        _openvpn: Option<u32>,
+    ) -> Result<(ManagementClient, mpsc::UnboundedReceiver<ManagementEvent>), String> {
+        let (stream, _) = tokio::time::timeout(timeout, self.listener.accept())
+            .await
+            .map_err(|_| "OpenVPN did not connect to the management interface".to_string())?
+            .map_err(|e| format!("Failed to accept management connection: {}", e))?;
+        let (reader, writer) = stream.into_split();
+        Ok(start(BufReader::new(reader), writer))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ManagementListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.private);
+    }
+}
+
+/// Reads notifications and replies off an authenticated management connection
+fn start(
+    reader: impl AsyncBufRead + Unpin + Send + 'static,
+    writer: impl AsyncWrite + Unpin + Send + 'static,
+) -> (ManagementClient, mpsc::UnboundedReceiver<ManagementEvent>) {
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    let (response_tx, response_rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut lines = reader.lines();
+        let mut pending: Vec<String> = Vec::new();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.starts_with('>') {
+                if let Some(event) = parse_notification(&line) {
+                    let _ = event_tx.send(event);
+                }
+            } else if let Some(message) = line.strip_prefix("SUCCESS:") {
+                let _ = response_tx.send(Ok(message.trim().to_string()));
+            } else if let Some(message) = line.strip_prefix("ERROR:") {
+                let _ = response_tx.send(Err(message.trim().to_string()));
+            } else if line == "END" {
+                let _ = response_tx.send(Ok(pending.join("\n")));
+                pending.clear();
+            } else {
+                pending.push(line);
+            }
+        }
+    });
+
+    let client = ManagementClient {
+        connection: Mutex::new(Connection {
+            writer: Box::new(writer),
+            responses: response_rx,
+            unanswered: 0,
+        }),
+        timeout: COMMAND_TIMEOUT,
+    };
+    (client, event_rx)
+}
+
+struct Connection {
+    writer: Box<dyn AsyncWrite + Unpin + Send>,
+    responses: mpsc::UnboundedReceiver<Result<String, String>>,
+    /// Commands that timed out. openvpn answers in order, so their replies come
+    /// before the reply to the next command.
+    unanswered: usize,
+}
+
+pub struct ManagementClient {
+    connection: Mutex<Connection>,
+    timeout: Duration,
+}
+
+impl ManagementClient {
+    /// Sends a command and waits for its SUCCESS/ERROR (or END-terminated) reply
+    pub async fn command(&self, command: &str) -> Result<String, String> {
+        // Only the verb ends up in errors so credentials never leak into messages
+        let verb = command.split_whitespace().next().unwrap_or_default();
+        let mut connection = self.connection.lock().await;
+
+        connection
+            .writer
+            .write_all(format!("{}\n", command).as_bytes())
+            .await
+            .map_err(|e| format!("Failed to send '{}' to OpenVPN: {}", verb, e))?;
+
+        let connection = &mut *connection;
+        let reply = tokio::time::timeout(self.timeout, async {
+            loop {
+                let reply = connection.responses.recv().await;
+                if connection.unanswered == 0 || reply.is_none() {
+                    break reply;
+                }
+                // A late reply to a command that timed out
+                connection.unanswered -= 1;
+            }
+        })
+        .await;
+        match reply {
+            Ok(Some(Ok(reply))) => Ok(reply),
+            Ok(Some(Err(e))) => Err(format!("OpenVPN rejected '{}': {}", verb, e)),
+            Ok(None) => Err("OpenVPN closed the management interface".to_string()),
+            Err(_) => {
+                connection.unanswered += 1;
+                Err(format!("OpenVPN did not answer '{}'", verb))
+            }
+        }
+    }
+
+    /// Turns on real-time state notifications. The log is read from openvpn's
+    /// stdout, so `>LOG:` is left off rather than getting every line twice.
+    pub async fn enable_notifications(&self) -> Result<(), String> {
+        self.command("state on").await?;
+        Ok(())
+    }
+
+    /// Asks openvpn to report `>BYTECOUNT:` every `interval` seconds (0 disables it)
+    pub async fn enable_bytecount(&self, interval: u32) -> Result<(), String> {
+        self.command(&format!("bytecount {}", interval)).await?;
+        Ok(())
+    }
+
+    /// Changes openvpn's `--verb` while it runs
+    pub async fn set_verb(&self, verb: u8) -> Result<(), String> {
+        self.command(&format!("verb {}", verb)).await?;
+        Ok(())
+    }
+
+    /// Lets openvpn continue past `--management-hold`
+    pub async fn hold_release(&self) -> Result<(), String> {
+        self.command("hold release").await?;
+        Ok(())
+    }
+
+    pub async fn send_credentials(
+        &self,
+        realm: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<(), String> {
+        // Both lines are checked before either is sent
+        let username = format!("username {} {}", quote(realm)?, quote(username)?);
+        let password = format!("password {} {}", quote(realm)?, quote(password)?);
+        self.command(&username).await?;
+        self.command(&password).await?;
+        Ok(())
+    }
+
+    /// Sends a signal such as `SIGTERM` or `SIGUSR1` to openvpn
+    pub async fn signal(&self, signal: &str) -> Result<(), String> {
+        self.command(&format!("signal {}", signal)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn matches_replies_that_come_late_to_their_commands() {
+        let (ours, theirs) = tokio::io::duplex(4096);
+        let (reader, writer) = tokio::io::split(ours);
+        let (mut client, _events) = start(BufReader::new(reader), writer);
+        client.timeout = Duration::from_millis(50);
+        let (commands, mut openvpn) = tokio::io::split(theirs);
+        let mut commands = BufReader::new(commands).lines();
+
+        assert!(client.command("state on").await.is_err());
+        assert_eq!(commands.next_line().await.unwrap().unwrap(), "state on");
+        openvpn
+            .write_all(b"SUCCESS: real-time state notification set to ON\r\n")
+            .await
+            .unwrap();
+
+        let (reply, _) = tokio::join!(client.command("verb 4"), async {
+            assert_eq!(commands.next_line().await.unwrap().unwrap(), "verb 4");
+            openvpn.write_all(b"SUCCESS: verb=4\r\n").await.unwrap();
+        });
+        assert_eq!(reply.unwrap(), "verb=4");
+    }
+
+    #[test]
+    fn parses_state_notifications() {
+        let event = parse_notification(
+            ">STATE:1712345678,CONNECTED,SUCCESS,10.8.0.6,185.12.4.1,1194,,,fd00::6",
+        )
+        .unwrap();
+        assert_eq!(
+            event,
+            ManagementEvent::State(StateChange {
+                timestamp: 1712345678,
+                state: OpenVpnState::Connected,
+                description: "SUCCESS".to_string(),
+                local_ip: Some("10.8.0.6".to_string()),
+                remote_ip: Some("185.12.4.1".to_string()),
+                remote_port: Some(1194),
+                local_ipv6: Some("fd00::6".to_string()),
+            })
+        );
+
+        let event = parse_notification(">STATE:1712345670,WAIT,,,,,,").unwrap();
+        match event {
+            ManagementEvent::State(change) => {
+                assert_eq!(change.state, OpenVpnState::Wait);
+                assert_eq!(change.local_ip, None);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_password_requests() {
+        assert_eq!(
+            parse_notification(">PASSWORD:Need 'Auth' username/password"),
+            Some(ManagementEvent::Password(
+                PasswordRequest::NeedUsernamePassword {
+                    realm: "Auth".to_string()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_notification(">PASSWORD:Verification Failed: 'Auth'"),
+            Some(ManagementEvent::Password(
+                PasswordRequest::VerificationFailed {
+                    realm: "Auth".to_string()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_notification(">PASSWORD:Need 'Private Key' password"),
+            Some(ManagementEvent::Password(PasswordRequest::NeedPassword {
+                realm: "Private Key".to_string()
+            }))
+        );
+    }
+
+    #[test]
+    fn parses_log_and_bytecount() {
+        assert_eq!(
+            parse_notification(">LOG:1712345678,W,WARNING: cipher AES-128-CBC, deprecated"),
+            Some(ManagementEvent::Log(LogEntry {
+                timestamp: 1712345678,
+                level: LogLevel::Warning,
+                message: "WARNING: cipher AES-128-CBC, deprecated".to_string(),
+            }))
+        );
+        assert_eq!(
+            parse_notification(">BYTECOUNT:12345,678"),
+            Some(ManagementEvent::ByteCount {
+                bytes_in: 12345,
+                bytes_out: 678
+            })
+        );
+        assert_eq!(parse_notification("SUCCESS: hold release succeeded"), None);
+    }
+
+    #[test]
+    fn quotes_arguments() {
+        assert_eq!(quote(r#"pa"ss\word"#).unwrap(), r#""pa\"ss\\word""#);
+    }
+
+    #[test]
+    fn refuses_arguments_that_would_end_the_command() {
+        for value in [
+            "secret\nsignal SIGTERM",
+            "secret\r\nhold release",
+            "secret\0",
+            "\u{85}",
+        ] {
+            assert!(quote(value).is_err(), "{:?} was quoted", value);
+        }
+        assert!(quote("pässwörd with spaces").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accepts_only_openvpn_as_the_peer() {
+        assert!(verify_peer(1000, 1000, Some(42), Some(42)).is_ok());
+        // pkexec runs openvpn as root
+        assert!(verify_peer(1000, 0, Some(42), Some(42)).is_ok());
+        assert!(verify_peer(1000, 1000, None, Some(42)).is_ok());
+        assert!(verify_peer(1000, 1001, Some(42), Some(42)).is_err());
+        assert!(verify_peer(1000, 1000, Some(43), Some(42)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn refuses_a_process_that_is_not_openvpn() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let listener = ManagementListener::bind().await.unwrap();
+        let private = listener.private.clone();
+        let mode = std::fs::metadata(&private).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let socket = listener.openvpn_args()[1].clone();
+        let _stranger = tokio::net::UnixStream::connect(&socket).await.unwrap();
+        let accepted = listener
+            .accept(Duration::from_secs(5), Some(std::process::id() + 1))
+            .await;
+        assert!(accepted.is_err());
+        assert!(!private.exists());
+    }
+}
@@ -0,0 +1,685 @@
+use crate::error::VpnError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+
+/// Overrides the openvpn binary, e.g. for a build with extra plugins
+pub const OPENVPN_ENV: &str = "GEKKOVPN_OPENVPN";
+
+/// Overrides the directory holding one profile directory per server
+pub const CONFIG_DIR_ENV: &str = "GEKKOVPN_CONFIG_DIR";
+
+const CONFIG_DIR_NAME: &str = "openvpn_config";
+
+/// The saved overrides, in the app data directory next to the usage ledger
+const OVERRIDES_FILE: &str = "path_overrides.json";
+
+/// The app's bundle identifier, which names its data directory
+pub const APP_IDENTIFIER: &str = "app.gekkovpn.eu";
+
+/// Install locations of older versions, which did not use the resource directory
+const LEGACY_INSTALL_DIRS: &[&str] = &[
+    "C:\\Program Files\\GekkoVPN",
+    "C:\\Program Files (x86)\\GekkoVPN",
+];
+
+/// Where distributions put openvpn, which is often not on a user's PATH
+const SYSTEM_BINARY_DIRS: &[&str] = &[
+    "/usr/sbin",
+    "/usr/local/sbin",
+    "/opt/homebrew/sbin",
+    "/usr/local/opt/openvpn/sbin",
+];
+
+/// What path resolution needs from the system, so it can be faked in tests
+pub trait Environment: Send + Sync {
+    /// Tauri's resource directory, which holds the bundled `bin/`
+    fn resource_dir(&self) -> Option<PathBuf>;
+    fn exe_dir(&self) -> Option<PathBuf>;
+    fn app_data_dir(&self) -> Option<PathBuf>;
+    fn var(&self, name: &str) -> Option<String>;
+    /// Directories on PATH
+    fn search_path(&self) -> Vec<PathBuf>;
+    fn exists(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    /// Whether only an administrator can change `path`, so openvpn may run
+    /// elevated from it
+    fn admin_only(&self, path: &Path) -> bool;
+}
+
+/// The real system. The app data directory is the one Tauri gives the GUI, so every
+/// front-end finds the same profiles and usage.
+pub struct SystemEnvironment;
+
+impl Environment for SystemEnvironment {
+    /// Only the GUI has a resource directory; next to the executable is checked anyway
+    fn resource_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn exe_dir(&self) -> Option<PathBuf> {
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    }
+
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        let var = |name: &str| self.var(name).map(PathBuf::from);
+        let data_dir = if cfg!(windows) {
+            var("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|home| home.join("Library").join("Application Support"))
+        } else {
+            var("XDG_DATA_HOME").or_else(|| var("HOME").map(|home| home.join(".local/share")))
+        };
+        data_dir.map(|dir| dir.join(APP_IDENTIFIER))
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok().filter(|value| !value.is_empty())
+    }
+
+    fn search_path(&self) -> Vec<PathBuf> {
+        std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect())
+            .unwrap_or_default()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn admin_only(&self, path: &Path) -> bool {
+        admin_only(path)
+    }
+}
+
+/// Whether `path` and every directory above it belong to root, and no one but
+/// root may write to them
+#[cfg(unix)]
+pub fn admin_only(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    path.ancestors().all(|dir| match std::fs::metadata(dir) {
+        Ok(metadata) => {
+            let mode = metadata.mode();
+            metadata.uid() == 0 && mode & 0o002 == 0 && (mode & 0o020 == 0 || metadata.gid() == 0)
+        }
+        Err(_) => false,
+    })
+}
+
+/// By default only administrators may write below Program Files and the Windows
+/// directory
+#[cfg(windows)]
+pub fn admin_only(path: &Path) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    ["ProgramFiles", "ProgramFiles(x86)", "SystemRoot"]
+        .into_iter()
+        .filter_map(std::env::var_os)
+        .filter_map(|dir| PathBuf::from(dir).canonicalize().ok())
+        .any(|dir| path.starts_with(dir))
+}
+
+/// The overrides saved in the app data directory. A file users could have
+/// written is ignored.
+fn saved_overrides(env: &dyn Environment) -> PathOverrides {
+    let Some(path) = env.app_data_dir().map(|dir| dir.join(OVERRIDES_FILE)) else {
+        return PathOverrides::default();
+    };
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return PathOverrides::default();
+    };
+    if !env.admin_only(&path) {
+        warn!(path = ?path, "Ignoring path overrides that users can change");
+        return PathOverrides::default();
+    }
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        warn!(path = ?path, error = %e, "Ignoring unreadable path overrides");
+        PathOverrides::default()
+    })
+}
+
+/// Operating system and architecture the binaries are picked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub os: &'static str,
+    pub arch: &'static str,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        Platform {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        }
+    }
+
+    /// Directory of the bundled binaries for this architecture
+    pub fn bundle_dir_name(&self) -> Result<&'static str, VpnError> {
+        match self.arch {
+            "x86_64" => Ok("openvpn_amd64"),
+            "aarch64" => Ok("openvpn_arm64"),
+            arch => Err(VpnError::AppPaths(format!(
+                "Unsupported architecture: {}",
+                arch
+            ))),
+        }
+    }
+
+    pub fn openvpn_binary_name(&self) -> &'static str {
+        if self.os == "windows" {
+            "openvpn.exe"
+        } else {
+            "openvpn"
+        }
+    }
+}
+
+/// Paths an administrator set, which win over everything else
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathOverrides {
+    pub openvpn_binary: Option<PathBuf>,
+    pub config_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathSource {
+    Setting,
+    Environment,
+    Bundled,
+    System,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppPaths {
+    pub openvpn_binary: PathBuf,
+    pub openvpn_source: PathSource,
+    /// The bundled `bin/` with one directory per architecture, which also holds tapctl
+    pub bin_dir: PathBuf,
+    pub config_dir: PathBuf,
+    pub config_source: PathSource,
+}
+
+/// Finds openvpn and the server profiles
+pub struct PathResolver {
+    env: Box<dyn Environment>,
+    platform: Platform,
+    overrides: RwLock<PathOverrides>,
+}
+
+impl PathResolver {
+    /// Starts with the overrides saved in the app data directory
+    pub fn new(env: impl Environment + 'static, platform: Platform) -> Self {
+        let overrides = saved_overrides(&env);
+        PathResolver {
+            env: Box::new(env),
+            platform,
+            overrides: RwLock::new(overrides),
+        }
+    }
+
+    pub fn overrides(&self) -> PathOverrides {
+        self.overrides.read().unwrap().clone()
+    }
+
+    pub fn set_overrides(&self, overrides: PathOverrides) -> Result<(), VpnError> {
+        if let Some(path) = &overrides.openvpn_binary {
+            self.check_override(path, false)?;
+        }
+        if let Some(path) = &overrides.config_dir {
+            self.check_override(path, true)?;
+        }
+        self.save(&overrides)?;
+        *self.overrides.write().unwrap() = overrides;
+        Ok(())
+    }
+
+    /// Without an app data directory the overrides are only kept in memory
+    fn save(&self, overrides: &PathOverrides) -> Result<(), VpnError> {
+        let Some(dir) = self.env.app_data_dir() else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| VpnError::Storage(format!("Failed to create {:?}: {}", dir, e)))?;
+        if !self.env.admin_only(&dir) {
+            return Err(VpnError::InvalidSetting(format!(
+                "Path overrides cannot be saved in {}, which users other than an administrator can change",
+                dir.display()
+            )));
+        }
+
+        let path = dir.join(OVERRIDES_FILE);
+        let contents = serde_json::to_string_pretty(overrides)
+            .map_err(|e| VpnError::Storage(format!("Failed to serialize path overrides: {}", e)))?;
+        // Write then rename, so a crash mid-write keeps the previous overrides intact
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, contents)
+            .and_then(|_| std::fs::rename(&temp, &path))
+            .map_err(|e| VpnError::Storage(format!("Failed to write {:?}: {}", path, e)))
+    }
+
+    /// openvpn runs elevated with the binary and the profiles, so neither may be
+    /// something the user can change
+    fn check_override(&self, path: &Path, dir: bool) -> Result<(), VpnError> {
+        let invalid = |problem: &str| {
+            Err(VpnError::InvalidSetting(format!(
+                "{} {}",
+                path.display(),
+                problem
+            )))
+        };
+        if !self.env.exists(path) {
+            invalid("does not exist")
+        } else if dir && !self.env.is_dir(path) {
+            invalid("is not a directory")
+        } else if !dir && self.env.is_dir(path) {
+            invalid("is not a file")
+        } else if !self.env.admin_only(path) {
+            invalid("can be changed by users other than an administrator")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Directories that may hold a bundled `bin/` and `openvpn_config/`, best first
+    fn install_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = [self.env.resource_dir(), self.env.exe_dir()]
+            .into_iter()
+            .flatten()
+            .collect();
+        if self.platform.os == "windows" {
+            dirs.extend(LEGACY_INSTALL_DIRS.iter().map(PathBuf::from));
+        }
+        dirs.dedup();
+        dirs
+    }
+
+    /// Resolves openvpn in order of: the setting, the environment, the bundle,
+    /// then an openvpn installed on the system. The config directory is resolved
+    /// the same way, falling back to the app data directory. A setting or an
+    /// environment variable pointing at something users can change fails the
+    /// resolution instead of falling back.
+    pub fn resolve(&self) -> Result<AppPaths, VpnError> {
+        let overrides = self.overrides();
+        let install_dirs = self.install_dirs();
+        let bundle_dir_name = self.platform.bundle_dir_name()?;
+        let binary_name = self.platform.openvpn_binary_name();
+
+        let bin_dirs: Vec<PathBuf> = install_dirs.iter().map(|dir| dir.join("bin")).collect();
+        let bundled = bin_dirs
+            .iter()
+            .map(|bin| bin.join(bundle_dir_name).join(binary_name));
+        let mut system = self
+            .env
+            .search_path()
+            .into_iter()
+            .chain(SYSTEM_BINARY_DIRS.iter().map(PathBuf::from))
+            .map(|dir| dir.join(binary_name));
+
+        let (openvpn_binary, openvpn_source) = overrides
+            .openvpn_binary
+            .map(|path| (path, PathSource::Setting))
+            .or_else(|| {
+                self.env
+                    .var(OPENVPN_ENV)
+                    .map(|path| (PathBuf::from(path), PathSource::Environment))
+            })
+            .map(|(path, source)| self.check_override(&path, false).map(|()| (path, source)))
+            .transpose()?
+            .or_else(|| {
+                bundled
+                    .clone()
+                    .find(|path| self.env.exists(path))
+                    .map(|path| (path, PathSource::Bundled))
+            })
+            .or_else(|| {
+                system
+                    .find(|path| self.env.exists(path))
+                    .map(|path| (path, PathSource::System))
+            })
+            .ok_or_else(|| {
+                VpnError::OpenVpnNotFound(
+                    bundled
+                        .clone()
+                        .next()
+                        .unwrap_or_else(|| PathBuf::from(binary_name)),
+                )
+            })?;
+
+        let bin_dir = bin_dirs
+            .iter()
+            .find(|bin| self.env.exists(bin))
+            .or(bin_dirs.first())
+            .cloned()
+            .ok_or_else(|| VpnError::AppPaths("No install directory found".to_string()))?;
+
+        let config_dirs: Vec<PathBuf> = install_dirs
+            .iter()
+            .chain(self.env.app_data_dir().iter())
+            .map(|dir| dir.join(CONFIG_DIR_NAME))
+            .collect();
+        let (config_dir, config_source) = overrides
+            .config_dir
+            .map(|path| (path, PathSource::Setting))
+            .or_else(|| {
+                self.env
+                    .var(CONFIG_DIR_ENV)
+                    .map(|path| (PathBuf::from(path), PathSource::Environment))
+            })
+            .map(|(path, source)| self.check_override(&path, true).map(|()| (path, source)))
+            .transpose()?
+            .or_else(|| {
+                config_dirs
+                    .iter()
+                    .find(|dir| self.env.exists(dir))
+                    .map(|dir| (dir.clone(), PathSource::Bundled))
+            })
+            // Missing profiles are reported when one is opened
+            .or_else(|| {
+                config_dirs
+                    .first()
+                    .map(|dir| (dir.clone(), PathSource::Bundled))
+            })
+            .ok_or_else(|| VpnError::AppPaths("No config directory found".to_string()))?;
+
+        Ok(AppPaths {
+            openvpn_binary,
+            openvpn_source,
+            bin_dir,
+            config_dir,
+            config_source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    #[derive(Default)]
+    struct FakeEnvironment {
+        resource_dir: Option<PathBuf>,
+        exe_dir: Option<PathBuf>,
+        app_data_dir: Option<PathBuf>,
+        vars: HashMap<String, String>,
+        search_path: Vec<PathBuf>,
+        files: HashSet<PathBuf>,
+        dirs: HashSet<PathBuf>,
+        /// Paths users other than an administrator can change
+        writable: HashSet<PathBuf>,
+    }
+
+    impl FakeEnvironment {
+        fn with_files(mut self, files: &[&str]) -> Self {
+            self.files.extend(files.iter().map(PathBuf::from));
+            self
+        }
+
+        fn with_dirs(mut self, dirs: &[&str]) -> Self {
+            self.dirs.extend(dirs.iter().map(PathBuf::from));
+            self.with_files(dirs)
+        }
+    }
+
+    impl Environment for FakeEnvironment {
+        fn resource_dir(&self) -> Option<PathBuf> {
+            self.resource_dir.clone()
+        }
+
+        fn exe_dir(&self) -> Option<PathBuf> {
+            self.exe_dir.clone()
+        }
+
+        fn app_data_dir(&self) -> Option<PathBuf> {
+            self.app_data_dir.clone()
+        }
+
+        fn var(&self, name: &str) -> Option<String> {
+            self.vars.get(name).cloned()
+        }
+
+        fn search_path(&self) -> Vec<PathBuf> {
+            self.search_path.clone()
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.files.contains(path)
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            self.dirs.contains(path)
+        }
+
+        fn admin_only(&self, path: &Path) -> bool {
+            !self.writable.contains(path)
+        }
+    }
+
+    const WINDOWS_ARM64: Platform = Platform {
+        os: "windows",
+        arch: "aarch64",
+    };
+    const LINUX_X64: Platform = Platform {
+        os: "linux",
+        arch: "x86_64",
+    };
+
+    #[test]
+    fn picks_the_bundle_for_the_architecture() {
+        let env = FakeEnvironment {
+            resource_dir: Some(PathBuf::from("/app/resources")),
+            exe_dir: Some(PathBuf::from("/app")),
+            ..FakeEnvironment::default()
+        }
+        .with_files(&[
+            "/app/resources/bin",
+            "/app/resources/bin/openvpn_amd64/openvpn.exe",
+            "/app/resources/bin/openvpn_arm64/openvpn.exe",
+            "/app/openvpn_config",
+        ]);
+
+        let paths = PathResolver::new(env, WINDOWS_ARM64).resolve().unwrap();
+        assert_eq!(
+            paths.openvpn_binary,
+            PathBuf::from("/app/resources/bin/openvpn_arm64/openvpn.exe")
+        );
+        assert_eq!(paths.openvpn_source, PathSource::Bundled);
+        assert_eq!(paths.bin_dir, PathBuf::from("/app/resources/bin"));
+        assert_eq!(paths.config_dir, PathBuf::from("/app/openvpn_config"));
+    }
+
+    #[test]
+    fn falls_back_to_openvpn_on_the_system() {
+        let env = FakeEnvironment {
+            exe_dir: Some(PathBuf::from("/opt/gekkovpn")),
+            app_data_dir: Some(PathBuf::from("/home/alice/.local/share/app.gekkovpn.eu")),
+            search_path: vec![PathBuf::from("/usr/bin"), PathBuf::from("/bin")],
+            ..FakeEnvironment::default()
+        }
+        .with_files(&[
+            "/usr/sbin/openvpn",
+            "/home/alice/.local/share/app.gekkovpn.eu/openvpn_config",
+        ]);
+
+        let paths = PathResolver::new(env, LINUX_X64).resolve().unwrap();
+        assert_eq!(paths.openvpn_binary, PathBuf::from("/usr/sbin/openvpn"));
+        assert_eq!(paths.openvpn_source, PathSource::System);
+        assert_eq!(
+            paths.config_dir,
+            PathBuf::from("/home/alice/.local/share/app.gekkovpn.eu/openvpn_config")
+        );
+    }
+
+    #[test]
+    fn settings_win_over_the_environment_and_the_bundle() {
+        let mut env = FakeEnvironment {
+            exe_dir: Some(PathBuf::from("/opt/gekkovpn")),
+            ..FakeEnvironment::default()
+        }
+        .with_files(&[
+            "/opt/gekkovpn/bin/openvpn_amd64/openvpn",
+            "/custom/openvpn",
+            "/env/openvpn",
+        ])
+        .with_dirs(&["/custom/profiles", "/env/profiles"]);
+        env.vars
+            .insert(OPENVPN_ENV.to_string(), "/env/openvpn".to_string());
+        env.vars
+            .insert(CONFIG_DIR_ENV.to_string(), "/env/profiles".to_string());
+        let resolver = PathResolver::new(env, LINUX_X64);
+
+        let paths = resolver.resolve().unwrap();
+        assert_eq!(paths.openvpn_binary, PathBuf::from("/env/openvpn"));
+        assert_eq!(paths.openvpn_source, PathSource::Environment);
+        assert_eq!(paths.config_dir, PathBuf::from("/env/profiles"));
+
+        resolver
+            .set_overrides(PathOverrides {
+                openvpn_binary: Some(PathBuf::from("/custom/openvpn")),
+                config_dir: Some(PathBuf::from("/custom/profiles")),
+            })
+            .unwrap();
+        let paths = resolver.resolve().unwrap();
+        assert_eq!(paths.openvpn_binary, PathBuf::from("/custom/openvpn"));
+        assert_eq!(paths.openvpn_source, PathSource::Setting);
+        assert_eq!(paths.config_source, PathSource::Setting);
+
+        let missing = resolver.set_overrides(PathOverrides {
+            openvpn_binary: Some(PathBuf::from("/nowhere/openvpn")),
+            config_dir: None,
+        });
+        assert!(matches!(missing, Err(VpnError::InvalidSetting(_))));
+    }
+
+    #[test]
+    fn refuses_overrides_that_users_can_change_or_of_the_wrong_kind() {
+        let mut env = FakeEnvironment::default()
+            .with_files(&["/usr/sbin/openvpn", "/home/alice/openvpn"])
+            .with_dirs(&["/etc/openvpn", "/home/alice/profiles"]);
+        env.writable.extend(
+            ["/home/alice/openvpn", "/home/alice/profiles"]
+                .iter()
+                .map(PathBuf::from),
+        );
+        let resolver = PathResolver::new(env, LINUX_X64);
+
+        let refused = [
+            (Some("/home/alice/openvpn"), None),
+            (None, Some("/home/alice/profiles")),
+            (Some("/etc/openvpn"), None),
+            (None, Some("/usr/sbin/openvpn")),
+        ];
+        for (openvpn_binary, config_dir) in refused {
+            let error = resolver
+                .set_overrides(PathOverrides {
+                    openvpn_binary: openvpn_binary.map(PathBuf::from),
+                    config_dir: config_dir.map(PathBuf::from),
+                })
+                .unwrap_err();
+            assert!(matches!(error, VpnError::InvalidSetting(_)), "{:?}", error);
+        }
+        assert_eq!(resolver.overrides(), PathOverrides::default());
+
+        resolver
+            .set_overrides(PathOverrides {
+                openvpn_binary: Some(PathBuf::from("/usr/sbin/openvpn")),
+                config_dir: Some(PathBuf::from("/etc/openvpn")),
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn keeps_overrides_saved_where_only_an_administrator_can_change_them() {
+        let data_dir =
+            std::env::temp_dir().join(format!("gekkovpn-overrides-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let env = |writable: &[&Path]| {
+            let mut env = FakeEnvironment {
+                app_data_dir: Some(data_dir.clone()),
+                ..FakeEnvironment::default()
+            }
+            .with_files(&["/usr/sbin/openvpn"])
+            .with_dirs(&["/etc/openvpn"]);
+            env.writable
+                .extend(writable.iter().map(|path| path.to_path_buf()));
+            env
+        };
+        let overrides = PathOverrides {
+            openvpn_binary: Some(PathBuf::from("/usr/sbin/openvpn")),
+            config_dir: Some(PathBuf::from("/etc/openvpn")),
+        };
+
+        PathResolver::new(env(&[]), LINUX_X64)
+            .set_overrides(overrides.clone())
+            .unwrap();
+        assert_eq!(
+            PathResolver::new(env(&[]), LINUX_X64).overrides(),
+            overrides
+        );
+
+        let saved = data_dir.join(OVERRIDES_FILE);
+        let ignored = PathResolver::new(env(&[&saved]), LINUX_X64);
+        assert_eq!(ignored.overrides(), PathOverrides::default());
+        let refused = PathResolver::new(env(&[&data_dir]), LINUX_X64).set_overrides(overrides);
+        assert!(matches!(refused, Err(VpnError::InvalidSetting(_))));
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    #[test]
+    fn fails_instead_of_using_an_environment_that_users_can_change() {
+        let mut env = FakeEnvironment {
+            exe_dir: Some(PathBuf::from("/opt/gekkovpn")),
+            ..FakeEnvironment::default()
+        }
+        .with_files(&["/opt/gekkovpn/bin/openvpn_amd64/openvpn", "/tmp/openvpn"]);
+        env.vars
+            .insert(OPENVPN_ENV.to_string(), "/tmp/openvpn".to_string());
+        env.writable.insert(PathBuf::from("/tmp/openvpn"));
+
+        let error = PathResolver::new(env, LINUX_X64).resolve().unwrap_err();
+        assert!(matches!(error, VpnError::InvalidSetting(_)), "{:?}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn counts_the_temporary_directory_as_writable_by_users() {
+        assert!(!admin_only(&std::env::temp_dir()));
+        assert!(!admin_only(Path::new("/nowhere/openvpn")));
+    }
+
+    #[test]
+    fn reports_the_expected_bundle_location_when_openvpn_is_missing() {
+        let env = FakeEnvironment {
+            resource_dir: Some(PathBuf::from("C:\\GekkoVPN")),
+            ..FakeEnvironment::default()
+        };
+
+        let error = PathResolver::new(
+            env,
+            Platform {
+                os: "windows",
+                arch: "x86_64",
+            },
+        )
+        .resolve()
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            VpnError::OpenVpnNotFound(path) if path == Path::new("C:\\GekkoVPN").join("bin").join("openvpn_amd64").join("openvpn.exe")
+        ));
+    }
+}
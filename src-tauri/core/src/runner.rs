@@ -0,0 +1,261 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How often a running command is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What a finished command printed and whether it succeeded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// A command started without waiting for it. Only the OpenVPN installer on
+/// Windows is run like this.
+#[cfg(any(windows, test))]
+pub trait RunningCommand: Send {
+    fn kill(&mut self) -> io::Result<()>;
+}
+
+/// Runs external programs such as tapctl, msiexec and ip. Adapters go through this
+/// instead of `std::process`, so tests can script what those programs answer.
+pub trait CommandRunner: Send + Sync {
+    /// Runs `program` to completion. It is killed, and `TimedOut` returned, when it
+    /// takes longer than `timeout`.
+    fn output(&self, program: &Path, args: &[&str], timeout: Duration)
+        -> io::Result<CommandOutput>;
+
+    /// Starts `program` and returns right away
+    #[cfg(any(windows, test))]
+    fn spawn(&self, program: &Path, args: &[&str]) -> io::Result<Box<dyn RunningCommand>>;
+}
+
+/// Runs programs for real
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(
+        &self,
+        program: &Path,
+        args: &[&str],
+        timeout: Duration,
+    ) -> io::Result<CommandOutput> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Drained on their own threads so a chatty program never blocks on a full pipe
+        let stdout = read_to_end(child.stdout.take());
+        let stderr = read_to_end(child.stderr.take());
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} did not finish within {:?}", program.display(), timeout),
+                ));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+
+        Ok(CommandOutput {
+            success: status.success(),
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+
+    #[cfg(any(windows, test))]
+    fn spawn(&self, program: &Path, args: &[&str]) -> io::Result<Box<dyn RunningCommand>> {
+        Ok(Box::new(Command::new(program).args(args).spawn()?))
+    }
+}
+
+#[cfg(any(windows, test))]
+impl RunningCommand for std::process::Child {
+    fn kill(&mut self) -> io::Result<()> {
+        std::process::Child::kill(self)?;
+        self.wait()?;
+        Ok(())
+    }
+}
+
+fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        String::from_utf8_lossy(&bytes).to_string()
+    })
+}
+
+/// `tapctl.exe create --name GekkoVPN`: the program's file name and its arguments
+#[cfg(test)]
+fn command_line(program: &Path, args: &[&str]) -> String {
+    let program = program
+        .file_name()
+        .map_or_else(|| program.to_string_lossy(), |name| name.to_string_lossy());
+    std::iter::once(program.as_ref())
+        .chain(args.iter().copied())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// How a scripted command answers
+#[cfg(test)]
+pub enum Scripted {
+    Output(CommandOutput),
+    Error(io::ErrorKind),
+    /// The command was started and keeps running until killed
+    Started,
+}
+
+/// Answers commands from a script, in order, and records every command line it
+/// was given. A command that is not next in the script fails the test.
+#[cfg(test)]
+#[derive(Default)]
+pub struct ScriptedRunner {
+    script: std::sync::Mutex<std::collections::VecDeque<(String, Scripted)>>,
+    calls: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl ScriptedRunner {
+    /// Expects a command line that starts with `command`, answered with `answer`
+    pub fn expect(self, command: &str, answer: Scripted) -> Self {
+        self.script
+            .lock()
+            .unwrap()
+            .push_back((command.to_string(), answer));
+        self
+    }
+
+    pub fn succeed(self, command: &str, stdout: &str) -> Self {
+        self.expect(
+            command,
+            Scripted::Output(CommandOutput {
+                success: true,
+                stdout: stdout.to_string(),
+                stderr: String::new(),
+            }),
+        )
+    }
+
+    pub fn fail(self, command: &str, stderr: &str) -> Self {
+        self.expect(
+            command,
+            Scripted::Output(CommandOutput {
+                success: false,
+                stdout: String::new(),
+                stderr: stderr.to_string(),
+            }),
+        )
+    }
+
+    /// Shares the record of command lines, which outlives the runner
+    pub fn calls(&self) -> std::sync::Arc<std::sync::Mutex<Vec<String>>> {
+        self.calls.clone()
+    }
+
+    fn next(&self, program: &Path, args: &[&str]) -> Scripted {
+        let line = command_line(program, args);
+        self.calls.lock().unwrap().push(line.clone());
+        let (expected, answer) = self
+            .script
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| panic!("Unexpected command `{}`", line));
+        assert!(
+            line.starts_with(&expected),
+            "Expected `{}`, got `{}`",
+            expected,
+            line
+        );
+        answer
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for ScriptedRunner {
+    fn output(&self, program: &Path, args: &[&str], _: Duration) -> io::Result<CommandOutput> {
+        match self.next(program, args) {
+            Scripted::Output(output) => Ok(output),
+            Scripted::Error(kind) => Err(io::Error::new(kind, format!("scripted {:?}", kind))),
+            Scripted::Started => panic!("`{}` was started, not run", command_line(program, args)),
+        }
+    }
+
+    fn spawn(&self, program: &Path, args: &[&str]) -> io::Result<Box<dyn RunningCommand>> {
+        match self.next(program, args) {
+            Scripted::Started => Ok(Box::new(ScriptedProcess {
+                line: format!("kill {}", command_line(program, &[])),
+                calls: self.calls.clone(),
+            })),
+            Scripted::Error(kind) => Err(io::Error::new(kind, format!("scripted {:?}", kind))),
+            Scripted::Output(_) => panic!("`{}` was run, not started", command_line(program, args)),
+        }
+    }
+}
+
+#[cfg(test)]
+struct ScriptedProcess {
+    line: String,
+    calls: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl RunningCommand for ScriptedProcess {
+    fn kill(&mut self) -> io::Result<()> {
+        self.calls.lock().unwrap().push(self.line.clone());
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_output_and_exit_status() {
+        let output = SystemRunner
+            .output(
+                Path::new("sh"),
+                &["-c", "echo listed; echo oops >&2; exit 3"],
+                Duration::from_secs(10),
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            CommandOutput {
+                success: false,
+                stdout: "listed\n".to_string(),
+                stderr: "oops\n".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn kills_a_command_that_takes_too_long() {
+        let start = Instant::now();
+        let error = SystemRunner
+            .output(Path::new("sleep"), &["10"], Duration::from_millis(200))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,278 @@
+use crate::adapter::{AdapterInfo, AdapterReport};
+use crate::catalog::Server;
+use crate::client::{ConnectionInfo, VpnClient};
+use crate::credentials::{CredentialStore, Keyring};
+use crate::diagnosis::Diagnosis;
+use crate::error::VpnError;
+use crate::ipc::{self, Access, Connection, Request};
+use crate::logbuffer::{LogFilter, LogRecord};
+use crate::paths::{AppPaths, PathOverrides};
+use crate::states::ConnectionState;
+use crate::supervisor::ReconnectPolicy;
+use crate::traffic::TrafficStats;
+use crate::usage::{Granularity, UsageCap, UsageRecord};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone)]
+enum Tunnel {
+    Local(VpnClient),
+    Remote(Connection),
+}
+
+/// The tunnel as front-ends use it, whether it runs in this process or in the
+/// daemon. Passwords always come from this process's store, the user's keyring.
+#[derive(Clone)]
+pub struct Service {
+    tunnel: Tunnel,
+    credentials: Arc<dyn CredentialStore>,
+}
+
+impl Service {
+    /// A client in this process, which reads passwords itself
+    pub fn local(client: VpnClient) -> Self {
+        Service {
+            credentials: client.credentials(),
+            tunnel: Tunnel::Local(client),
+        }
+    }
+
+    /// A client behind `connection`, which is sent passwords from the keyring
+    pub fn remote(connection: Connection) -> Self {
+        Service {
+            tunnel: Tunnel::Remote(connection),
+            credentials: Arc::new(Keyring),
+        }
+    }
+
+    /// Reads passwords from `credentials` instead
+    pub fn with_credentials(self, credentials: Arc<dyn CredentialStore>) -> Self {
+        Service {
+            credentials,
+            ..self
+        }
+    }
+
+    /// The connection to the daemon, if the tunnel runs there
+    pub fn connection(&self) -> Option<&Connection> {
+        match &self.tunnel {
+            Tunnel::Local(_) => None,
+            Tunnel::Remote(connection) => Some(connection),
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(&self, request: Request) -> Result<T, VpnError> {
+        let value = match &self.tunnel {
+            Tunnel::Local(client) => ipc::handle(client, Access::Full, request).await?,
+            Tunnel::Remote(connection) => connection.call(request).await?,
+        };
+        serde_json::from_value(value)
+            .map_err(|e| VpnError::Protocol(format!("Unexpected reply: {}", e)))
+    }
+
+    /// The password to send along for `username`. A client in this process
+    /// reads it when it needs it.
+    fn password_for(&self, username: &str) -> Result<Option<String>, VpnError> {
+        match self.tunnel {
+            Tunnel::Local(_) => Ok(None),
+            Tunnel::Remote(_) => self.credentials.password(username).map(Some),
+        }
+    }
+
+    pub async fn connect(&self, server_name: String, username: String) -> Result<String, VpnError> {
+        let password = self.password_for(&username)?;
+        self.call(Request::Connect {
+            server_name,
+            username,
+            password,
+        })
+        .await
+    }
+
+    pub async fn disconnect(&self) -> Result<String, VpnError> {
+        self.call(Request::Disconnect).await
+    }
+
+    pub async fn switch_server(
+        &self,
+        server_name: String,
+        username: String,
+    ) -> Result<String, VpnError> {
+        let password = self.password_for(&username)?;
+        self.call(Request::SwitchServer {
+            server_name,
+            username,
+            password,
+        })
+        .await
+    }
+
+    pub async fn reconnect(&self) -> Result<String, VpnError> {
+        self.call(Request::Reconnect).await
+    }
+
+    /// Aborts the connect or reconnect attempt in progress.
+    /// Returns whether there was one to abort.
+    pub async fn cancel_connect(&self) -> Result<bool, VpnError> {
+        self.call(Request::CancelConnect).await
+    }
+
+    /// The servers with a profile where the tunnel runs
+    pub async fn list_servers(&self) -> Result<Vec<Server>, VpnError> {
+        self.call(Request::ListServers).await
+    }
+
+    pub async fn status(&self) -> Result<ConnectionInfo, VpnError> {
+        self.call(Request::Status).await
+    }
+
+    pub async fn state(&self) -> Result<ConnectionState, VpnError> {
+        self.call(Request::State).await
+    }
+
+    pub async fn traffic_stats(&self) -> Result<TrafficStats, VpnError> {
+        self.call(Request::TrafficStats).await
+    }
+
+    pub async fn last_failure(&self) -> Result<Option<Diagnosis>, VpnError> {
+        self.call(Request::LastFailure).await
+    }
+
+    pub async fn logs(&self, filter: LogFilter) -> Result<Vec<LogRecord>, VpnError> {
+        self.call(Request::Logs { filter }).await
+    }
+
+    /// All buffered log lines as plain text
+    pub async fn export_logs(&self) -> Result<String, VpnError> {
+        self.call(Request::ExportLogs).await
+    }
+
+    pub async fn set_openvpn_verb(&self, verb: u8) -> Result<(), VpnError> {
+        self.call(Request::SetOpenVpnVerb { verb }).await
+    }
+
+    pub async fn usage(
+        &self,
+        granularity: Granularity,
+        server: Option<String>,
+    ) -> Result<Vec<UsageRecord>, VpnError> {
+        self.call(Request::Usage {
+            granularity,
+            server,
+        })
+        .await
+    }
+
+    pub async fn usage_csv(&self) -> Result<String, VpnError> {
+        self.call(Request::UsageCsv).await
+    }
+
+    pub async fn usage_cap(&self) -> Result<Option<UsageCap>, VpnError> {
+        self.call(Request::UsageCap).await
+    }
+
+    pub async fn set_usage_cap(&self, cap: Option<UsageCap>) -> Result<(), VpnError> {
+        self.call(Request::SetUsageCap { cap }).await
+    }
+
+    pub async fn app_paths(&self) -> Result<AppPaths, VpnError> {
+        self.call(Request::AppPaths).await
+    }
+
+    pub async fn path_overrides(&self) -> Result<PathOverrides, VpnError> {
+        self.call(Request::PathOverrides).await
+    }
+
+    pub async fn set_path_overrides(&self, overrides: PathOverrides) -> Result<(), VpnError> {
+        self.call(Request::SetPathOverrides { overrides }).await
+    }
+
+    pub async fn adapters(&self) -> Result<AdapterReport, VpnError> {
+        self.call(Request::Adapters).await
+    }
+
+    pub async fn create_adapter(&self, name: Option<String>) -> Result<AdapterInfo, VpnError> {
+        self.call(Request::CreateAdapter { name }).await
+    }
+
+    pub async fn remove_adapter(&self, name: String) -> Result<(), VpnError> {
+        self.call(Request::RemoveAdapter { name }).await
+    }
+
+    pub async fn reconnect_policy(&self) -> Result<ReconnectPolicy, VpnError> {
+        self.call(Request::ReconnectPolicy).await
+    }
+
+    pub async fn set_reconnect_policy(&self, policy: ReconnectPolicy) -> Result<(), VpnError> {
+        self.call(Request::SetReconnectPolicy { policy }).await
+    }
+
+    pub async fn shutdown_grace_period(&self) -> Result<Duration, VpnError> {
+        self.call(Request::ShutdownGracePeriod)
+            .await
+            .map(Duration::from_secs)
+    }
+
+    pub async fn set_shutdown_grace_period(&self, grace_period: Duration) -> Result<(), VpnError> {
+        self.call(Request::SetShutdownGracePeriod {
+            seconds: grace_period.as_secs(),
+        })
+        .await
+    }
+
+    /// The password saved in this process's store for `username`, if there is one
+    pub fn password(&self, username: &str) -> Result<Option<String>, VpnError> {
+        match self.credentials.password(username) {
+            Ok(password) => Ok(Some(password)),
+            Err(VpnError::NoSavedPassword { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save_password(&self, username: &str, password: &str) -> Result<(), VpnError> {
+        self.credentials.set_password(username, password)
+    }
+
+    pub fn forget_password(&self, username: &str) -> Result<(), VpnError> {
+        self.credentials.delete_password(username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Timeouts;
+    use crate::credentials::MemoryStore;
+    use crate::testing::{Harness, CONNECTS, SERVER};
+
+    #[tokio::test]
+    async fn sends_the_password_from_its_own_store() {
+        let harness = Harness::new("service-remote", CONNECTS, Timeouts::default());
+        let (server, client) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(ipc::serve(
+            server,
+            harness.client.clone(),
+            harness.broadcast.clone(),
+            |_| Ok(Access::User),
+        ));
+        let service = Service::remote(Connection::open(client).await.unwrap())
+            .with_credentials(Arc::new(MemoryStore::with("carol", "open sesame")));
+
+        service
+            .connect(SERVER.to_string(), "carol".to_string())
+            .await
+            .unwrap();
+
+        // The server has no password for carol, so it came along, and is not kept
+        assert_eq!(service.state().await.unwrap(), ConnectionState::Connected);
+        assert_eq!(harness.client.password("carol").unwrap(), None);
+        assert!(matches!(
+            service
+                .connect(SERVER.to_string(), "dave".to_string())
+                .await,
+            Err(VpnError::NoSavedPassword { .. })
+        ));
+        service.disconnect().await.unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use crate::management::ManagementClient;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::process::Child;
+use tracing::warn;
+
+/// Event emitted to the frontend with the outcome of every openvpn shutdown
+pub const SHUTDOWN_EVENT: &str = "vpn-shutdown";
+
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ShutdownOutcome {
+    /// openvpn exited by itself after being asked to
+    Graceful {
+        exit_code: Option<i32>,
+        elapsed_ms: u64,
+    },
+    /// openvpn had to be killed
+    Forced { reason: String },
+    /// openvpn was already gone
+    AlreadyExited { exit_code: Option<i32> },
+}
+
+/// Asks openvpn to exit so it can run its down scripts, restore routes and notify
+/// the server, and only kills it if it is still running after `grace_period`.
+/// Fails when openvpn could neither be asked to exit nor killed, as happens to one
+/// running as root through pkexec; it is still running then and `child` is still
+/// its handle.
+pub async fn shutdown(
+    child: &mut Child,
+    management: Option<&ManagementClient>,
+    grace_period: Duration,
+) -> Result<ShutdownOutcome, String> {
+    if let Ok(Some(status)) = child.try_wait() {
+        return Ok(ShutdownOutcome::AlreadyExited {
+            exit_code: status.code(),
+        });
+    }
+
+    let mut asked = false;
+    if let Some(client) = management {
+        match client.signal("SIGTERM").await {
+            Ok(_) => asked = true,
+            Err(e) => warn!(error = %e, "Could not ask OpenVPN to exit"),
+        }
+    }
+    let mut refused = None;
+    if !asked {
+        match send_sigterm(child) {
+            Ok(()) => asked = true,
+            Err(e) => {
+                warn!(error = %e, "Could not send SIGTERM to OpenVPN");
+                refused = Some(e);
+            }
+        }
+    }
+
+    if asked {
+        let start = Instant::now();
+        if let Ok(Ok(status)) = tokio::time::timeout(grace_period, child.wait()).await {
+            return Ok(ShutdownOutcome::Graceful {
+                exit_code: status.code(),
+                elapsed_ms: start.elapsed().as_millis() as u64,
+            });
+        }
+    }
+
+    let reason = if asked {
+        format!(
+            "OpenVPN did not exit within {} seconds",
+            grace_period.as_secs()
+        )
+    } else {
+        format!(
+            "OpenVPN could not be asked to exit: {}",
+            refused.unwrap_or_default()
+        )
+    };
+    warn!("{}, killing it", reason);
+
+    child
+        .kill()
+        .await
+        .map_err(|e| format!("{}, and it could not be killed: {}", reason, e))?;
+    Ok(ShutdownOutcome::Forced { reason })
+}
+
+/// Fails, rather than waiting out the grace period for nothing, when openvpn runs as
+/// another user, as it does when started through pkexec
+#[cfg(unix)]
+fn send_sigterm(child: &Child) -> Result<(), String> {
+    let pid = child
+        .id()
+        .ok_or_else(|| "OpenVPN has already exited".to_string())?;
+    let pid = libc::pid_t::try_from(pid).map_err(|_| format!("Invalid process id {}", pid))?;
+    // SAFETY: kill only sends a signal, to a child that has not been reaped yet
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == 0 {
+        return Ok(());
+    }
+    let error = std::io::Error::last_os_error();
+    Err(if error.raw_os_error() == Some(libc::EPERM) {
+        format!(
+            "not allowed to signal process {}, which runs as another user",
+            pid
+        )
+    } else {
+        format!("failed to signal process {}: {}", pid, error)
+    })
+}
+
+// Windows has no SIGTERM, the management interface is the only graceful path there
+#[cfg(not(unix))]
+fn send_sigterm(_child: &Child) -> Result<(), String> {
+    Err("Windows has no SIGTERM".to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn terminates_a_process_without_management() {
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let outcome = shutdown(&mut child, None, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(
+            matches!(
+                outcome,
+                ShutdownOutcome::Graceful {
+                    exit_code: None,
+                    ..
+                }
+            ),
+            "{:?}",
+            outcome
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::events::Events;
+use crate::management::OpenVpnState;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tracing::info;
+
+/// Event emitted to the frontend on every connection state change
+pub const STATE_EVENT: &str = "vpn-state";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", content = "reason", rename_all = "snake_case")]
+pub enum ConnectionState {
+    Disconnected,
+    Resolving,
+    Connecting,
+    Authenticating,
+    Connected,
+    Reconnecting,
+    Disconnecting,
+    Failed(String),
+}
+
+impl ConnectionState {
+    pub fn is_establishing(&self) -> bool {
+        matches!(
+            self,
+            ConnectionState::Resolving
+                | ConnectionState::Connecting
+                | ConnectionState::Authenticating
+                | ConnectionState::Reconnecting
+        )
+    }
+
+    pub fn can_transition_to(&self, next: &ConnectionState) -> bool {
+        use ConnectionState::*;
+
+        match (self, next) {
+            // A new attempt only starts from an idle state
+            (Disconnected | Failed(_), Resolving | Connecting) => true,
+            (Failed(_), Disconnected) => true,
+            // openvpn moves back and forth between these while it (re)establishes the tunnel
+            (from, Resolving | Connecting | Authenticating | Reconnecting) => {
+                from.is_establishing() || (*from == Connected && *next == Reconnecting)
+            }
+            // Back from disconnecting when openvpn could not be stopped and the tunnel
+            // is still up
+            (from, Connected) => from.is_establishing() || *from == Disconnecting,
+            (from, Disconnecting) => from.is_establishing() || *from == Connected,
+            (Disconnecting, Disconnected) => true,
+            (from, Failed(_)) => {
+                from.is_establishing() || matches!(from, Connected | Disconnecting)
+            }
+            _ => false,
+        }
+    }
+
+    /// Maps an openvpn `>STATE:` to the state shown to the user
+    pub fn from_openvpn(state: &OpenVpnState) -> Option<ConnectionState> {
+        match state {
+            OpenVpnState::Resolve => Some(ConnectionState::Resolving),
+            OpenVpnState::Connecting | OpenVpnState::Wait | OpenVpnState::TcpConnect => {
+                Some(ConnectionState::Connecting)
+            }
+            OpenVpnState::Auth
+            | OpenVpnState::AuthPending
+            | OpenVpnState::GetConfig
+            | OpenVpnState::AssignIp
+            | OpenVpnState::AddRoutes => Some(ConnectionState::Authenticating),
+            OpenVpnState::Connected => Some(ConnectionState::Connected),
+            OpenVpnState::Reconnecting => Some(ConnectionState::Reconnecting),
+            // Whether an exit is a disconnect or a lost tunnel is up to the manager
+            OpenVpnState::Exiting | OpenVpnState::Unknown(_) => None,
+        }
+    }
+}
+
+/// Current connection state, shared between the manager and its tunnel watcher
+pub struct StateMachine {
+    events: Events,
+    current: Mutex<ConnectionState>,
+}
+
+impl StateMachine {
+    pub fn new(events: Events) -> Self {
+        StateMachine {
+            events,
+            current: Mutex::new(ConnectionState::Disconnected),
+        }
+    }
+
+    pub fn current(&self) -> ConnectionState {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Moves to `next` if the transition is valid and broadcasts it to the frontend
+    pub fn transition(&self, next: ConnectionState) -> Result<(), String> {
+        let mut current = self.current.lock().unwrap();
+        if *current == next {
+            return Ok(());
+        }
+        if !current.can_transition_to(&next) {
+            return Err(format!(
+                "Invalid connection state transition from {:?} to {:?}",
+                *current, next
+            ));
+        }
+
+        info!(from = ?*current, to = ?next, "Connection state changed");
+        *current = next.clone();
+        drop(current);
+
+        self.events
+            .emit(STATE_EVENT, next)
+            .map_err(|e| format!("Failed to emit connection state: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_the_normal_connection_lifecycle() {
+        let lifecycle = [
+            ConnectionState::Disconnected,
+            ConnectionState::Connecting,
+            ConnectionState::Resolving,
+            ConnectionState::Connecting,
+            ConnectionState::Authenticating,
+            ConnectionState::Connected,
+            ConnectionState::Reconnecting,
+            ConnectionState::Connected,
+            ConnectionState::Disconnecting,
+            ConnectionState::Disconnected,
+        ];
+        for pair in lifecycle.windows(2) {
+            assert!(
+                pair[0].can_transition_to(&pair[1]),
+                "{:?} -> {:?} should be allowed",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_transitions() {
+        let failed = ConnectionState::Failed("boom".to_string());
+        assert!(!ConnectionState::Connected.can_transition_to(&ConnectionState::Connecting));
+        assert!(!ConnectionState::Disconnected.can_transition_to(&ConnectionState::Connected));
+        assert!(!ConnectionState::Disconnected.can_transition_to(&failed));
+        assert!(!ConnectionState::Disconnecting.can_transition_to(&ConnectionState::Reconnecting));
+        assert!(failed.can_transition_to(&ConnectionState::Connecting));
+        // Only because openvpn could not be stopped
+        assert!(ConnectionState::Disconnecting.can_transition_to(&ConnectionState::Connected));
+    }
+}
@@ -0,0 +1,181 @@
+use crate::events::Events;
+use crate::logbuffer::{LogBuffer, Severity};
+use crate::management::{ManagementEvent, OpenVpnState};
+use crate::states::{ConnectionState, StateMachine};
+use crate::traffic::TrafficMonitor;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+/// Event emitted to the frontend for every step of a reconnect
+pub const RECONNECT_EVENT: &str = "vpn-reconnect";
+
+/// How often openvpn reports `>BYTECOUNT:`, which feeds the traffic stats and
+/// doubles as a liveness signal
+pub const BYTECOUNT_INTERVAL_SECS: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconnectPolicy {
+    pub enabled: bool,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    pub max_attempts: u32,
+    /// When set, the tunnel counts as stalled when nothing is received for this
+    /// long. Off by default, since an idle tunnel receives nothing either; a dead
+    /// server is noticed by openvpn's own ping-restart, which reports RECONNECTING.
+    pub stall_timeout_secs: Option<u64>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            enabled: true,
+            initial_delay_ms: 1_000,
+            max_delay_ms: 60_000,
+            multiplier: 2.0,
+            max_attempts: 5,
+            stall_timeout_secs: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Backoff before the given attempt, starting at 1
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1) as i32;
+        let delay = self.initial_delay_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        Duration::from_millis(delay.min(self.max_delay_ms as f64) as u64)
+    }
+
+    pub fn stall_timeout(&self) -> Option<Duration> {
+        self.stall_timeout_secs
+            .map(|secs| Duration::from_secs(secs.max(BYTECOUNT_INTERVAL_SECS as u64 * 2)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ReconnectEvent {
+    TunnelLost {
+        reason: String,
+    },
+    Scheduled {
+        attempt: u32,
+        max_attempts: u32,
+        delay_ms: u64,
+    },
+    Attempting {
+        attempt: u32,
+        max_attempts: u32,
+    },
+    AttemptFailed {
+        attempt: u32,
+        error: String,
+    },
+    Reconnected {
+        attempt: u32,
+    },
+    GaveUp {
+        attempts: u32,
+    },
+}
+
+pub fn report(events: &Events, logs: &LogBuffer, event: ReconnectEvent) {
+    info!(event = ?event, "Reconnect");
+    let severity = match event {
+        ReconnectEvent::GaveUp { .. } => Severity::Error,
+        ReconnectEvent::TunnelLost { .. } | ReconnectEvent::AttemptFailed { .. } => {
+            Severity::Warning
+        }
+        _ => Severity::Info,
+    };
+    logs.app(severity, format!("Reconnect: {:?}", event));
+    let _ = events.emit(RECONNECT_EVENT, event);
+}
+
+/// Follows a connected tunnel until it is lost or `stop` fires, mirroring openvpn's
+/// state into `state` and its byte counts into `traffic`. Returns why the tunnel
+/// was lost, or `None` when stopped.
+/// A dead openvpn closes its management connection, so it shows up here, and so
+/// does one that received nothing for `stall_timeout` when that is set.
+pub async fn watch_tunnel(
+    state: &StateMachine,
+    traffic: &TrafficMonitor,
+    mut events: UnboundedReceiver<ManagementEvent>,
+    mut stop: watch::Receiver<bool>,
+    stall_timeout: Option<Duration>,
+) -> Option<String> {
+    let mut last_activity = Instant::now();
+    let mut last_bytes_in = 0;
+    let mut check = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            _ = stop.changed() => return None,
+            event = events.recv() => match event {
+                Some(ManagementEvent::State(change)) => {
+                    debug!(state = ?change.state, description = %change.description, "OpenVPN state");
+                    if change.state == OpenVpnState::Exiting {
+                        return Some(format!("OpenVPN is exiting: {}", change.description));
+                    }
+                    if let Some(next) = ConnectionState::from_openvpn(&change.state) {
+                        let _ = state.transition(next);
+                    }
+                }
+                Some(ManagementEvent::ByteCount { bytes_in, bytes_out }) => {
+                    traffic.record(bytes_in, bytes_out);
+                    if bytes_in != last_bytes_in {
+                        last_bytes_in = bytes_in;
+                        last_activity = Instant::now();
+                    }
+                }
+                Some(ManagementEvent::Fatal(message)) => return Some(message),
+                Some(_) => {}
+                None => return Some("OpenVPN exited unexpectedly".to_string()),
+            },
+            _ = check.tick() => {
+                if let Some(timeout) = stall_timeout.filter(|t| last_activity.elapsed() >= *t) {
+                    return Some(format!(
+                        "No data received for {} seconds",
+                        timeout.as_secs()
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_cap() {
+        let policy = ReconnectPolicy {
+            initial_delay_ms: 500,
+            max_delay_ms: 5_000,
+            multiplier: 2.0,
+            ..ReconnectPolicy::default()
+        };
+
+        assert_eq!(policy.delay_for(1), Duration::from_millis(500));
+        assert_eq!(policy.delay_for(2), Duration::from_millis(1_000));
+        assert_eq!(policy.delay_for(4), Duration::from_millis(4_000));
+        assert_eq!(policy.delay_for(5), Duration::from_millis(5_000));
+        assert_eq!(policy.delay_for(50), Duration::from_millis(5_000));
+    }
+
+    #[test]
+    fn detects_stalls_only_when_asked_to() {
+        assert_eq!(ReconnectPolicy::default().stall_timeout(), None);
+        let policy = ReconnectPolicy {
+            stall_timeout_secs: Some(0),
+            ..ReconnectPolicy::default()
+        };
+        // Never shorter than two byte counts
+        assert_eq!(policy.stall_timeout(), Some(Duration::from_secs(2)));
+    }
+}
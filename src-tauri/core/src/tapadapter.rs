@@ -0,0 +1,531 @@
+use crate::adapter::{AdapterHealth, AdapterInfo, NetworkAdapter, ADAPTER_NAME};
+use crate::error::VpnError;
+use crate::paths::Platform;
+use crate::runner::CommandRunner;
+#[cfg(windows)]
+use crate::runner::SystemRunner;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+#[cfg(windows)]
+use tracing::trace;
+use tracing::{debug, info, warn};
+#[cfg(windows)]
+use winreg::enums::*;
+#[cfg(windows)]
+use winreg::RegKey;
+
+#[cfg(windows)]
+const TAP_WINDOWS_COMPONENT_ID: &str = "tap0901";
+#[cfg(windows)]
+const NETWORK_ADAPTERS_KEY: &str =
+    r"SYSTEM\CurrentControlSet\Control\Class\{4D36E972-E325-11CE-BFC1-08002BE10318}";
+
+/// tapctl is given up on after this long
+const TAPCTL_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the OpenVPN installer gets to install the TAP driver
+const INSTALL_TIMEOUT: Duration = Duration::from_secs(60);
+/// Pause between checks for the driver, and before looking for a new adapter
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What tapctl cannot tell: whether adapters may be created, and whether the TAP driver is there
+pub trait TapDriver: Send + Sync {
+    fn is_elevated(&self) -> bool;
+    fn is_installed(&self) -> Result<bool, VpnError>;
+}
+
+/// The elevation of this process and the network adapter classes in the registry
+#[cfg(windows)]
+pub struct RegistryDriver;
+
+#[cfg(windows)]
+impl TapDriver for RegistryDriver {
+    fn is_elevated(&self) -> bool {
+        is_elevated::is_elevated()
+    }
+
+    fn is_installed(&self) -> Result<bool, VpnError> {
+        let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+        match hklm.open_subkey(NETWORK_ADAPTERS_KEY) {
+            Ok(adapters) => {
+                for i in 0..1000 {
+                    let subkey_name = format!("{:04}", i);
+                    if let Ok(subkey) = adapters.open_subkey(&subkey_name) {
+                        if let Ok(component_id) = subkey.get_value::<String, _>("ComponentId") {
+                            trace!(component_id = %component_id, "Found network adapter");
+                            if component_id.to_lowercase()
+                                == TAP_WINDOWS_COMPONENT_ID.to_lowercase()
+                            {
+                                return Ok(true);
+                            }
+                        }
+                    }
+                }
+                debug!("No TAP driver found in registry");
+                Ok(false)
+            }
+            Err(e) => Err(VpnError::Adapter(format!(
+                "Failed to check TAP driver: {}",
+                e
+            ))),
+        }
+    }
+}
+
+/// TAP-Windows adapters, managed with the tapctl.exe bundled with openvpn
+pub struct TapAdapter {
+    tapctl_path: PathBuf,
+    base_dir: PathBuf,
+    platform: Platform,
+    runner: Box<dyn CommandRunner>,
+    driver: Box<dyn TapDriver>,
+    tapctl_timeout: Duration,
+    install_timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl TapAdapter {
+    #[cfg(windows)]
+    pub fn new(base_dir: &Path, platform: Platform) -> Result<Self, VpnError> {
+        let adapter = TapAdapter::with(base_dir, platform, SystemRunner, RegistryDriver)?;
+        if !adapter.tapctl_path.exists() {
+            return Err(VpnError::Adapter(format!(
+                "tapctl.exe not found at {:?}",
+                adapter.tapctl_path
+            )));
+        }
+        Ok(adapter)
+    }
+
+    fn with(
+        base_dir: &Path,
+        platform: Platform,
+        runner: impl CommandRunner + 'static,
+        driver: impl TapDriver + 'static,
+    ) -> Result<Self, VpnError> {
+        Ok(TapAdapter {
+            tapctl_path: base_dir
+                .join(platform.bundle_dir_name()?)
+                .join("tapctl.exe"),
+            base_dir: base_dir.to_path_buf(),
+            platform,
+            runner: Box::new(runner),
+            driver: Box::new(driver),
+            tapctl_timeout: TAPCTL_TIMEOUT,
+            install_timeout: INSTALL_TIMEOUT,
+            poll_interval: POLL_INTERVAL,
+        })
+    }
+
+    fn install_openvpn(&self) -> Result<(), VpnError> {
+        // No need to check admin here as it's checked in ensure_exists
+        let arch = self.platform.arch;
+        let installer_name = match arch {
+            "x86_64" => "OpenVPN-2.6.12-I001-amd64.msi",
+            "aarch64" => "OpenVPN-2.6.12-I001-arm64.msi",
+            _ => {
+                return Err(VpnError::Adapter(format!(
+                    "Unsupported architecture: {}",
+                    arch
+                )))
+            }
+        };
+
+        let installer_path = self.base_dir.join(installer_name);
+        if !installer_path.exists() {
+            return Err(VpnError::Adapter(format!(
+                "OpenVPN installer not found at {:?}",
+                installer_path
+            )));
+        }
+
+        info!(path = ?installer_path, "Running OpenVPN installer");
+
+        // Start the installer process
+        let installer = installer_path.to_string_lossy();
+        let mut child = self
+            .runner
+            .spawn(
+                Path::new("msiexec"),
+                &["/i", &installer, "/quiet", "/qn", "/norestart"],
+            )
+            .map_err(|e| VpnError::Adapter(format!("Failed to start OpenVPN installer: {}", e)))?;
+
+        let start = Instant::now();
+        while start.elapsed() < self.install_timeout {
+            // Check if the TAP driver is installed
+            if self.driver.is_installed()? {
+                info!("TAP driver detected, installation successful");
+                // Try to terminate the installer gracefully
+                let _ = child.kill();
+                return Ok(());
+            }
+            std::thread::sleep(self.poll_interval);
+        }
+
+        // If we get here, kill the process and return success anyway
+        // since the TAP driver might still have been installed
+        warn!("Installation timeout reached, attempting to proceed");
+        let _ = child.kill();
+        let _ = self.runner.output(
+            Path::new("taskkill"),
+            &["/F", "/IM", "msiexec.exe"],
+            self.tapctl_timeout,
+        );
+
+        Ok(())
+    }
+
+    fn tapctl(&self, args: &[&str]) -> Result<String, VpnError> {
+        debug!(tapctl = ?self.tapctl_path, ?args, "Running tapctl");
+        let output = self
+            .runner
+            .output(&self.tapctl_path, args, self.tapctl_timeout)
+            .map_err(|e| VpnError::Adapter(format!("Failed to execute tapctl: {}", e)))?;
+
+        debug!(stdout = %output.stdout, stderr = %output.stderr, "tapctl finished");
+
+        if !output.success {
+            return Err(tapctl_error(
+                args.first().copied().unwrap_or_default(),
+                &output.stderr,
+            ));
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl NetworkAdapter for TapAdapter {
+    fn ensure_exists(&self) -> Result<AdapterInfo, VpnError> {
+        // First check if adapter exists without requiring admin
+        let existing = self.list()?;
+        if let Some(adapter) = existing
+            .iter()
+            .find(|adapter| adapter.name == ADAPTER_NAME)
+            .or(existing.first())
+        {
+            debug!(adapter = %adapter.name, "TAP adapter already exists");
+            return Ok(adapter.clone());
+        }
+
+        // No adapter found, now check if we have admin rights
+        if !self.driver.is_elevated() {
+            return Err(VpnError::AdminRequired);
+        }
+
+        // Try to create adapter
+        info!("No TAP adapter found, attempting to create one");
+        match self.create(ADAPTER_NAME) {
+            Ok(adapter) => Ok(adapter),
+            Err(e) => {
+                warn!(error = %e, "Failed to create adapter, installing OpenVPN");
+                self.install_openvpn()?;
+                // Try creating adapter again after OpenVPN install
+                self.create(ADAPTER_NAME)
+            }
+        }
+    }
+
+    fn list(&self) -> Result<Vec<AdapterInfo>, VpnError> {
+        // This can run without admin privileges
+        Ok(parse_adapter_list(&self.tapctl(&["list"])?))
+    }
+
+    fn create(&self, name: &str) -> Result<AdapterInfo, VpnError> {
+        // No need to check admin here as it's checked in ensure_exists
+        info!(tapctl = ?self.tapctl_path, name, "Creating TAP adapter");
+        self.tapctl(&["create", "--name", name])?;
+
+        // Verify the adapter was created
+        std::thread::sleep(self.poll_interval);
+        let adapter = self
+            .list()?
+            .into_iter()
+            .find(|adapter| adapter.name == name)
+            .ok_or_else(|| {
+                VpnError::Adapter(
+                    "TAP adapter creation seemed to succeed but no adapter is present.".to_string(),
+                )
+            })?;
+
+        info!("TAP adapter created successfully");
+        Ok(adapter)
+    }
+
+    fn remove(&self, adapter: &AdapterInfo) -> Result<(), VpnError> {
+        info!(adapter = %adapter.name, "Removing TAP adapter");
+        self.tapctl(&["delete", &adapter.name])?;
+        Ok(())
+    }
+
+    fn health(&self) -> AdapterHealth {
+        match self.driver.is_installed() {
+            Ok(true) => {}
+            Ok(false) => {
+                return AdapterHealth::Unavailable("The TAP driver is not installed".to_string())
+            }
+            Err(e) => return AdapterHealth::Unavailable(e.to_string()),
+        }
+        match self.list() {
+            Ok(adapters) if adapters.is_empty() => AdapterHealth::Missing,
+            Ok(_) => AdapterHealth::Ready,
+            Err(e) => AdapterHealth::Unavailable(e.to_string()),
+        }
+    }
+
+    /// openvpn 2.6 uses its DCO driver unless told otherwise, and that cannot open a TAP adapter
+    fn openvpn_args(&self, adapter: &AdapterInfo) -> Vec<String> {
+        [
+            "--windows-driver",
+            "tap-windows6",
+            "--dev-node",
+            &adapter.dev_node,
+        ]
+        .map(str::to_string)
+        .to_vec()
+    }
+}
+
+/// Turns what tapctl printed to stderr into an error. Without admin rights Windows
+/// refuses to create or delete adapters with "Access is denied".
+fn tapctl_error(command: &str, stderr: &str) -> VpnError {
+    if stderr.contains("Access is denied") || stderr.contains("0x5)") {
+        return VpnError::AdminRequired;
+    }
+    VpnError::Adapter(format!("tapctl {} failed: {}", command, stderr.trim()))
+}
+
+/// Adapters from `tapctl list`, one `{GUID}<TAB>name` per line
+fn parse_adapter_list(output: &str) -> Vec<AdapterInfo> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let name = line.split_once('\t').map_or(line, |(_, name)| name).trim();
+            AdapterInfo {
+                name: name.to_string(),
+                dev_node: name.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{Scripted, ScriptedRunner};
+    use std::env;
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    const WINDOWS_X64: Platform = Platform {
+        os: "windows",
+        arch: "x86_64",
+    };
+    const LISTED: &str = "{6A6C4E52-0C39-4F5E-9F3B-2D8B3F2B1A11}\tGekkoVPN\r\n";
+    const CREATE_FAILED: &str =
+        "Creating TAP adapter failed: The system cannot find the file specified. (code 0x2)\r\n";
+
+    /// Elevation, and a driver that shows up once it was checked `installed_after` times
+    struct FakeDriver {
+        elevated: bool,
+        installed_after: Option<usize>,
+        checks: AtomicUsize,
+    }
+
+    impl FakeDriver {
+        fn new(elevated: bool, installed_after: Option<usize>) -> Self {
+            FakeDriver {
+                elevated,
+                installed_after,
+                checks: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl TapDriver for FakeDriver {
+        fn is_elevated(&self) -> bool {
+            self.elevated
+        }
+
+        fn is_installed(&self) -> Result<bool, VpnError> {
+            let checks = self.checks.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(self.installed_after.is_some_and(|after| checks >= after))
+        }
+    }
+
+    /// A TapAdapter in a directory that holds the OpenVPN installer, with timing shrunk for tests
+    fn tap(
+        name: &str,
+        runner: ScriptedRunner,
+        driver: FakeDriver,
+    ) -> (TapAdapter, Arc<Mutex<Vec<String>>>) {
+        let dir = env::temp_dir().join(format!("gekkovpn-tap-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("OpenVPN-2.6.12-I001-amd64.msi"), "").unwrap();
+
+        let calls = runner.calls();
+        let mut tap = TapAdapter::with(&dir, WINDOWS_X64, runner, driver).unwrap();
+        tap.install_timeout = Duration::from_millis(50);
+        tap.poll_interval = Duration::from_millis(1);
+        (tap, calls)
+    }
+
+    fn calls(calls: &Arc<Mutex<Vec<String>>>) -> Vec<String> {
+        calls.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn uses_an_existing_adapter() {
+        let runner = ScriptedRunner::default().succeed(
+            "tapctl.exe list",
+            "{0F3E7C1D-1111-4B7A-8E55-3C2A9B8D7E66}\tOpenVPN TAP-Windows6\r\n{6A6C4E52-0C39-4F5E-9F3B-2D8B3F2B1A11}\tGekkoVPN\r\n",
+        );
+        let (tap, log) = tap("existing", runner, FakeDriver::new(false, None));
+
+        assert_eq!(tap.ensure_exists().unwrap().name, "GekkoVPN");
+        assert_eq!(calls(&log), ["tapctl.exe list"]);
+    }
+
+    #[test]
+    fn creates_an_adapter_when_there_is_none() {
+        let runner = ScriptedRunner::default()
+            .succeed("tapctl.exe list", "")
+            .succeed(
+                "tapctl.exe create --name GekkoVPN",
+                "{6A6C4E52-0C39-4F5E-9F3B-2D8B3F2B1A11}\r\n",
+            )
+            .succeed("tapctl.exe list", LISTED);
+        let (tap, log) = tap("create", runner, FakeDriver::new(true, Some(1)));
+
+        let adapter = tap.ensure_exists().unwrap();
+        assert_eq!(adapter.dev_node, "GekkoVPN");
+        assert_eq!(
+            tap.openvpn_args(&adapter),
+            ["--windows-driver", "tap-windows6", "--dev-node", "GekkoVPN"]
+        );
+        assert_eq!(
+            calls(&log),
+            [
+                "tapctl.exe list",
+                "tapctl.exe create --name GekkoVPN",
+                "tapctl.exe list"
+            ]
+        );
+    }
+
+    #[test]
+    fn needs_admin_rights_to_create_an_adapter() {
+        let runner = ScriptedRunner::default().succeed("tapctl.exe list", "");
+        let (tap, log) = tap("admin", runner, FakeDriver::new(false, None));
+
+        assert!(matches!(tap.ensure_exists(), Err(VpnError::AdminRequired)));
+        assert_eq!(calls(&log), ["tapctl.exe list"]);
+    }
+
+    #[test]
+    fn installs_openvpn_when_creating_an_adapter_fails() {
+        let runner = ScriptedRunner::default()
+            .succeed("tapctl.exe list", "")
+            .fail("tapctl.exe create --name GekkoVPN", CREATE_FAILED)
+            .expect("msiexec /i ", Scripted::Started)
+            .succeed("tapctl.exe create --name GekkoVPN", "")
+            .succeed("tapctl.exe list", LISTED);
+        let (tap, log) = tap("install", runner, FakeDriver::new(true, Some(2)));
+
+        assert_eq!(tap.ensure_exists().unwrap().name, "GekkoVPN");
+        let log = calls(&log);
+        assert_eq!(log.len(), 6, "{:?}", log);
+        assert!(log[2].ends_with("OpenVPN-2.6.12-I001-amd64.msi /quiet /qn /norestart"));
+        assert_eq!(log[3], "kill msiexec");
+        assert_eq!(log[4], "tapctl.exe create --name GekkoVPN");
+    }
+
+    #[test]
+    fn gives_up_on_an_installer_that_never_finishes() {
+        let runner = ScriptedRunner::default()
+            .succeed("tapctl.exe list", "")
+            .fail("tapctl.exe create --name GekkoVPN", CREATE_FAILED)
+            .expect("msiexec /i ", Scripted::Started)
+            .succeed("taskkill /F /IM msiexec.exe", "")
+            .fail("tapctl.exe create --name GekkoVPN", CREATE_FAILED);
+        let (tap, log) = tap("stuck", runner, FakeDriver::new(true, None));
+
+        match tap.ensure_exists() {
+            Err(VpnError::Adapter(detail)) => {
+                assert!(
+                    detail.starts_with("tapctl create failed: Creating TAP adapter failed"),
+                    "{}",
+                    detail
+                )
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let log = calls(&log);
+        assert_eq!(
+            log[3..],
+            [
+                "kill msiexec",
+                "taskkill /F /IM msiexec.exe",
+                "tapctl.exe create --name GekkoVPN"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_a_tapctl_that_does_not_answer() {
+        let runner = ScriptedRunner::default()
+            .expect("tapctl.exe list", Scripted::Error(io::ErrorKind::TimedOut));
+        let (tap, _) = tap("hang", runner, FakeDriver::new(true, None));
+
+        assert!(
+            matches!(tap.ensure_exists(), Err(VpnError::Adapter(detail)) if detail.starts_with("Failed to execute tapctl"))
+        );
+        assert!(matches!(tap.health(), AdapterHealth::Unavailable(_)));
+    }
+
+    #[test]
+    fn maps_access_denied_to_admin_required() {
+        let runner = ScriptedRunner::default().fail(
+            "tapctl.exe delete GekkoVPN",
+            "Deleting adapter failed: Access is denied. (code 0x5)\r\n",
+        );
+        let (tap, _) = tap("denied", runner, FakeDriver::new(false, None));
+        let adapter = AdapterInfo {
+            name: "GekkoVPN".to_string(),
+            dev_node: "GekkoVPN".to_string(),
+        };
+
+        assert!(matches!(tap.remove(&adapter), Err(VpnError::AdminRequired)));
+    }
+
+    #[test]
+    fn parses_tapctl_list() {
+        let adapters = parse_adapter_list("{6A6C4E52-0C39-4F5E-9F3B-2D8B3F2B1A11}\tGekkoVPN\r\n\r\n{0F3E7C1D-1111-4B7A-8E55-3C2A9B8D7E66}\tOpenVPN TAP-Windows6\r\n");
+        let names: Vec<&str> = adapters
+            .iter()
+            .map(|adapter| adapter.name.as_str())
+            .collect();
+        assert_eq!(names, ["GekkoVPN", "OpenVPN TAP-Windows6"]);
+        assert_eq!(adapters[0].dev_node, "GekkoVPN");
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_tap_adapter_management() {
+        let base_dir = env::current_dir().unwrap();
+        let tap = TapAdapter::new(&base_dir, Platform::current()).unwrap();
+
+        // Ensure we can create an adapter
+        tap.ensure_exists().unwrap();
+
+        // List should show at least one adapter
+        let adapters = tap.list().unwrap();
+        assert!(!adapters.is_empty());
+
+        // Clean up after test
+        for adapter in adapters {
+            tap.remove(&adapter).unwrap();
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! A client whose openvpn is the scripted fake from `examples/`, for tests
+
+use crate::adapter::{FakeAdapter, NetworkAdapter};
+use crate::catalog;
+use crate::client::{Backend, VpnClient};
+use crate::connection::{Connector, Launcher, Timeouts};
+use crate::credentials::MemoryStore;
+use crate::error::VpnError;
+use crate::events::{Broadcast, EventSink, Events, RecordedEvents};
+use crate::paths::{AppPaths, Environment, PathResolver, Platform, CONFIG_DIR_ENV, OPENVPN_ENV};
+use crate::states::{ConnectionState, STATE_EVENT};
+use crate::usage::UsageLedger;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub(crate) const SERVER: &str = "fake";
+
+pub(crate) const CONNECTS: &str = "
+    stdout OpenVPN 2.6.12 x86_64-pc-linux-gnu [SSL (OpenSSL)] [LZO] [LZ4] [EPOLL]
+    management
+    state RESOLVE,,,,
+    state WAIT,,,,
+    auth
+    state GET_CONFIG,,,,
+    state CONNECTED,SUCCESS,10.8.0.2,185.107.56.21,1194,,
+    bytecount 1024 512
+    serve
+";
+
+/// A temporary directory with nothing but the fake's profile, which the
+/// environment points at along with the fake openvpn
+pub(crate) struct TempInstall {
+    pub(crate) dir: PathBuf,
+    /// Whether the install counts as one only an administrator can change
+    pub(crate) admin_only: bool,
+}
+
+impl Environment for TempInstall {
+    fn resource_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn exe_dir(&self) -> Option<PathBuf> {
+        Some(self.dir.clone())
+    }
+
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        let path = match name {
+            OPENVPN_ENV => fake_openvpn(),
+            CONFIG_DIR_ENV => self.dir.join("openvpn_config"),
+            _ => return None,
+        };
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    fn search_path(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn admin_only(&self, _: &Path) -> bool {
+        self.admin_only
+    }
+}
+
+/// Starts openvpn as is, with an adapter that only exists in memory
+pub(crate) struct FakeLauncher;
+
+impl Launcher for FakeLauncher {
+    fn adapter(&self, _: &AppPaths) -> Result<Box<dyn NetworkAdapter>, VpnError> {
+        Ok(Box::new(FakeAdapter::default()))
+    }
+
+    fn command(&self, openvpn: &Path) -> Result<tokio::process::Command, VpnError> {
+        Ok(tokio::process::Command::new(openvpn))
+    }
+
+    fn platform_args(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The fake openvpn from `examples/`, which cargo builds along with the tests
+pub(crate) fn fake_openvpn() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let fake = exe
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .join("examples")
+        .join(format!("fake_openvpn{}", std::env::consts::EXE_SUFFIX));
+    assert!(
+        fake.exists(),
+        "{} is missing, build it with `cargo build --example fake_openvpn`",
+        fake.display()
+    );
+    fake
+}
+
+pub(crate) struct Harness {
+    pub(crate) client: VpnClient,
+    pub(crate) events: Arc<RecordedEvents>,
+    /// The same events, for who subscribes
+    pub(crate) broadcast: Broadcast,
+    dir: PathBuf,
+}
+
+impl Harness {
+    /// A manager whose openvpn plays `scenario`
+    pub(crate) fn new(name: &str, scenario: &str, timeouts: Timeouts) -> Self {
+        Self::install(name, scenario, timeouts, true)
+    }
+
+    /// Like `new`, but with an openvpn and profiles that any user could have changed
+    pub(crate) fn writable_by_users(name: &str, scenario: &str, timeouts: Timeouts) -> Self {
+        Self::install(name, scenario, timeouts, false)
+    }
+
+    fn install(name: &str, scenario: &str, timeouts: Timeouts, admin_only: bool) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("gekkovpn-harness-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config_dir = dir.join("openvpn_config");
+        let profile = catalog::config_path(&config_dir, SERVER);
+        std::fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        std::fs::write(&profile, scenario).unwrap();
+
+        let paths = PathResolver::new(
+            TempInstall {
+                dir: dir.clone(),
+                admin_only,
+            },
+            Platform::current(),
+        );
+        let events = Arc::new(RecordedEvents::default());
+        let broadcast = Broadcast::default();
+        let client = VpnClient::start(Backend {
+            events: Events::new(Arc::new(Tee(events.clone(), broadcast.clone()))),
+            paths,
+            usage: UsageLedger::open(None),
+            connector: Connector {
+                launcher: Arc::new(FakeLauncher),
+                credentials: Arc::new(MemoryStore::with("alice", "correct horse")),
+                timeouts,
+            },
+        });
+        client.set_shutdown_grace_period(Duration::from_secs(2));
+        Harness {
+            client,
+            events,
+            broadcast,
+            dir,
+        }
+    }
+
+    pub(crate) async fn connect(&self) -> Result<String, VpnError> {
+        self.client
+            .connect(SERVER.to_string(), "alice".to_string())
+            .await
+    }
+
+    /// The `state` of every state event, in order
+    pub(crate) fn states(&self) -> Vec<String> {
+        self.events
+            .named(STATE_EVENT)
+            .iter()
+            .map(|event| event["state"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    pub(crate) async fn wait_for_state(&self, matches: impl Fn(&ConnectionState) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !matches(&self.client.state()) {
+            assert!(Instant::now() < deadline, "Still {:?}", self.client.state());
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Records events and hands them to subscribers
+struct Tee(Arc<RecordedEvents>, Broadcast);
+
+impl EventSink for Tee {
+    fn emit_json(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        self.0.emit_json(event, payload.clone())?;
+        self.1.emit_json(event, payload)
+    }
+}
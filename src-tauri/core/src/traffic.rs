@@ -0,0 +1,222 @@
+use crate::events::Events;
+use crate::usage::{Usage, UsageLedger, USAGE_WARNING_EVENT};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use tracing::warn;
+
+/// Event emitted to the frontend on every `>BYTECOUNT:` while connected
+pub const TRAFFIC_EVENT: &str = "traffic";
+
+/// Traffic of the current session. Rates are in bytes per second.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrafficStats {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub rate_in: f64,
+    pub rate_out: f64,
+    pub peak_rate_in: f64,
+    pub peak_rate_out: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+/// Turns openvpn's cumulative byte counts into session totals and rates.
+/// A session can span several openvpn processes when it reconnects, each of
+/// which counts from zero again.
+#[derive(Debug, Default)]
+pub struct TrafficCounter {
+    stats: TrafficStats,
+    last: Option<Sample>,
+}
+
+impl TrafficCounter {
+    /// Starts a new session
+    pub fn reset(&mut self) {
+        *self = TrafficCounter::default();
+    }
+
+    /// A new openvpn process starts counting at zero
+    pub fn begin_tunnel(&mut self, now: Instant) {
+        self.last = Some(Sample {
+            at: now,
+            bytes_in: 0,
+            bytes_out: 0,
+        });
+    }
+
+    /// The tunnel is down, so nothing is flowing until the next one starts
+    pub fn end_tunnel(&mut self) {
+        self.last = None;
+        self.stats.rate_in = 0.0;
+        self.stats.rate_out = 0.0;
+    }
+
+    pub fn update(&mut self, bytes_in: u64, bytes_out: u64, now: Instant) -> TrafficStats {
+        let last = self.last.unwrap_or(Sample {
+            at: now,
+            bytes_in,
+            bytes_out,
+        });
+        // A soft restart inside openvpn resets its counters as well
+        let delta = |current: u64, previous: u64| {
+            if current >= previous {
+                current - previous
+            } else {
+                current
+            }
+        };
+        let delta_in = delta(bytes_in, last.bytes_in);
+        let delta_out = delta(bytes_out, last.bytes_out);
+
+        self.stats.bytes_in += delta_in;
+        self.stats.bytes_out += delta_out;
+
+        let elapsed = now.duration_since(last.at).as_secs_f64();
+        if elapsed > 0.0 {
+            self.stats.rate_in = delta_in as f64 / elapsed;
+            self.stats.rate_out = delta_out as f64 / elapsed;
+            self.stats.peak_rate_in = self.stats.peak_rate_in.max(self.stats.rate_in);
+            self.stats.peak_rate_out = self.stats.peak_rate_out.max(self.stats.rate_out);
+        }
+
+        self.last = Some(Sample {
+            at: now,
+            bytes_in,
+            bytes_out,
+        });
+        self.stats.clone()
+    }
+
+    pub fn stats(&self) -> TrafficStats {
+        self.stats.clone()
+    }
+}
+
+/// Session traffic shared between the manager and the tunnel watcher.
+/// Everything recorded is also added to the usage ledger.
+pub struct TrafficMonitor {
+    events: Events,
+    counter: Mutex<TrafficCounter>,
+    usage: UsageLedger,
+    /// Server the current tunnel runs to, which the usage is booked on
+    server: Mutex<Option<String>>,
+}
+
+impl TrafficMonitor {
+    pub fn new(events: Events, usage: UsageLedger) -> Self {
+        TrafficMonitor {
+            events,
+            counter: Mutex::new(TrafficCounter::default()),
+            usage,
+            server: Mutex::new(None),
+        }
+    }
+
+    pub fn stats(&self) -> TrafficStats {
+        self.counter.lock().unwrap().stats()
+    }
+
+    pub fn usage(&self) -> &UsageLedger {
+        &self.usage
+    }
+
+    pub fn reset(&self) {
+        self.counter.lock().unwrap().reset();
+    }
+
+    pub fn begin_tunnel(&self, server: &str) {
+        *self.server.lock().unwrap() = Some(server.to_string());
+        self.counter.lock().unwrap().begin_tunnel(Instant::now());
+    }
+
+    pub fn end_tunnel(&self) {
+        *self.server.lock().unwrap() = None;
+        let stats = {
+            let mut counter = self.counter.lock().unwrap();
+            counter.end_tunnel();
+            counter.stats()
+        };
+        let _ = self.events.emit(TRAFFIC_EVENT, stats);
+        if let Err(e) = self.usage.flush() {
+            warn!("{}", e);
+        }
+    }
+
+    /// Records a `>BYTECOUNT:` and broadcasts the new totals and rates
+    pub fn record(&self, bytes_in: u64, bytes_out: u64) {
+        let (before, after) = {
+            let mut counter = self.counter.lock().unwrap();
+            let before = counter.stats();
+            (before, counter.update(bytes_in, bytes_out, Instant::now()))
+        };
+
+        if let Some(server) = self.server.lock().unwrap().as_deref() {
+            let delta = Usage {
+                bytes_in: after.bytes_in - before.bytes_in,
+                bytes_out: after.bytes_out - before.bytes_out,
+            };
+            if let Some(warning) = self.usage.add(server, delta, SystemTime::now()) {
+                warn!(warning = ?warning, "Usage cap warning");
+                let _ = self.events.emit(USAGE_WARNING_EVENT, warning);
+            }
+        }
+        let _ = self.events.emit(TRAFFIC_EVENT, after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn tracks_totals_rates_and_peaks() {
+        let start = Instant::now();
+        let mut counter = TrafficCounter::default();
+        counter.begin_tunnel(start);
+
+        let stats = counter.update(2_000, 1_000, start + Duration::from_secs(1));
+        assert_eq!(stats.bytes_in, 2_000);
+        assert_eq!(stats.rate_in, 2_000.0);
+        assert_eq!(stats.rate_out, 1_000.0);
+
+        let stats = counter.update(3_000, 1_500, start + Duration::from_secs(3));
+        assert_eq!(stats.bytes_in, 3_000);
+        assert_eq!(stats.bytes_out, 1_500);
+        assert_eq!(stats.rate_in, 500.0);
+        assert_eq!(stats.peak_rate_in, 2_000.0);
+        assert_eq!(stats.peak_rate_out, 1_000.0);
+    }
+
+    #[test]
+    fn keeps_session_totals_across_reconnects() {
+        let start = Instant::now();
+        let mut counter = TrafficCounter::default();
+        counter.begin_tunnel(start);
+        counter.update(5_000, 500, start + Duration::from_secs(1));
+
+        counter.end_tunnel();
+        assert_eq!(counter.stats().rate_in, 0.0);
+
+        // The new openvpn process counts from zero
+        let restart = start + Duration::from_secs(10);
+        counter.begin_tunnel(restart);
+        let stats = counter.update(1_000, 100, restart + Duration::from_secs(1));
+        assert_eq!(stats.bytes_in, 6_000);
+        assert_eq!(stats.bytes_out, 600);
+
+        // A soft restart inside openvpn also resets its counters
+        let stats = counter.update(300, 10, restart + Duration::from_secs(2));
+        assert_eq!(stats.bytes_in, 6_300);
+        assert_eq!(stats.peak_rate_in, 5_000.0);
+
+        counter.reset();
+        assert_eq!(counter.stats(), TrafficStats::default());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::adapter::{AdapterHealth, AdapterInfo, NetworkAdapter};
+use crate::error::VpnError;
+use crate::runner::{CommandRunner, SystemRunner};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// The kernel's clone device every tun interface is opened through
+const TUN_DEVICE: &str = "/dev/net/tun";
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// Interface openvpn creates for the tunnel
+const INTERFACE_NAME: &str = "gekkovpn0";
+
+const IP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tun interfaces on Linux. openvpn creates its interface through the clone device
+/// when it starts, so there is nothing to set up beforehand as long as the tun
+/// module is loaded. `create` and `remove` manage persistent interfaces, which
+/// openvpn attaches to when one with the same name exists.
+pub struct TunAdapter {
+    clone_device: PathBuf,
+    sys_class_net: PathBuf,
+    interface: String,
+    runner: Box<dyn CommandRunner>,
+}
+
+impl Default for TunAdapter {
+    fn default() -> Self {
+        TunAdapter {
+            clone_device: PathBuf::from(TUN_DEVICE),
+            sys_class_net: PathBuf::from(SYS_CLASS_NET),
+            interface: INTERFACE_NAME.to_string(),
+            runner: Box::new(SystemRunner),
+        }
+    }
+}
+
+impl TunAdapter {
+    fn adapter(&self, name: &str) -> AdapterInfo {
+        AdapterInfo {
+            name: name.to_string(),
+            dev_node: self.clone_device.to_string_lossy().to_string(),
+        }
+    }
+
+    fn ensure_tun_module(&self) -> Result<(), VpnError> {
+        if self.clone_device.exists() {
+            Ok(())
+        } else {
+            Err(VpnError::Adapter(format!(
+                "{} does not exist, load the tun module with `modprobe tun`",
+                self.clone_device.display()
+            )))
+        }
+    }
+
+    fn ip_tuntap(&self, action: &str, name: &str) -> Result<(), VpnError> {
+        debug!(action, name, "Running ip tuntap");
+        let output = self
+            .runner
+            .output(
+                Path::new("ip"),
+                &["tuntap", action, "dev", name, "mode", "tun"],
+                IP_TIMEOUT,
+            )
+            .map_err(|e| VpnError::Adapter(format!("Failed to execute ip: {}", e)))?;
+        if !output.success {
+            // Only root may create persistent interfaces
+            if output.stderr.contains("Operation not permitted") {
+                return Err(VpnError::AdminRequired);
+            }
+            return Err(VpnError::Adapter(format!(
+                "ip tuntap {} {} failed: {}",
+                action,
+                name,
+                output.stderr.trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl NetworkAdapter for TunAdapter {
+    fn ensure_exists(&self) -> Result<AdapterInfo, VpnError> {
+        self.ensure_tun_module()?;
+        Ok(self.adapter(&self.interface))
+    }
+
+    /// Interfaces with a `tun_flags` attribute, which only tun and tap devices have
+    fn list(&self) -> Result<Vec<AdapterInfo>, VpnError> {
+        let entries = std::fs::read_dir(&self.sys_class_net).map_err(|e| {
+            VpnError::Adapter(format!(
+                "Failed to read {}: {}",
+                self.sys_class_net.display(),
+                e
+            ))
+        })?;
+        let mut adapters: Vec<AdapterInfo> = entries
+            .flatten()
+            .filter(|entry| entry.path().join("tun_flags").exists())
+            .map(|entry| self.adapter(&entry.file_name().to_string_lossy()))
+            .collect();
+        adapters.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(adapters)
+    }
+
+    fn create(&self, name: &str) -> Result<AdapterInfo, VpnError> {
+        self.ensure_tun_module()?;
+        info!(name, "Creating persistent tun interface");
+        self.ip_tuntap("add", name)?;
+        Ok(self.adapter(name))
+    }
+
+    fn remove(&self, adapter: &AdapterInfo) -> Result<(), VpnError> {
+        info!(adapter = %adapter.name, "Removing tun interface");
+        self.ip_tuntap("del", &adapter.name)
+    }
+
+    fn health(&self) -> AdapterHealth {
+        match self.ensure_tun_module() {
+            Ok(()) => AdapterHealth::Ready,
+            Err(e) => AdapterHealth::Unavailable(e.to_string()),
+        }
+    }
+
+    /// Names the interface openvpn creates, whatever `dev` the profile has
+    fn openvpn_args(&self, adapter: &AdapterInfo) -> Vec<String> {
+        [
+            "--dev",
+            &adapter.name,
+            "--dev-type",
+            "tun",
+            "--dev-node",
+            &adapter.dev_node,
+        ]
+        .map(str::to_string)
+        .to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::ScriptedRunner;
+
+    #[test]
+    fn lists_tun_interfaces_and_names_the_one_openvpn_creates() {
+        let root = std::env::temp_dir().join(format!("gekkovpn-tun-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let net = root.join("net");
+        for (interface, tun) in [
+            ("lo", false),
+            ("tun0", true),
+            ("eth0", false),
+            ("gekkovpn0", true),
+        ] {
+            std::fs::create_dir_all(net.join(interface)).unwrap();
+            if tun {
+                std::fs::write(net.join(interface).join("tun_flags"), "0x1001\n").unwrap();
+            }
+        }
+
+        let adapter = TunAdapter {
+            clone_device: root.join("tun"),
+            sys_class_net: net,
+            interface: INTERFACE_NAME.to_string(),
+            runner: Box::new(ScriptedRunner::default()),
+        };
+        let names: Vec<String> = adapter
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+        assert_eq!(names, ["gekkovpn0", "tun0"]);
+
+        assert!(matches!(adapter.health(), AdapterHealth::Unavailable(_)));
+        assert!(adapter.ensure_exists().is_err());
+
+        std::fs::write(root.join("tun"), "").unwrap();
+        assert_eq!(adapter.health(), AdapterHealth::Ready);
+        let tunnel = adapter.ensure_exists().unwrap();
+        assert_eq!(
+            adapter.openvpn_args(&tunnel),
+            [
+                "--dev".to_string(),
+                "gekkovpn0".to_string(),
+                "--dev-type".to_string(),
+                "tun".to_string(),
+                "--dev-node".to_string(),
+                root.join("tun").to_string_lossy().to_string(),
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn manages_persistent_interfaces_with_ip() {
+        let runner = ScriptedRunner::default()
+            .succeed("ip tuntap add dev gekkovpn1 mode tun", "")
+            .fail(
+                "ip tuntap del dev gekkovpn1 mode tun",
+                "ioctl(TUNSETIFF): Operation not permitted\n",
+            );
+        let calls = runner.calls();
+        let adapter = TunAdapter {
+            clone_device: std::env::temp_dir(),
+            runner: Box::new(runner),
+            ..TunAdapter::default()
+        };
+
+        let created = adapter.create("gekkovpn1").unwrap();
+        assert_eq!(created.name, "gekkovpn1");
+        assert!(matches!(
+            adapter.remove(&created),
+            Err(VpnError::AdminRequired)
+        ));
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+}
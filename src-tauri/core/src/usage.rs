@@ -0,0 +1,438 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Event emitted to the frontend when the monthly usage nears or passes the cap
+pub const USAGE_WARNING_EVENT: &str = "usage-warning";
+
+/// How often the ledger is written to disk while traffic flows
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+const LEDGER_FILE: &str = "usage.json";
+const LEDGER_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.bytes_in + self.bytes_out
+    }
+
+    fn add(&mut self, other: Usage) {
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    Day,
+    Month,
+}
+
+/// Usage of one server over one day (`2024-05-14`) or month (`2024-05`), in UTC
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub period: String,
+    pub server: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageCap {
+    pub monthly_bytes: u64,
+    /// Warn once this share of the cap is used
+    pub warn_at_percent: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "level", rename_all = "snake_case")]
+pub enum UsageWarning {
+    NearCap {
+        month: String,
+        used_bytes: u64,
+        cap_bytes: u64,
+    },
+    CapExceeded {
+        month: String,
+        used_bytes: u64,
+        cap_bytes: u64,
+    },
+}
+
+/// The last warning that was sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Warned {
+    month: String,
+    /// 1 near the cap, 2 past it
+    level: u8,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LedgerFile {
+    version: u32,
+    /// day -> server -> usage
+    days: BTreeMap<String, BTreeMap<String, Usage>>,
+    cap: Option<UsageCap>,
+    /// Kept with the totals, so each warning is sent once even across restarts
+    warned: Option<Warned>,
+}
+
+#[derive(Default)]
+struct LedgerState {
+    file: LedgerFile,
+    dirty: bool,
+}
+
+/// Bytes transferred per server and day, persisted in the app data directory
+pub struct UsageLedger {
+    path: Option<PathBuf>,
+    state: Mutex<LedgerState>,
+}
+
+impl UsageLedger {
+    /// Opens the ledger in `dir`, starting empty if there is none yet.
+    /// Without a directory usage is only kept in memory.
+    pub fn open(dir: Option<&Path>) -> Self {
+        let path = dir.map(|dir| dir.join(LEDGER_FILE));
+        let file = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                    warn!(path = ?path, error = %e, "Ignoring unreadable usage ledger");
+                    LedgerFile::default()
+                }),
+                Err(_) => LedgerFile::default(),
+            },
+            None => LedgerFile::default(),
+        };
+
+        UsageLedger {
+            path,
+            state: Mutex::new(LedgerState {
+                file,
+                ..LedgerState::default()
+            }),
+        }
+    }
+
+    /// Adds traffic for `server` at `now`, returning a warning when this pushes
+    /// the month past the warning threshold or the cap
+    pub fn add(&self, server: &str, usage: Usage, now: SystemTime) -> Option<UsageWarning> {
+        if usage.total() == 0 {
+            return None;
+        }
+        let day = day_key(now);
+        let mut state = self.state.lock().unwrap();
+        state
+            .file
+            .days
+            .entry(day.clone())
+            .or_default()
+            .entry(server.to_string())
+            .or_default()
+            .add(usage);
+        state.dirty = true;
+
+        let cap = state.file.cap?;
+        let month = day[..7].to_string();
+        let used = month_total(&state.file, &month);
+        let level = if used >= cap.monthly_bytes {
+            2
+        } else if used as u128 * 100 >= cap.monthly_bytes as u128 * cap.warn_at_percent as u128 {
+            1
+        } else {
+            return None;
+        };
+        if matches!(&state.file.warned, Some(warned) if warned.month == month && warned.level >= level)
+        {
+            return None;
+        }
+        state.file.warned = Some(Warned {
+            month: month.clone(),
+            level,
+        });
+
+        Some(if level == 2 {
+            UsageWarning::CapExceeded {
+                month,
+                used_bytes: used,
+                cap_bytes: cap.monthly_bytes,
+            }
+        } else {
+            UsageWarning::NearCap {
+                month,
+                used_bytes: used,
+                cap_bytes: cap.monthly_bytes,
+            }
+        })
+    }
+
+    /// Usage per server and period, oldest first, optionally for one server only
+    pub fn records(&self, granularity: Granularity, server: Option<&str>) -> Vec<UsageRecord> {
+        let state = self.state.lock().unwrap();
+        let mut periods: BTreeMap<(String, String), Usage> = BTreeMap::new();
+
+        for (day, servers) in &state.file.days {
+            let period = match granularity {
+                Granularity::Day => day.clone(),
+                Granularity::Month => day[..7].to_string(),
+            };
+            for (name, usage) in servers {
+                if server.is_some_and(|server| server != name) {
+                    continue;
+                }
+                periods
+                    .entry((period.clone(), name.clone()))
+                    .or_default()
+                    .add(*usage);
+            }
+        }
+
+        periods
+            .into_iter()
+            .map(|((period, server), usage)| UsageRecord {
+                period,
+                server,
+                bytes_in: usage.bytes_in,
+                bytes_out: usage.bytes_out,
+            })
+            .collect()
+    }
+
+    /// Daily usage as CSV with a header row
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("date,server,bytes_in,bytes_out\n");
+        for record in self.records(Granularity::Day, None) {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{}",
+                record.period,
+                csv_field(&record.server),
+                record.bytes_in,
+                record.bytes_out
+            );
+        }
+        csv
+    }
+
+    pub fn cap(&self) -> Option<UsageCap> {
+        self.state.lock().unwrap().file.cap
+    }
+
+    pub fn set_cap(&self, cap: Option<UsageCap>) {
+        let mut state = self.state.lock().unwrap();
+        state.file.cap = cap;
+        state.file.warned = None;
+        state.dirty = true;
+    }
+
+    /// Writes the ledger to disk if it changed since the last flush
+    pub fn flush(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let contents = {
+            let mut state = self.state.lock().unwrap();
+            if !state.dirty {
+                return Ok(());
+            }
+            state.file.version = LEDGER_VERSION;
+            state.dirty = false;
+            serde_json::to_string_pretty(&state.file)
+                .map_err(|e| format!("Failed to serialize usage ledger: {}", e))?
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        // Write then rename, so a crash mid-write keeps the previous ledger intact
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, contents)
+            .and_then(|_| std::fs::rename(&temp, path))
+            .map_err(|e| {
+                self.state.lock().unwrap().dirty = true;
+                format!("Failed to write usage ledger {:?}: {}", path, e)
+            })
+    }
+}
+
+fn month_total(file: &LedgerFile, month: &str) -> u64 {
+    file.days
+        .iter()
+        .filter(|(day, _)| day.starts_with(month))
+        .flat_map(|(_, servers)| servers.values())
+        .map(Usage::total)
+        .sum()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// `YYYY-MM-DD` of `time` in UTC
+pub(crate) fn day_key(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn usage(bytes_in: u64, bytes_out: u64) -> Usage {
+        Usage {
+            bytes_in,
+            bytes_out,
+        }
+    }
+
+    #[test]
+    fn formats_utc_days() {
+        assert_eq!(day_key(at(0)), "1970-01-01");
+        assert_eq!(day_key(at(951_782_400)), "2000-02-29");
+        assert_eq!(day_key(at(1_715_677_921)), "2024-05-14");
+        assert_eq!(day_key(at(1_735_689_599)), "2024-12-31");
+    }
+
+    #[test]
+    fn adds_up_per_server_day_and_month() {
+        let ledger = UsageLedger::open(None);
+        ledger.add("nl1", usage(100, 10), at(1_715_677_921));
+        ledger.add("nl1", usage(50, 5), at(1_715_680_000));
+        ledger.add("de1", usage(7, 3), at(1_715_680_000));
+        ledger.add("nl1", usage(1, 1), at(1_715_677_921 + 86_400));
+
+        assert_eq!(
+            ledger.records(Granularity::Day, Some("nl1")),
+            vec![
+                UsageRecord {
+                    period: "2024-05-14".to_string(),
+                    server: "nl1".to_string(),
+                    bytes_in: 150,
+                    bytes_out: 15,
+                },
+                UsageRecord {
+                    period: "2024-05-15".to_string(),
+                    server: "nl1".to_string(),
+                    bytes_in: 1,
+                    bytes_out: 1,
+                },
+            ]
+        );
+
+        let months = ledger.records(Granularity::Month, None);
+        assert_eq!(months.len(), 2);
+        assert_eq!(months[1].server, "nl1");
+        assert_eq!(months[1].bytes_in, 151);
+
+        assert_eq!(
+            ledger.to_csv(),
+            "date,server,bytes_in,bytes_out\n\
+             2024-05-14,de1,7,3\n\
+             2024-05-14,nl1,150,15\n\
+             2024-05-15,nl1,1,1\n"
+        );
+    }
+
+    #[test]
+    fn warns_once_near_and_once_past_the_cap() {
+        let ledger = UsageLedger::open(None);
+        ledger.set_cap(Some(UsageCap {
+            monthly_bytes: 1_000,
+            warn_at_percent: 80,
+        }));
+        let now = at(1_715_677_921);
+
+        assert_eq!(ledger.add("nl1", usage(500, 0), now), None);
+        assert!(matches!(
+            ledger.add("nl1", usage(300, 0), now),
+            Some(UsageWarning::NearCap {
+                used_bytes: 800,
+                ..
+            })
+        ));
+        assert_eq!(ledger.add("nl1", usage(100, 0), now), None);
+        assert!(matches!(
+            ledger.add("nl1", usage(0, 100), now),
+            Some(UsageWarning::CapExceeded {
+                used_bytes: 1_000,
+                ..
+            })
+        ));
+        assert_eq!(ledger.add("nl1", usage(1, 0), now), None);
+    }
+
+    #[test]
+    fn survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("gekkovpn-usage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let ledger = UsageLedger::open(Some(&dir));
+        ledger.add("nl1", usage(42, 24), at(1_715_677_921));
+        ledger.flush().unwrap();
+
+        let reopened = UsageLedger::open(Some(&dir));
+        assert_eq!(reopened.records(Granularity::Day, None).len(), 1);
+        assert_eq!(reopened.records(Granularity::Day, None)[0].bytes_in, 42);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn warns_once_across_restarts() {
+        let dir =
+            std::env::temp_dir().join(format!("gekkovpn-usage-warned-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let now = at(1_715_677_921);
+
+        let ledger = UsageLedger::open(Some(&dir));
+        ledger.set_cap(Some(UsageCap {
+            monthly_bytes: 1_000,
+            warn_at_percent: 80,
+        }));
+        assert!(ledger.add("nl1", usage(900, 0), now).is_some());
+        ledger.flush().unwrap();
+
+        let reopened = UsageLedger::open(Some(&dir));
+        let again = reopened.add("nl1", usage(10, 0), now);
+        let exceeded = reopened.add("nl1", usage(100, 0), now);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(again, None);
+        assert!(matches!(exceeded, Some(UsageWarning::CapExceeded { .. })));
+    }
+}
@@ -0,0 +1,177 @@
+//! `gekkovpnd`, the privileged service that owns the tunnel, so the app and
+//! `gekkovpn` run as normal users. It listens on a Unix socket that everyone can
+//! open, and decides from the peer's credentials what it may do; see `peer.rs`.
+//! Passwords are sent along with each connect and only kept in memory.
+//! There is no daemon on Windows, where the app runs as administrator and owns
+//! the tunnel itself.
+
+#[cfg(unix)]
+mod peer;
+
+#[cfg(unix)]
+use {
+    crate::peer::{Peer, Policy},
+    clap::Parser,
+    gekkovpn_core::credentials::MemoryStore,
+    gekkovpn_core::ipc,
+    gekkovpn_core::logging,
+    gekkovpn_core::paths::{Environment, SystemEnvironment},
+    gekkovpn_core::{Backend, Broadcast, Events, VpnClient, VpnError},
+    std::path::{Path, PathBuf},
+    std::sync::Arc,
+    tokio::net::UnixListener,
+    tokio::signal::unix::{signal, SignalKind},
+    tracing::{info, warn},
+};
+
+use std::process::ExitCode;
+
+/// Where usage and settings are kept unless systemd says otherwise
+#[cfg(unix)]
+const STATE_DIR: &str = "/var/lib/gekkovpn";
+
+#[cfg(unix)]
+#[derive(Parser)]
+#[command(name = "gekkovpnd", version, about = "Privileged GekkoVPN service")]
+struct Args {
+    /// Defaults to $GEKKOVPN_SOCKET, or the system socket
+    #[arg(long)]
+    socket: Option<PathBuf>,
+    /// Members of this group may control the tunnel
+    #[arg(long, default_value = "gekkovpn")]
+    group: String,
+}
+
+/// The system, with the service's own data directory instead of a user's
+#[cfg(unix)]
+struct DaemonEnvironment;
+
+#[cfg(unix)]
+impl Environment for DaemonEnvironment {
+    fn resource_dir(&self) -> Option<PathBuf> {
+        SystemEnvironment.resource_dir()
+    }
+
+    fn exe_dir(&self) -> Option<PathBuf> {
+        SystemEnvironment.exe_dir()
+    }
+
+    /// systemd's `StateDirectory=`, which may list several
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        let state_dir = self
+            .var("STATE_DIRECTORY")
+            .and_then(|dirs| dirs.split(':').next().map(PathBuf::from));
+        Some(state_dir.unwrap_or_else(|| PathBuf::from(STATE_DIR)))
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        SystemEnvironment.var(name)
+    }
+
+    fn search_path(&self) -> Vec<PathBuf> {
+        SystemEnvironment.search_path()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        SystemEnvironment.exists(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        SystemEnvironment.is_dir(path)
+    }
+
+    fn admin_only(&self, path: &Path) -> bool {
+        SystemEnvironment.admin_only(path)
+    }
+}
+
+#[cfg(unix)]
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    logging::init(None);
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() -> ExitCode {
+    eprintln!("gekkovpnd runs on Linux and macOS. On Windows, run GekkoVPN as administrator.");
+    ExitCode::FAILURE
+}
+
+#[cfg(unix)]
+async fn run(args: Args) -> Result<(), VpnError> {
+    let socket = args.socket.unwrap_or_else(ipc::socket_path);
+    let broadcast = Broadcast::default();
+    let mut backend = Backend::new(Events::new(Arc::new(broadcast.clone())), DaemonEnvironment);
+    // Root has no keyring and keeps no passwords, which other users could connect
+    // with; front-ends send the user's password with each connect
+    backend.connector.credentials = Arc::new(MemoryStore::default());
+    let client = VpnClient::start(backend);
+
+    let listener = listen(&socket)?;
+    // SAFETY: geteuid cannot fail and touches no memory
+    let policy = Arc::new(Policy::new(unsafe { libc::geteuid() }, args.group));
+    let mut terminate = signal(SignalKind::terminate())
+        .map_err(|e| VpnError::Process(format!("Failed to handle SIGTERM: {}", e)))?;
+    info!(socket = ?socket, "Listening");
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((stream, _)) = accepted else {
+                    continue;
+                };
+                let access = stream
+                    .peer_cred()
+                    .map_err(|e| VpnError::PermissionDenied(format!("Unknown peer: {}", e)))
+                    .and_then(|cred| policy.access(Peer { uid: cred.uid(), gid: cred.gid() }));
+                if let Err(e) = &access {
+                    warn!(error = %e, "Refused a client");
+                }
+                tokio::spawn(ipc::serve(stream, client.clone(), broadcast.clone(), move |_| access));
+            }
+            _ = terminate.recv() => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    info!("Shutting down");
+    // Nothing to do when the tunnel is already down
+    let _ = client.disconnect().await;
+    let _ = std::fs::remove_file(&socket);
+    Ok(())
+}
+
+/// Binds `socket` so everyone can connect; the peer check decides who may stay
+#[cfg(unix)]
+fn listen(socket: &Path) -> Result<UnixListener, VpnError> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let failed = |what: &str, e: std::io::Error| {
+        VpnError::Process(format!("Failed to {} {:?}: {}", what, socket, e))
+    };
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| failed("create the directory of", e))?;
+    }
+    if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+        return Err(VpnError::Process(format!(
+            "Another gekkovpnd listens on {:?}",
+            socket
+        )));
+    }
+    // Left behind by a daemon that did not shut down cleanly
+    if std::fs::symlink_metadata(socket).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(socket).map_err(|e| failed("remove the stale", e))?;
+    }
+    let listener = UnixListener::bind(socket).map_err(|e| failed("listen on", e))?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o666))
+        .map_err(|e| failed("open up", e))?;
+    Ok(listener)
+}
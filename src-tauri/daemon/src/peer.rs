@@ -0,0 +1,140 @@
+//! Who may use the daemon, decided from the uid and gid the kernel reports for
+//! the other end of the socket. Root and the daemon's own user may do everything;
+//! members of the daemon's group may control the tunnel. Membership is looked up
+//! through NSS, so groups from LDAP, sssd or systemd-homed count as well.
+
+use gekkovpn_core::ipc::Access;
+use gekkovpn_core::VpnError;
+use nix::unistd::{Group, Uid, User};
+
+/// The peer as the kernel reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peer {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+pub struct Policy {
+    /// The user the daemon runs as
+    owner: u32,
+    group: String,
+}
+
+impl Policy {
+    pub fn new(owner: u32, group: String) -> Self {
+        Policy { owner, group }
+    }
+
+    /// Asks the user and group databases on every connection, so users added to
+    /// the group can connect without restarting the daemon
+    pub fn access(&self, peer: Peer) -> Result<Access, VpnError> {
+        let group = Group::from_name(&self.group).ok().flatten();
+        let groups = group
+            .as_ref()
+            .map(|group| groups_of(peer.uid, group))
+            .unwrap_or_default();
+        self.decide(peer, group.map(|group| group.gid.as_raw()), &groups)
+    }
+
+    /// `group` is the gid of the daemon's group, if it exists, and `groups` every
+    /// group the peer's user is in
+    fn decide(&self, peer: Peer, group: Option<u32>, groups: &[u32]) -> Result<Access, VpnError> {
+        if peer.uid == 0 || peer.uid == self.owner {
+            return Ok(Access::Full);
+        }
+        match group {
+            Some(gid) if peer.gid == gid || groups.contains(&gid) => Ok(Access::User),
+            _ => Err(VpnError::PermissionDenied(format!(
+                "uid {} is not in the {} group",
+                peer.uid, self.group
+            ))),
+        }
+    }
+}
+
+/// The primary and supplementary groups of the user with `uid`
+#[cfg(not(target_os = "macos"))]
+fn groups_of(uid: u32, _group: &Group) -> Vec<u32> {
+    let Ok(Some(user)) = User::from_uid(Uid::from_raw(uid)) else {
+        return Vec::new();
+    };
+    let Ok(name) = std::ffi::CString::new(user.name) else {
+        return Vec::new();
+    };
+    nix::unistd::getgrouplist(&name, user.gid)
+        .map(|groups| groups.iter().map(|gid| gid.as_raw()).collect())
+        .unwrap_or_else(|_| vec![user.gid.as_raw()])
+}
+
+/// macOS has no `getgrouplist` in nix, so the members listed for `group` count
+#[cfg(target_os = "macos")]
+fn groups_of(uid: u32, group: &Group) -> Vec<u32> {
+    let Ok(Some(user)) = User::from_uid(Uid::from_raw(uid)) else {
+        return Vec::new();
+    };
+    let mut groups = vec![user.gid.as_raw()];
+    if group.mem.contains(&user.name) {
+        groups.push(group.gid.as_raw());
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEKKOVPN: u32 = 990;
+
+    fn decide(policy: &Policy, uid: u32, gid: u32, groups: &[u32]) -> Result<Access, VpnError> {
+        policy.decide(Peer { uid, gid }, Some(GEKKOVPN), groups)
+    }
+
+    #[test]
+    fn looks_up_groups_through_the_system() {
+        let root = Group::from_gid(0.into()).unwrap().unwrap();
+        assert!(groups_of(0, &root).contains(&0));
+        assert!(groups_of(u32::MAX - 1, &root).is_empty());
+    }
+
+    #[test]
+    fn gives_root_and_the_owner_full_access() {
+        let policy = Policy::new(1002, "gekkovpn".to_string());
+
+        assert_eq!(decide(&policy, 0, 0, &[]).unwrap(), Access::Full);
+        assert_eq!(decide(&policy, 1002, 1002, &[]).unwrap(), Access::Full);
+    }
+
+    #[test]
+    fn lets_members_of_the_group_control_the_tunnel() {
+        let policy = Policy::new(0, "gekkovpn".to_string());
+
+        assert_eq!(
+            decide(&policy, 1000, 1000, &[1000, GEKKOVPN]).unwrap(),
+            Access::User
+        );
+        // A process running with the group as its primary group
+        assert_eq!(decide(&policy, 4242, GEKKOVPN, &[]).unwrap(), Access::User);
+    }
+
+    #[test]
+    fn refuses_everyone_else() {
+        let policy = Policy::new(0, "gekkovpn".to_string());
+        assert!(matches!(
+            decide(&policy, 1002, 1002, &[1002]),
+            Err(VpnError::PermissionDenied(_))
+        ));
+
+        // Without the group, no one but root and the owner gets in
+        assert!(matches!(
+            policy.decide(
+                Peer {
+                    uid: 1000,
+                    gid: 1000
+                },
+                None,
+                &[1000]
+            ),
+            Err(VpnError::PermissionDenied(_))
+        ));
+    }
+}
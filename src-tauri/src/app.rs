@@ -0,0 +1,103 @@
+use gekkovpn_core::paths::{Environment, SystemEnvironment};
+use gekkovpn_core::{EventSink, Events, Service};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::warn;
+
+/// Sends the core's events to the webview
+pub struct Webview(pub AppHandle);
+
+impl EventSink for Webview {
+    fn emit_json(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        self.0.emit(event, payload).map_err(|e| e.to_string())
+    }
+}
+
+/// The real system, with directories from Tauri's path resolver
+pub struct TauriEnvironment {
+    app: AppHandle,
+}
+
+impl TauriEnvironment {
+    pub fn new(app: AppHandle) -> Self {
+        TauriEnvironment { app }
+    }
+}
+
+impl Environment for TauriEnvironment {
+    fn resource_dir(&self) -> Option<PathBuf> {
+        self.app.path().resource_dir().ok()
+    }
+
+    fn exe_dir(&self) -> Option<PathBuf> {
+        SystemEnvironment.exe_dir()
+    }
+
+    fn app_data_dir(&self) -> Option<PathBuf> {
+        self.app.path().app_data_dir().ok()
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        SystemEnvironment.var(name)
+    }
+
+    fn search_path(&self) -> Vec<PathBuf> {
+        SystemEnvironment.search_path()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        SystemEnvironment.exists(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        SystemEnvironment.is_dir(path)
+    }
+
+    fn admin_only(&self, path: &Path) -> bool {
+        SystemEnvironment.admin_only(path)
+    }
+}
+
+/// The tunnel of the daemon when one is running, with its events sent to `events`
+#[cfg(unix)]
+pub async fn daemon(events: Events) -> Option<Service> {
+    use gekkovpn_core::ipc::Connection;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let connected = match Connection::daemon().await {
+        Ok(connection) => connection?,
+        Err(e) => {
+            warn!(error = %e, "Not using the VPN service");
+            return None;
+        }
+    };
+    let mut updates = match connected.subscribe().await {
+        Ok(updates) => updates,
+        Err(e) => {
+            warn!(error = %e, "Not using the VPN service");
+            return None;
+        }
+    };
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match updates.recv().await {
+                Ok(event) => {
+                    let _ = events.emit(&event.name, event.payload);
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => {
+                    warn!("Lost the connection to the VPN service");
+                    return;
+                }
+            }
+        }
+    });
+    Some(Service::remote(connected))
+}
+
+/// There is no daemon on Windows; the app runs elevated instead
+#[cfg(not(unix))]
+pub async fn daemon(_events: Events) -> Option<Service> {
+    None
+}
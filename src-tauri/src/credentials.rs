@@ -1,8 +1,9 @@
-use keyring::Entry;
+use gekkovpn_core::{Service, VpnError};
 use std::sync::Mutex;
 use tauri::State;
+use tracing::warn;
 
-const SERVICE_NAME: &str = "GekkoVPN";
+/// Keyring entry holding the password until the username is known
 const TEMP_KEY: &str = "temp_credentials";
 
 #[derive(Debug, Clone)]
@@ -17,15 +18,11 @@ pub struct CredentialsState {
 #[tauri::command]
 pub async fn save_vpn_password(
     state: State<'_, CredentialsState>,
+    service: State<'_, Service>,
     password: String,
-) -> Result<(), String> {
+) -> Result<(), VpnError> {
     // First save to temporary storage
-    let keyring = Entry::new(SERVICE_NAME, TEMP_KEY)
-        .map_err(|e| format!("Failed to create keyring entry: {}", e))?;
-
-    keyring
-        .set_password(&password)
-        .map_err(|e| format!("Failed to save temporary password: {}", e))?;
+    service.save_password(TEMP_KEY, &password)?;
 
     // Update in-memory state
     let credentials = VpnCredentials { password };
@@ -34,47 +31,44 @@ pub async fn save_vpn_password(
 }
 
 #[tauri::command]
-pub async fn associate_username(username: String) -> Result<(), String> {
-    // Get password from temporary storage
-    let temp_keyring = Entry::new(SERVICE_NAME, TEMP_KEY)
-        .map_err(|e| format!("Failed to access temp storage: {}", e))?;
-
-    let password = temp_keyring
-        .get_password()
-        .map_err(|e| format!("Failed to get temporary password: {}", e))?;
+pub async fn associate_username(
+    state: State<'_, CredentialsState>,
+    service: State<'_, Service>,
+    username: String,
+) -> Result<(), VpnError> {
+    // Prefer the password saved by this session, the temporary storage survives restarts
+    let cached = state.credentials.lock().unwrap().take();
+    let password = match cached {
+        Some(credentials) => credentials.password,
+        None => service.password(TEMP_KEY)?.ok_or_else(|| {
+            VpnError::Keyring("No password was saved to associate with the username".to_string())
+        })?,
+    };
 
     // Save with actual username
-    let user_keyring = Entry::new(SERVICE_NAME, &username)
-        .map_err(|e| format!("Failed to create user keyring: {}", e))?;
-
-    user_keyring
-        .set_password(&password)
-        .map_err(|e| format!("Failed to save user password: {}", e))?;
+    service.save_password(&username, &password)?;
 
     // Clean up temporary storage
-    let _ = temp_keyring.delete_password();
+    let _ = service.forget_password(TEMP_KEY);
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn get_vpn_password(username: String) -> Result<Option<String>, String> {
-    let keyring = Entry::new(SERVICE_NAME, &username)
-        .map_err(|e| format!("Failed to create keyring: {}", e))?;
-
-    match keyring.get_password() {
-        Ok(password) => Ok(Some(password)),
-        Err(_) => Ok(None),
-    }
+pub async fn get_vpn_password(
+    service: State<'_, Service>,
+    username: String,
+) -> Result<Option<String>, VpnError> {
+    Ok(service.password(&username).unwrap_or_default())
 }
 
 #[tauri::command]
-pub async fn clear_credentials(username: String) -> Result<(), String> {
-    let keyring = Entry::new(SERVICE_NAME, &username)
-        .map_err(|e| format!("Failed to access keyring: {}", e))?;
-
-    if let Err(e) = keyring.delete_password() {
-        println!("Failed to delete password: {}", e);
+pub async fn clear_credentials(
+    service: State<'_, Service>,
+    username: String,
+) -> Result<(), VpnError> {
+    if let Err(e) = service.forget_password(&username) {
+        warn!(error = %e, "Failed to delete password");
     }
     Ok(())
 }
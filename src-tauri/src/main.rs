@@ -1,253 +1,244 @@
+mod app;
 mod credentials;
-mod states;
-mod tapadapter;
 
+use crate::app::{TauriEnvironment, Webview};
 use crate::credentials::CredentialsState;
-use crate::states::VpnState;
-use crate::tapadapter::TapAdapter;
-use std::io::{BufRead, BufReader, Write};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::sync::Mutex;
-use std::time::Instant;
-use tauri::State;
-
-pub fn get_app_paths() -> Result<(PathBuf, PathBuf), String> {
-    // Try to get executable path first
-    let exe_dir = std::env::current_exe()
-        .map_err(|e| format!("Failed to get executable path: {}", e))?
-        .parent()
-        .ok_or("Failed to get executable directory")?
-        .to_path_buf();
-
-    // Common installation paths to check
-    let possible_paths = vec![
-        exe_dir.clone(),
-        PathBuf::from("C:\\Program Files\\GekkoVPN"),
-        PathBuf::from("C:\\Program Files (x86)\\GekkoVPN"),
-    ];
-
-    // Find first valid path that contains our binaries
-    for base_path in possible_paths {
-        let openvpn_dir = base_path.join("bin").join("openvpn_amd64");
-        let config_dir = base_path.join("openvpn_config");
-
-        if openvpn_dir.exists() && config_dir.exists() {
-            return Ok((openvpn_dir, config_dir));
-        }
-    }
-
-    // If no valid paths found, fall back to executable directory
-    Ok((
-        exe_dir.join("bin").join("openvpn_amd64"),
-        exe_dir.join("openvpn_config"),
-    ))
-}
+use gekkovpn_core::adapter::{AdapterInfo, AdapterReport};
+use gekkovpn_core::catalog::Server;
+use gekkovpn_core::diagnosis::Diagnosis;
+use gekkovpn_core::logbuffer::{LogFilter, LogRecord};
+use gekkovpn_core::logging::{self, Verbosity};
+use gekkovpn_core::paths::AppPaths;
+use gekkovpn_core::redact::{self, RedactionConfig};
+use gekkovpn_core::states::ConnectionState;
+use gekkovpn_core::supervisor::ReconnectPolicy;
+use gekkovpn_core::traffic::TrafficStats;
+use gekkovpn_core::usage::{Granularity, UsageCap, UsageRecord};
+use gekkovpn_core::{diagnostics, Backend, ConnectionInfo, Events, Service, VpnClient, VpnError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
 
 #[tauri::command]
 async fn connect_vpn(
-    vpn_state: State<'_, VpnState>,
+    service: State<'_, Service>,
     server_name: String,
-    mut username: String,
-) -> Result<String, String> {
-    // Get application paths
-    let (openvpn_dir, config_dir) = get_app_paths()?;
-
-    // Initialize and ensure TAP adapter exists
-    let tap = TapAdapter::new(openvpn_dir.parent().unwrap().to_path_buf());
-    tap.ensure_adapter_exists()?;
-
-    // Get stored password using the username
-    let keyring = keyring::Entry::new("GekkoVPN", &username)
-        .map_err(|e| format!("Failed to access keyring: {}", e))?;
-
-    let password = keyring
-        .get_password()
-        .map_err(|e| format!("Failed to get password: {}", e))?;
-
-    username.push_str("@GekkoVPN");
-    println!("Auth Details:");
-    println!("Username: {}", username);
-    println!("Password retrieved from keyring");
-
-    // Setup OpenVPN paths
-    let openvpn_path = openvpn_dir.join("openvpn.exe");
-    let config_path = config_dir
-        .join(&server_name)
-        .join("gekko-vpn-server_openvpn_remote_access_l3.ovpn");
-
-    println!("OpenVPN binary path: {:?}", openvpn_path);
-    println!("OpenVPN config path: {:?}", config_path);
-
-    // Validate paths and VPN state
-    if !openvpn_path.exists() {
-        return Err(format!("OpenVPN binary not found at {:?}", openvpn_path));
-    }
-    if !config_path.exists() {
-        return Err(format!("Config file not found at {:?}", config_path));
-    }
-    if vpn_state.child_process.lock().unwrap().is_some() {
-        return Err("VPN is already running. Please disconnect first.".to_string());
-    }
+    username: String,
+) -> Result<String, VpnError> {
+    service.connect(server_name, username).await
+}
 
-    // Start OpenVPN process
-    let mut child = Command::new(&openvpn_path)
-        .arg("--config")
-        .arg(&config_path)
-        .arg("--auth-nocache")
-        .arg("--auth-retry")
-        .arg("none")
-        .arg("--connect-retry")
-        .arg("1")
-        .arg("--data-ciphers")
-        .arg("AES-256-GCM:AES-128-GCM:AES-128-CBC")
-        .arg("--cipher")
-        .arg("AES-128-CBC")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to start OpenVPN: {}", e))?;
-
-    let (tx, rx) = std::sync::mpsc::channel();
-    let mut stdin_writer = child.stdin.take().ok_or("Failed to get stdin")?;
-
-    // Handle stdout with line buffering
-    let stdout = child.stdout.take().ok_or("Failed to get stdout")?;
-    let tx_clone = tx.clone();
-    tauri::async_runtime::spawn(async move {
-        let mut reader = BufReader::new(stdout);
-        let mut line = String::new();
-
-        while let Ok(n) = reader.read_line(&mut line) {
-            if n == 0 {
-                break;
-            }
-            print!("[OpenVPN] {}", line);
-
-            if line.contains("Enter Auth Username:") {
-                println!("Username prompt detected");
-                tx_clone.send("need_username").unwrap_or(());
-            } else if line.contains("Enter Auth Password:") {
-                println!("Password prompt detected");
-                tx_clone.send("need_password").unwrap_or(());
-            } else if line.contains("Initialization Sequence Completed") {
-                println!("Connection successful!");
-                tx_clone.send("connected").unwrap_or(());
-            } else if line.contains("AUTH_FAILED") {
-                println!("Authentication failed!");
-                tx_clone.send("auth_failed").unwrap_or(());
-            }
-
-            line.clear();
-        }
-    });
-
-    // Handle stderr
-    let stderr = child.stderr.take().ok_or("Failed to get stderr")?;
-    tauri::async_runtime::spawn(async move {
-        let mut reader = BufReader::new(stderr);
-        let mut line = String::new();
-
-        while let Ok(n) = reader.read_line(&mut line) {
-            if n == 0 {
-                break;
-            }
-            eprint!("[OpenVPN] {}", line);
-            line.clear();
-        }
-    });
-
-    // Initial credentials
-    println!("Sending initial username: {}", username);
-    stdin_writer
-        .write_all(username.as_bytes())
-        .and_then(|_| stdin_writer.write_all(b"\n"))
-        .and_then(|_| stdin_writer.flush())
-        .map_err(|e| format!("Failed to write initial username: {}", e))?;
-
-    std::thread::sleep(std::time::Duration::from_millis(500));
-
-    println!("Sending initial password");
-    stdin_writer
-        .write_all(password.as_bytes())
-        .and_then(|_| stdin_writer.write_all(b"\n"))
-        .and_then(|_| stdin_writer.flush())
-        .map_err(|e| format!("Failed to write initial password: {}", e))?;
-
-    // Handle connection with timeout
-    let timeout = std::time::Duration::from_secs(30);
-    let start_time = Instant::now();
-    let mut auth_attempts = 0;
-    const MAX_AUTH_ATTEMPTS: i32 = 2;
-
-    while start_time.elapsed() < timeout {
-        match rx.recv_timeout(std::time::Duration::from_millis(100)) {
-            Ok("need_username") if auth_attempts < MAX_AUTH_ATTEMPTS => {
-                println!("Received username prompt, retrying...");
-                stdin_writer
-                    .write_all(username.as_bytes())
-                    .and_then(|_| stdin_writer.write_all(b"\n"))
-                    .and_then(|_| stdin_writer.flush())
-                    .map_err(|e| format!("Failed to write username: {}", e))?;
-                auth_attempts += 1;
-            }
-            Ok("need_password") if auth_attempts < MAX_AUTH_ATTEMPTS => {
-                println!("Received password prompt, retrying...");
-                std::thread::sleep(std::time::Duration::from_millis(500));
-                stdin_writer
-                    .write_all(password.as_bytes())
-                    .and_then(|_| stdin_writer.write_all(b"\n"))
-                    .and_then(|_| stdin_writer.flush())
-                    .map_err(|e| format!("Failed to write password: {}", e))?;
-                auth_attempts += 1;
-            }
-            Ok("connected") => {
-                println!("VPN connection established successfully");
-                *vpn_state.child_process.lock().unwrap() = Some(child);
-                *vpn_state.connected_server.lock().unwrap() = Some(server_name.clone());
-                return Ok(format!(
-                    "Connected to {} with user {}",
-                    server_name, username
-                ));
-            }
-            Ok("auth_failed") => {
-                child.kill().unwrap_or(());
-                return Err("Authentication failed. Please check your credentials.".to_string());
-            }
-            _ => continue,
-        }
-    }
+#[tauri::command]
+async fn disconnect_vpn(service: State<'_, Service>) -> Result<String, VpnError> {
+    service.disconnect().await
+}
+
+#[tauri::command]
+async fn switch_server(
+    service: State<'_, Service>,
+    server_name: String,
+    username: String,
+) -> Result<String, VpnError> {
+    service.switch_server(server_name, username).await
+}
 
-    // Timeout reached
-    child.kill().unwrap_or(());
-    Err("Connection timed out waiting for authentication".to_string())
+#[tauri::command]
+async fn reconnect_vpn(service: State<'_, Service>) -> Result<String, VpnError> {
+    service.reconnect().await
 }
 
 #[tauri::command]
-async fn disconnect_vpn(state: State<'_, VpnState>) -> Result<String, String> {
-    if let Some(mut child) = state.child_process.lock().unwrap().take() {
-        child
-            .kill()
-            .map_err(|e| format!("Failed to kill OpenVPN process: {}", e))?;
-        *state.connected_server.lock().unwrap() = None;
-        Ok("Disconnected from VPN".to_string())
+async fn cancel_connect(service: State<'_, Service>) -> Result<String, VpnError> {
+    if service.cancel_connect().await? {
+        Ok("Connection cancelled".to_string())
     } else {
-        Ok("Not connected to VPN".to_string())
+        Ok("No connection in progress".to_string())
     }
 }
 
 #[tauri::command]
-async fn get_vpn_status(state: State<'_, VpnState>) -> Result<bool, String> {
-    Ok(state.child_process.lock().unwrap().is_some())
+async fn list_servers(service: State<'_, Service>) -> Result<Vec<Server>, VpnError> {
+    service.list_servers().await
+}
+
+#[tauri::command]
+async fn get_vpn_status(service: State<'_, Service>) -> Result<bool, VpnError> {
+    Ok(service.status().await?.pid.is_some())
+}
+
+#[tauri::command]
+async fn get_connection_info(service: State<'_, Service>) -> Result<ConnectionInfo, VpnError> {
+    service.status().await
+}
+
+#[tauri::command]
+async fn get_traffic_stats(service: State<'_, Service>) -> Result<TrafficStats, VpnError> {
+    service.traffic_stats().await
+}
+
+#[tauri::command]
+async fn get_usage(
+    service: State<'_, Service>,
+    granularity: Granularity,
+    server: Option<String>,
+) -> Result<Vec<UsageRecord>, VpnError> {
+    service.usage(granularity, server).await
+}
+
+#[tauri::command]
+async fn export_usage_csv(service: State<'_, Service>, path: String) -> Result<(), VpnError> {
+    std::fs::write(&path, service.usage_csv().await?)
+        .map_err(|e| VpnError::Storage(format!("Failed to write {}: {}", path, e)))
+}
+
+#[tauri::command]
+async fn get_usage_cap(service: State<'_, Service>) -> Result<Option<UsageCap>, VpnError> {
+    service.usage_cap().await
+}
+
+#[tauri::command]
+async fn set_usage_cap(service: State<'_, Service>, cap: Option<UsageCap>) -> Result<(), VpnError> {
+    service.set_usage_cap(cap).await
+}
+
+#[tauri::command]
+async fn get_vpn_logs(
+    service: State<'_, Service>,
+    filter: Option<LogFilter>,
+) -> Result<Vec<LogRecord>, VpnError> {
+    service.logs(filter.unwrap_or_default()).await
+}
+
+#[tauri::command]
+async fn export_vpn_logs(service: State<'_, Service>, path: String) -> Result<(), VpnError> {
+    std::fs::write(&path, service.export_logs().await?)
+        .map_err(|e| VpnError::Storage(format!("Failed to write {}: {}", path, e)))
+}
+
+#[tauri::command]
+async fn get_redaction_config() -> Result<RedactionConfig, VpnError> {
+    Ok(redact::config())
+}
+
+#[tauri::command]
+async fn set_redaction_config(config: RedactionConfig) -> Result<(), VpnError> {
+    redact::set_config(config);
+    Ok(())
+}
+
+#[tauri::command]
+async fn export_diagnostics(
+    app: AppHandle,
+    service: State<'_, Service>,
+    path: String,
+    server_name: Option<String>,
+) -> Result<(), VpnError> {
+    let version = app.package_info().version.to_string();
+    let log_dir = app.path().app_log_dir().ok();
+    diagnostics::collect(
+        &service,
+        &version,
+        log_dir.as_deref(),
+        server_name.as_deref(),
+    )
+    .await
+    .write(std::path::Path::new(&path))
+    .map_err(VpnError::Storage)
+}
+
+#[tauri::command]
+async fn get_log_level() -> Result<Verbosity, VpnError> {
+    Ok(logging::verbosity())
+}
+
+#[tauri::command]
+async fn set_log_level(service: State<'_, Service>, level: Verbosity) -> Result<(), VpnError> {
+    logging::set_verbosity(level).map_err(VpnError::InvalidSetting)?;
+    service.set_openvpn_verb(level.openvpn_verb()).await
+}
+
+#[tauri::command]
+async fn get_app_paths(service: State<'_, Service>) -> Result<AppPaths, VpnError> {
+    service.app_paths().await
+}
+
+#[tauri::command]
+async fn get_network_adapters(service: State<'_, Service>) -> Result<AdapterReport, VpnError> {
+    service.adapters().await
+}
+
+#[tauri::command]
+async fn create_network_adapter(
+    service: State<'_, Service>,
+    name: Option<String>,
+) -> Result<AdapterInfo, VpnError> {
+    service.create_adapter(name).await
+}
+
+#[tauri::command]
+async fn remove_network_adapter(service: State<'_, Service>, name: String) -> Result<(), VpnError> {
+    service.remove_adapter(name).await
+}
+
+#[tauri::command]
+async fn get_reconnect_policy(service: State<'_, Service>) -> Result<ReconnectPolicy, VpnError> {
+    service.reconnect_policy().await
+}
+
+#[tauri::command]
+async fn set_reconnect_policy(
+    service: State<'_, Service>,
+    policy: ReconnectPolicy,
+) -> Result<(), VpnError> {
+    service.set_reconnect_policy(policy).await
+}
+
+#[tauri::command]
+async fn get_shutdown_grace_period(service: State<'_, Service>) -> Result<u64, VpnError> {
+    Ok(service.shutdown_grace_period().await?.as_secs())
+}
+
+#[tauri::command]
+async fn set_shutdown_grace_period(
+    service: State<'_, Service>,
+    seconds: u64,
+) -> Result<(), VpnError> {
+    service
+        .set_shutdown_grace_period(Duration::from_secs(seconds))
+        .await
+}
+
+#[tauri::command]
+async fn get_connection_state(service: State<'_, Service>) -> Result<ConnectionState, VpnError> {
+    service.state().await
+}
+
+#[tauri::command]
+async fn get_last_failure(service: State<'_, Service>) -> Result<Option<Diagnosis>, VpnError> {
+    service.last_failure().await
 }
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_updater::Builder::new().build())
-        .manage(VpnState {
-            child_process: Mutex::new(None),
-            connected_server: Mutex::new(None),
+        .setup(|app| {
+            logging::init(app.path().app_log_dir().ok().as_deref());
+            let handle = app.handle().clone();
+            let events = Events::new(Arc::new(Webview(handle.clone())));
+            // The tunnel runs in the daemon when there is one, so the app needs no privileges
+            let service = match tauri::async_runtime::block_on(app::daemon(events.clone())) {
+                Some(service) => service,
+                None => {
+                    // The client spawns its tasks on Tauri's runtime
+                    let _runtime = tauri::async_runtime::handle().inner().enter();
+                    Service::local(VpnClient::start(Backend::new(
+                        events,
+                        TauriEnvironment::new(handle),
+                    )))
+                }
+            };
+            app.manage(service);
+            Ok(())
         })
         .manage(CredentialsState {
             credentials: Mutex::new(None),
@@ -255,7 +246,34 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             connect_vpn,
             disconnect_vpn,
+            switch_server,
+            reconnect_vpn,
+            cancel_connect,
+            list_servers,
             get_vpn_status,
+            get_connection_info,
+            get_traffic_stats,
+            get_usage,
+            export_usage_csv,
+            get_usage_cap,
+            set_usage_cap,
+            get_connection_state,
+            get_last_failure,
+            get_vpn_logs,
+            export_vpn_logs,
+            export_diagnostics,
+            get_redaction_config,
+            set_redaction_config,
+            get_log_level,
+            set_log_level,
+            get_app_paths,
+            get_network_adapters,
+            create_network_adapter,
+            remove_network_adapter,
+            get_reconnect_policy,
+            set_reconnect_policy,
+            get_shutdown_grace_period,
+            set_shutdown_grace_period,
             credentials::save_vpn_password,
             credentials::get_vpn_password,
             credentials::associate_username,